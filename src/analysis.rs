@@ -0,0 +1,507 @@
+// Static analysis passes over function bodies that report likely mistakes without rejecting the program
+
+use crate::node::{DefFuncNode, DefMethodNode, Node};
+
+struct Binding {
+    iden: String,
+    used: bool,
+    // only bindings declared with ':=' are reported, parameters and loop variables are only tracked for shadowing
+    reported: bool,
+}
+
+struct Usage {
+    scopes: Vec<Vec<Binding>>,
+    unused: Vec<String>,
+}
+
+// the names of the variables declared in a function that are never read, in the order they are declared
+pub fn unused_variables(func: &DefFuncNode) -> Vec<String> {
+    let mut usage = Usage { scopes: vec![], unused: vec![] };
+    usage.visit_func(func);
+    usage.unused
+}
+
+impl Usage {
+    fn visit_func(&mut self, func: &DefFuncNode) {
+        self.scopes.push(vec![]);
+        for (iden, _) in &func.args {
+            self.bind(iden, false)
+        }
+        self.visit_body(&func.body);
+        self.pop()
+    }
+
+    fn pop(&mut self) {
+        if let Some(scope) = self.scopes.pop() {
+            let unused = scope.into_iter()
+                .filter(|binding| binding.reported && !binding.used)
+                .map(|binding| binding.iden);
+            self.unused.extend(unused)
+        }
+    }
+
+    fn bind(&mut self, iden: &str, reported: bool) {
+        if iden == "_" {
+            return
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(Binding { iden: iden.to_string(), used: false, reported })
+        }
+    }
+
+    // a read only counts as a use of the latest binding of the name
+    fn read(&mut self, iden: &str) {
+        let binding = self.scopes.iter_mut()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|binding| binding.iden == iden);
+        if let Some(binding) = binding {
+            binding.used = true
+        }
+    }
+
+    fn visit_body(&mut self, body: &[Node]) {
+        for node in body {
+            self.visit(node)
+        }
+    }
+
+    // the names an if, else or loop body declares go out of scope when it ends, like they do when it runs
+    fn visit_scoped(&mut self, body: &[Node]) {
+        self.scopes.push(vec![]);
+        self.visit_body(body);
+        self.pop()
+    }
+
+    fn visit(&mut self, node: &Node) {
+        match node {
+            Node::Variable(iden) => self.read(iden),
+            Node::Binop(node) => {
+                self.visit(&node.lhs);
+                self.visit(&node.rhs)
+            }
+            Node::Unop(node) => self.visit(&node.expr),
+            Node::CallFunc(node) => {
+                self.read(&node.iden);
+                self.visit_body(&node.args)
+            }
+            Node::Call(node) => {
+                self.visit(&node.callee);
+                self.visit_body(&node.args)
+            }
+            Node::Index(node) => {
+                self.visit(&node.base);
+                self.visit(&node.index)
+            }
+            Node::Slice(node) => {
+                self.visit(&node.base);
+                for bound in node.start.iter().chain(&node.end) {
+                    self.visit(bound)
+                }
+            }
+            Node::Member(node) => self.visit(&node.base),
+            Node::If(node) => {
+                self.visit(&node.cond);
+                self.visit_scoped(&node.body)
+            }
+            Node::Else(body) => self.visit_scoped(body),
+            Node::Guard(node) => {
+                self.visit(&node.cond);
+                self.visit(&node.this)
+            }
+            Node::Ternary(node) => {
+                self.visit(&node.cond);
+                self.visit(&node.then);
+                self.visit(&node.otherwise)
+            }
+            Node::While(node) => {
+                self.visit(&node.cond);
+                self.visit_scoped(&node.body)
+            }
+            Node::For(node) => {
+                self.visit(&node.collection);
+                self.scopes.push(vec![]);
+                self.bind(&node.element, false);
+                if let Some(index) = &node.index {
+                    self.bind(index, false)
+                }
+                self.visit_body(&node.body);
+                self.pop()
+            }
+            Node::Declare(iden, rhs) | Node::DeclareConst(iden, rhs) => {
+                self.visit(rhs);
+                self.bind(iden, true)
+            }
+            Node::DeclareMany(idens, rhs) => {
+                self.visit(rhs);
+                for iden in idens {
+                    self.bind(iden, true)
+                }
+            }
+            // assigning to a variable doesn't read it
+            Node::Assign(_, rhs) => self.visit(rhs),
+            Node::Store(node) => {
+                self.visit(&node.value);
+                self.visit(&node.target)
+            }
+            Node::Return(expr) => self.visit(expr),
+            Node::Struct(node) => {
+                for (_, value) in &node.fields {
+                    self.visit(value)
+                }
+            }
+            Node::Array(elems) | Node::Tuple(elems) => self.visit_body(elems),
+            Node::Range(node) => {
+                self.visit(&node.start);
+                self.visit(&node.end)
+            }
+            // a lambda can read the variables of the scope it is defined in
+            Node::Lambda(node) => {
+                self.scopes.push(vec![]);
+                for (iden, _) in &node.args {
+                    self.bind(iden, false)
+                }
+                self.visit(&node.body);
+                self.pop()
+            }
+            Node::Block(node) => {
+                self.visit_body(&node.body);
+                if let Some(value) = &node.value {
+                    self.visit(value)
+                }
+            }
+            // a nested function can't see the enclosing variables, so it is analyzed on its own
+            Node::DefFunc(func) | Node::DefMethod(DefMethodNode { func, .. }) => {
+                let mut usage = Usage { scopes: vec![], unused: vec![] };
+                usage.visit_func(func);
+                self.unused.extend(usage.unused)
+            }
+            Node::Constant(_) | Node::Break | Node::Continue
+                | Node::DefStruct(_) | Node::DefTypeAlias(_) | Node::Import(_) | Node::Module(_, _) => {}
+        }
+    }
+}
+
+// a warning for each body that keeps going after a return, break or continue, which points at the first
+// statement that can't run, statements don't have positions of their own so the first one inside of it is used
+pub fn unreachable_code(func: &DefFuncNode) -> Vec<String> {
+    let mut warnings = vec![];
+    unreachable_body(&func.iden, &func.body, None, &mut warnings);
+    warnings
+}
+
+// the trailing value of a block comes after its statements, so it can be the code that is unreachable
+fn unreachable_body(iden: &str, body: &[Node], value: Option<&Node>, warnings: &mut Vec<String>) {
+    for (i, node) in body.iter().enumerate() {
+        unreachable_node(iden, node, warnings);
+        let exit = match node {
+            Node::Return(_) => "return",
+            Node::Break => "break",
+            Node::Continue => "continue",
+            _ => continue
+        };
+        if let Some(dead) = body.get(i + 1).or(value) {
+            let warning = match dead.first_pos() {
+                Some(pos) => format!("unreachable code after {} in function {} at {}", exit, iden, pos),
+                None => format!("unreachable code after {} in function {}", exit, iden)
+            };
+            warnings.push(warning)
+        }
+        return
+    }
+}
+
+// a lambda body is reported as part of the function it is written in
+fn unreachable_node(iden: &str, node: &Node, warnings: &mut Vec<String>) {
+    match node {
+        Node::If(node) => unreachable_body(iden, &node.body, None, warnings),
+        Node::Else(body) => unreachable_body(iden, body, None, warnings),
+        Node::While(node) => unreachable_body(iden, &node.body, None, warnings),
+        Node::For(node) => unreachable_body(iden, &node.body, None, warnings),
+        Node::Block(node) => unreachable_body(iden, &node.body, node.value.as_deref(), warnings),
+        Node::Lambda(node) => unreachable_node(iden, &node.body, warnings),
+        Node::DefFunc(func) => unreachable_body(&func.iden, &func.body, None, warnings),
+        _ => {}
+    }
+    for expr in subexprs(node) {
+        unreachable_node(iden, expr, warnings)
+    }
+}
+
+// the expressions directly inside of a node, without the statements of a body or the body of a lambda
+fn subexprs(node: &Node) -> Vec<&Node> {
+    match node {
+        Node::Binop(node) => vec![&node.lhs, &node.rhs],
+        Node::Unop(node) => vec![&node.expr],
+        Node::CallFunc(node) => node.args.iter().collect(),
+        Node::Call(node) => [&*node.callee].into_iter().chain(&node.args).collect(),
+        Node::Index(node) => vec![&node.base, &node.index],
+        Node::Slice(node) => [&*node.base].into_iter().chain(node.start.as_deref()).chain(node.end.as_deref()).collect(),
+        Node::Member(node) => vec![&node.base],
+        Node::If(node) => vec![&node.cond],
+        Node::Guard(node) => vec![&node.cond, &node.this],
+        Node::Ternary(node) => vec![&node.cond, &node.then, &node.otherwise],
+        Node::While(node) => vec![&node.cond],
+        Node::For(node) => vec![&node.collection],
+        Node::Declare(_, expr) | Node::DeclareMany(_, expr) | Node::DeclareConst(_, expr) | Node::Assign(_, expr) | Node::Return(expr) => {
+            vec![expr]
+        }
+        Node::Store(node) => vec![&node.target, &node.value],
+        Node::Struct(node) => node.fields.iter().map(|(_, value)| value).collect(),
+        Node::Array(elems) | Node::Tuple(elems) => elems.iter().collect(),
+        Node::Range(node) => vec![&node.start, &node.end],
+        Node::Block(node) => node.value.as_deref().into_iter().collect(),
+        _ => vec![]
+    }
+}
+
+// a warning for each name declared with ':=' again in the scope that already declared it, which usually meant
+// to assign with '=', a declaration in an if, else or loop body only shadows the outer name so it isn't reported
+// a block has no scope of its own when it runs, so a name it declares again is in the scope around it
+pub fn redeclared_variables(func: &DefFuncNode) -> Vec<String> {
+    let mut warnings = vec![];
+    let mut params = func.args.iter().map(|(iden, _)| iden.clone()).collect();
+    redeclared_body(&func.iden, &func.body, &mut params, &mut warnings);
+    warnings
+}
+
+fn redeclared_body(iden: &str, body: &[Node], declared: &mut Vec<String>, warnings: &mut Vec<String>) {
+    for node in body {
+        redeclared_node(iden, node, declared, warnings);
+        let (names, rhs) = match node {
+            Node::Declare(name, rhs) | Node::DeclareConst(name, rhs) => (std::slice::from_ref(name), rhs),
+            Node::DeclareMany(names, rhs) => (names.as_slice(), rhs),
+            _ => continue
+        };
+        for name in names.iter().filter(|name| name.as_str() != "_") {
+            if !declared.contains(name) {
+                declared.push(name.clone());
+                continue
+            }
+            let warning = match rhs.first_pos() {
+                Some(pos) => format!("{} is declared again in the same scope in function {} at {}", name, iden, pos),
+                None => format!("{} is declared again in the same scope in function {}", name, iden)
+            };
+            warnings.push(warning)
+        }
+    }
+}
+
+// a lambda body is a scope of its own that starts with the parameters, like a function body
+fn redeclared_node(iden: &str, node: &Node, declared: &mut Vec<String>, warnings: &mut Vec<String>) {
+    match node {
+        Node::If(node) => redeclared_body(iden, &node.body, &mut vec![], warnings),
+        Node::Else(body) => redeclared_body(iden, body, &mut vec![], warnings),
+        Node::While(node) => redeclared_body(iden, &node.body, &mut vec![], warnings),
+        Node::For(node) => {
+            let mut declared = [&node.element].into_iter().chain(&node.index).cloned().collect();
+            redeclared_body(iden, &node.body, &mut declared, warnings)
+        }
+        Node::Block(node) => redeclared_body(iden, &node.body, declared, warnings),
+        Node::Lambda(node) => {
+            let mut params = node.args.iter().map(|(iden, _)| iden.clone()).collect();
+            redeclared_node(iden, &node.body, &mut params, warnings)
+        }
+        Node::DefFunc(func) => warnings.extend(redeclared_variables(func)),
+        _ => {}
+    }
+    for expr in subexprs(node) {
+        redeclared_node(iden, expr, declared, warnings)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufReader, Cursor};
+    use crate::analysis::{redeclared_variables, unreachable_code, unused_variables};
+    use crate::lexer::Lexer;
+    use crate::node::{DefFuncNode, Node};
+    use crate::parser::Parser;
+
+    fn analyze(program: &str, pass: fn(&DefFuncNode) -> Vec<String>) -> Vec<String> {
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+        let nodes = Parser::new(tokens).parse_program().unwrap();
+        match &nodes[0] {
+            Node::DefFunc(func) => pass(func),
+            node => panic!("expected a function definition, got {:?}", node)
+        }
+    }
+
+    fn unused(program: &str) -> Vec<String> {
+        analyze(program, unused_variables)
+    }
+
+    #[test]
+    fn test_unused_variables() {
+        let program = "
+            fn f(n int) -> int {
+                x := 1;
+                return n;
+            }
+        ";
+        assert_eq!(unused(program), vec!["x".to_string()]);
+
+        let program = "
+            fn f(n int) -> int {
+                x := 1;
+                _ := 2;
+                return n + x;
+            }
+        ";
+        assert_eq!(unused(program), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_unused_shadowed() {
+        // only the second x is read, and assigning to y doesn't read it
+        let program = "
+            fn f() -> int {
+                x := 1;
+                x := 2;
+                y := 0;
+                y = x;
+                return x;
+            }
+        ";
+        assert_eq!(unused(program), vec!["x".to_string(), "y".to_string()]);
+
+        // a loop or if body declares its own x, so the read after it is of the outer x
+        let program = "
+            fn f(n int) -> int {
+                x := 1;
+                while n > 0 {
+                    x := 2;
+                    n -= 1;
+                }
+                for i in 0..n {
+                    y := i;
+                    y := y + 1;
+                }
+                if n > 0 {
+                    z := x;
+                }
+                return x;
+            }
+        ";
+        assert_eq!(unused(program), vec!["x".to_string(), "y".to_string(), "z".to_string()]);
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let program = "
+            fn f(n int) -> int {
+                return n;
+                x := n + 1;
+                return x;
+            }
+        ";
+        assert_eq!(analyze(program, unreachable_code), vec!["unreachable code after return in function f at line: 4, col: 24".to_string()]);
+
+        let program = "
+            fn f(n int) -> int {
+                while n > 0 {
+                    break;
+                    n = n - 1;
+                }
+                for i in 0..n {
+                    if i == 2 {
+                        continue;
+                    }
+                }
+                return n;
+            }
+        ";
+        assert_eq!(analyze(program, unreachable_code), vec!["unreachable code after break in function f at line: 5, col: 27".to_string()]);
+
+        let program = "
+            fn f(n int) {
+                return;
+                print(n);
+            }
+        ";
+        assert_eq!(analyze(program, unreachable_code), vec!["unreachable code after return in function f".to_string()]);
+
+        // the body of a lambda or a block inside of an expression is checked too
+        let program = "
+            fn f() {
+                g := fn() { return 1; x := 2; };
+                print({ break; 1 });
+                return { continue; 2 };
+            }
+        ";
+        let expected = vec![
+            "unreachable code after return in function f".to_string(),
+            "unreachable code after break in function f".to_string(),
+            "unreachable code after continue in function f".to_string(),
+        ];
+        assert_eq!(analyze(program, unreachable_code), expected);
+    }
+
+    #[test]
+    fn test_redeclared_variables() {
+        let program = "
+            fn f(n int) -> int {
+                x := 1;
+                y := 2;
+                x := x + y;
+                n := 0;
+                _ := 1;
+                _ := 2;
+                return x + n;
+            }
+        ";
+        let expected = vec![
+            "x is declared again in the same scope in function f at line: 5, col: 24".to_string(),
+            "n is declared again in the same scope in function f".to_string(),
+        ];
+        assert_eq!(analyze(program, redeclared_variables), expected);
+
+        // each if, else and loop body is a scope of its own, so shadowing an outer name in one isn't reported
+        let program = "
+            fn f(n int) -> int {
+                x := 1;
+                if n > 0 {
+                    x := 2;
+                    n := x;
+                } else {
+                    x := 3;
+                }
+                for i in 0..n {
+                    x := i;
+                    i := x;
+                }
+                x, y := 1, 2;
+                return x + y;
+            }
+        ";
+        let expected = vec![
+            "i is declared again in the same scope in function f".to_string(),
+            "x is declared again in the same scope in function f".to_string(),
+        ];
+        assert_eq!(analyze(program, redeclared_variables), expected);
+
+        // but a block statement runs in the scope around it
+        let program = "
+            fn f() {
+                x := 1;
+                { x := 2; x };
+            }
+        ";
+        assert_eq!(analyze(program, redeclared_variables), vec!["x is declared again in the same scope in function f".to_string()]);
+
+        // a lambda body starts a scope with its parameters, so declaring an outer name in it isn't reported
+        let program = "
+            fn f() {
+                y := 0;
+                h := fn() { y := 1; y := 2; y };
+                k := fn(n) { n := 1; n };
+            }
+        ";
+        let expected = vec![
+            "y is declared again in the same scope in function f".to_string(),
+            "n is declared again in the same scope in function f".to_string(),
+        ];
+        assert_eq!(analyze(program, redeclared_variables), expected);
+    }
+}
@@ -0,0 +1,281 @@
+// Arbitrary precision integers, used by an interpreter with bigint enabled once an int leaves the range of an i64
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+// a sign and the magnitude as base 2^32 digits with the least significant first, without trailing zero digits,
+// so every value has exactly one representation and zero is never negative
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    mag: Vec<u32>,
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> BigInt {
+        let abs = n.unsigned_abs();
+        BigInt::new(n < 0, vec![abs as u32, (abs >> 32) as u32])
+    }
+}
+
+impl BigInt {
+    fn new(negative: bool, mut mag: Vec<u32>) -> BigInt {
+        while mag.last() == Some(&0) {
+            mag.pop();
+        }
+        BigInt { negative: negative && !mag.is_empty(), mag }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+
+    #[cfg(test)]
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.mag.len() > 2 {
+            return None
+        }
+        let abs = self.mag.iter().rev().fold(0u64, |abs, digit| (abs << 32) | *digit as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(abs)
+        } else {
+            i64::try_from(abs).ok()
+        }
+    }
+
+    // the number of bits in the magnitude
+    pub fn bits(&self) -> u32 {
+        match self.mag.last() {
+            Some(top) => (self.mag.len() as u32 - 1) * 32 + (32 - top.leading_zeros()),
+            None => 0
+        }
+    }
+
+    pub fn neg(&self) -> BigInt {
+        BigInt::new(!self.negative, self.mag.clone())
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_mag(&self.mag, &other.mag))
+        }
+        // the sign of the sum is the sign of the operand with the larger magnitude
+        match cmp_mag(&self.mag, &other.mag) {
+            Ordering::Less => BigInt::new(other.negative, sub_mag(&other.mag, &self.mag)),
+            _ => BigInt::new(self.negative, sub_mag(&self.mag, &other.mag))
+        }
+    }
+
+    pub fn sub(&self, other: &BigInt) -> BigInt {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &BigInt) -> BigInt {
+        BigInt::new(self.negative != other.negative, mul_mag(&self.mag, &other.mag))
+    }
+
+    // truncates towards zero and the remainder has the sign of the dividend, like the operators on an i64
+    pub fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        if other.is_zero() {
+            return None
+        }
+        let (quot, rem) = div_rem_mag(&self.mag, &other.mag);
+        Some((BigInt::new(self.negative != other.negative, quot), BigInt::new(self.negative, rem)))
+    }
+
+    pub fn pow(&self, mut exp: u32) -> BigInt {
+        let mut base = self.clone();
+        let mut result = BigInt::from(1);
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result.mul(&base);
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.mul(&base);
+            }
+        }
+        result
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.mag, &other.mag),
+            (true, true) => cmp_mag(&other.mag, &self.mag)
+        }
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0")
+        }
+        // peels off nine decimal digits at a time, which are printed most significant first
+        let mut chunks = vec![];
+        let mut mag = self.mag.clone();
+        while !mag.is_empty() {
+            chunks.push(div_small(&mut mag, 1_000_000_000));
+        }
+        let mut digits = if self.negative { "-".to_string() } else { String::new() };
+        let mut chunks = chunks.iter().rev();
+        if let Some(chunk) = chunks.next() {
+            digits += &chunk.to_string();
+        }
+        for chunk in chunks {
+            digits += &format!("{:09}", chunk);
+        }
+        write!(f, "{}", digits)
+    }
+}
+
+fn cmp_mag(lhs: &[u32], rhs: &[u32]) -> Ordering {
+    lhs.len().cmp(&rhs.len()).then_with(|| lhs.iter().rev().cmp(rhs.iter().rev()))
+}
+
+fn add_mag(lhs: &[u32], rhs: &[u32]) -> Vec<u32> {
+    let mut sum = Vec::with_capacity(lhs.len().max(rhs.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..lhs.len().max(rhs.len()) {
+        let digit = *lhs.get(i).unwrap_or(&0) as u64 + *rhs.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push(digit as u32);
+        carry = digit >> 32;
+    }
+    sum.push(carry as u32);
+    sum
+}
+
+// the lhs must be at least as large as the rhs
+fn sub_mag(lhs: &[u32], rhs: &[u32]) -> Vec<u32> {
+    let mut diff = Vec::with_capacity(lhs.len());
+    let mut borrow = 0i64;
+    for (i, digit) in lhs.iter().enumerate() {
+        let mut digit = *digit as i64 - *rhs.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = if digit < 0 { 1 } else { 0 };
+        if digit < 0 {
+            digit += 1 << 32;
+        }
+        diff.push(digit as u32);
+    }
+    diff
+}
+
+fn mul_mag(lhs: &[u32], rhs: &[u32]) -> Vec<u32> {
+    let mut product = vec![0u32; lhs.len() + rhs.len()];
+    for (i, l) in lhs.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, r) in rhs.iter().enumerate() {
+            let digit = product[i + j] as u64 + *l as u64 * *r as u64 + carry;
+            product[i + j] = digit as u32;
+            carry = digit >> 32;
+        }
+        product[i + rhs.len()] = carry as u32;
+    }
+    product
+}
+
+// divides the magnitude in place and gives the remainder
+fn div_small(mag: &mut Vec<u32>, divisor: u32) -> u32 {
+    let mut rem = 0u64;
+    for digit in mag.iter_mut().rev() {
+        let cur = (rem << 32) | *digit as u64;
+        *digit = (cur / divisor as u64) as u32;
+        rem = cur % divisor as u64;
+    }
+    while mag.last() == Some(&0) {
+        mag.pop();
+    }
+    rem as u32
+}
+
+// long division one bit at a time, which is slow for huge operands but only needs the other operations
+fn div_rem_mag(lhs: &[u32], rhs: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    let mut quot = vec![0u32; lhs.len()];
+    let mut rem: Vec<u32> = vec![];
+    for bit in (0..lhs.len() * 32).rev() {
+        // shifts the next bit of the dividend into the remainder
+        let mut carry = (lhs[bit / 32] >> (bit % 32)) & 1;
+        for digit in rem.iter_mut() {
+            let next = *digit >> 31;
+            *digit = (*digit << 1) | carry;
+            carry = next;
+        }
+        if carry != 0 {
+            rem.push(carry);
+        }
+        if cmp_mag(&rem, rhs) != Ordering::Less {
+            rem = sub_mag(&rem, rhs);
+            while rem.last() == Some(&0) {
+                rem.pop();
+            }
+            quot[bit / 32] |= 1 << (bit % 32);
+        }
+    }
+    (quot, rem)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bigint::BigInt;
+
+    fn big(n: i64) -> BigInt {
+        BigInt::from(n)
+    }
+
+    #[test]
+    fn test_bigint_i64_roundtrip() {
+        for n in [0, 1, -1, 42, u32::MAX as i64, -(u32::MAX as i64) - 1, i64::MAX, i64::MIN] {
+            assert_eq!(big(n).to_i64(), Some(n));
+            assert_eq!(big(n).to_string(), n.to_string());
+        }
+        assert_eq!(big(i64::MAX).add(&big(1)).to_i64(), None);
+        assert_eq!(big(i64::MIN).sub(&big(1)).to_i64(), None);
+        assert!(!big(0).neg().is_negative());
+    }
+
+    #[test]
+    fn test_bigint_arithmetic() {
+        let max = big(i64::MAX);
+        assert_eq!(max.add(&big(1)).to_string(), "9223372036854775808");
+        assert_eq!(big(i64::MIN).neg().to_string(), "9223372036854775808");
+        assert_eq!(max.mul(&max).to_string(), "85070591730234615847396907784232501249");
+        assert_eq!(max.mul(&max).sub(&max.mul(&max)), big(0));
+        assert_eq!(big(-3).add(&big(5)), big(2));
+        assert_eq!(big(3).add(&big(-5)), big(-2));
+        assert_eq!(big(2).pow(100).to_string(), "1267650600228229401496703205376");
+        assert_eq!(big(-2).pow(63), big(i64::MIN));
+
+        // the same signs as the i64 operators
+        for (lhs, rhs) in [(7, 2), (-7, 2), (7, -2), (-7, -2), (i64::MAX, 10), (1, i64::MIN)] {
+            assert_eq!(big(lhs).div_rem(&big(rhs)), Some((big(lhs / rhs), big(lhs % rhs))));
+        }
+        let huge = big(10).pow(30);
+        assert_eq!(huge.div_rem(&big(10).pow(12)), Some((big(10).pow(18), big(0))));
+        assert_eq!(big(1).div_rem(&big(0)), None);
+    }
+
+    #[test]
+    fn test_bigint_ordering() {
+        let huge = big(i64::MAX).mul(&big(4));
+        assert!(huge > big(i64::MAX));
+        assert!(huge.neg() < big(i64::MIN));
+        assert!(big(-1) < big(0));
+        assert!(huge.neg() < huge);
+        assert_eq!(huge.cmp(&huge.clone()), std::cmp::Ordering::Equal);
+    }
+}
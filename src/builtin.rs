@@ -0,0 +1,402 @@
+// Functions provided by the interpreter, looked up by name after variables and named functions
+
+use std::io::Write;
+use std::num::IntErrorKind;
+use crate::interpreter::{ExprResult, RunErr};
+use crate::node::{Const, TypeNode};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Builtin {
+    Ord,
+    Chr,
+    Len,
+    Print,
+    Abs,
+    Min,
+    Max,
+    Substring,
+    Split,
+    ToUpper,
+    ToLower,
+    Hex,
+    Bin,
+    Oct,
+    TypeOf,
+    Str,
+    ParseInt,
+    ParseFloat,
+    Assert,
+    AssertEq,
+}
+
+impl Builtin {
+    pub fn lookup(iden: &str) -> Option<Builtin> {
+        let builtin = match iden {
+            "ord" => Builtin::Ord,
+            "chr" => Builtin::Chr,
+            "len" => Builtin::Len,
+            "print" => Builtin::Print,
+            "abs" => Builtin::Abs,
+            "min" => Builtin::Min,
+            "max" => Builtin::Max,
+            "substring" => Builtin::Substring,
+            "split" => Builtin::Split,
+            "to_upper" => Builtin::ToUpper,
+            "to_lower" => Builtin::ToLower,
+            "hex" => Builtin::Hex,
+            "bin" => Builtin::Bin,
+            "oct" => Builtin::Oct,
+            "typeof" => Builtin::TypeOf,
+            "str" => Builtin::Str,
+            "parse_int" => Builtin::ParseInt,
+            "parse_float" => Builtin::ParseFloat,
+            "assert" => Builtin::Assert,
+            "assert_eq" => Builtin::AssertEq,
+            _ => return None
+        };
+        Some(builtin)
+    }
+
+    // a builtin that accepts arguments of more than one type has no single function type
+    pub fn type_node(&self) -> Option<TypeNode> {
+        let iden = |iden: &str| TypeNode::Iden(iden.to_string());
+        let type_node = match self {
+            Builtin::Ord => TypeNode::Fn(vec![iden("char")], Some(Box::new(iden("int")))),
+            Builtin::Chr => TypeNode::Fn(vec![iden("int")], Some(Box::new(iden("char")))),
+            Builtin::Substring => TypeNode::Fn(vec![iden("string"), iden("int"), iden("int")], Some(Box::new(iden("string")))),
+            Builtin::Split => {
+                TypeNode::Fn(vec![iden("string"), iden("string")], Some(Box::new(TypeNode::Array(Box::new(iden("string"))))))
+            }
+            Builtin::ToUpper | Builtin::ToLower => TypeNode::Fn(vec![iden("string")], Some(Box::new(iden("string")))),
+            Builtin::Hex | Builtin::Bin | Builtin::Oct => TypeNode::Fn(vec![iden("int")], Some(Box::new(iden("string")))),
+            Builtin::ParseInt => TypeNode::Fn(vec![iden("string")], Some(Box::new(iden("int")))),
+            Builtin::ParseFloat => TypeNode::Fn(vec![iden("string")], Some(Box::new(iden("float")))),
+            Builtin::Len | Builtin::Print | Builtin::Abs | Builtin::Min | Builtin::Max | Builtin::TypeOf | Builtin::Str | Builtin::Assert | Builtin::AssertEq => {
+                return None
+            }
+        };
+        Some(type_node)
+    }
+
+    // output is written to the sink of whoever runs the program instead of straight to stdout
+    pub fn call(&self, args: Vec<Const>, out: &mut dyn Write) -> ExprResult {
+        match self {
+            Builtin::Ord => match args.as_slice() {
+                [Const::Char(c)] => Ok(Const::Int(*c as i64)),
+                _ => Err(RunErr::Type("ord must be applied to a char", None))
+            },
+            Builtin::Chr => match args.as_slice() {
+                [Const::Int(n)] => u32::try_from(*n).ok()
+                    .and_then(char::from_u32)
+                    .map(Const::Char)
+                    .ok_or_else(|| RunErr::Range(format!("chr argument {} is not a valid char code", n))),
+                _ => Err(RunErr::Type("chr must be applied to an int", None))
+            },
+            Builtin::Len => match args.as_slice() {
+                [Const::String(str)] => Ok(Const::Int(str.chars().count() as i64)),
+                [Const::Array(elems)] => Ok(Const::Int(elems.len() as i64)),
+                // a range that starts past its end is empty
+                [Const::Range(start, end, inclusive)] => {
+                    let len = (*end as i128 - *start as i128 + *inclusive as i128).max(0);
+                    i64::try_from(len)
+                        .map(Const::Int)
+                        .map_err(|_| RunErr::Range(format!("len of range {} is out of range for i64", args[0])))
+                }
+                _ => Err(RunErr::Type("len must be applied to a string, an array or a range", None))
+            },
+            // prints its arguments separated by spaces, and gives back the empty tuple
+            Builtin::Print => {
+                let line: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                writeln!(out, "{}", line.join(" ")).map_err(|err| RunErr::Io(err.to_string()))?;
+                Ok(Const::Tuple(vec![]))
+            }
+            Builtin::Abs => match args.as_slice() {
+                [Const::Int(n)] => n.checked_abs()
+                    .map(Const::Int)
+                    .ok_or_else(|| RunErr::Range(format!("abs of {} is out of range for i64", n))),
+                [Const::Float(n)] => Ok(Const::Float(n.abs())),
+                _ => Err(RunErr::Type("abs must be applied to an int or a float", None))
+            },
+            Builtin::Min => Self::extremum(args, "min", |arg, acc| arg < acc),
+            Builtin::Max => Self::extremum(args, "max", |arg, acc| arg > acc),
+            // the indices count chars, and the end is excluded
+            Builtin::Substring => match args.as_slice() {
+                [Const::String(str), Const::Int(start), Const::Int(end)] => {
+                    let len = str.chars().count() as i64;
+                    if *start < 0 || start > end || *end > len {
+                        return Err(RunErr::Range(format!("substring {}..{} is out of range for a string of length {}", start, end, len)))
+                    }
+                    let sub = str.chars().skip(*start as usize).take((end - start) as usize).collect();
+                    Ok(Const::String(sub))
+                }
+                _ => Err(RunErr::Type("substring must be applied to a string and 2 ints", None))
+            },
+            // an empty separator splits the string into each of its chars
+            Builtin::Split => match args.as_slice() {
+                [Const::String(str), Const::String(sep)] => {
+                    let parts: Vec<Const> = if sep.is_empty() {
+                        str.chars().map(|c| Const::String(c.to_string())).collect()
+                    } else {
+                        str.split(sep.as_str()).map(|part| Const::String(part.to_string())).collect()
+                    };
+                    Ok(Const::Array(parts))
+                }
+                _ => Err(RunErr::Type("split must be applied to 2 strings", None))
+            },
+            Builtin::ToUpper => match args.as_slice() {
+                [Const::String(str)] => Ok(Const::String(str.to_uppercase())),
+                _ => Err(RunErr::Type("to_upper must be applied to a string", None))
+            },
+            Builtin::ToLower => match args.as_slice() {
+                [Const::String(str)] => Ok(Const::String(str.to_lowercase())),
+                _ => Err(RunErr::Type("to_lower must be applied to a string", None))
+            },
+            // a negative int is written as its magnitude after a minus sign, not in two's complement
+            Builtin::Hex | Builtin::Bin | Builtin::Oct => match args.as_slice() {
+                [Const::Int(n)] => {
+                    let sign = if *n < 0 { "-" } else { "" };
+                    let magnitude = n.unsigned_abs();
+                    let digits = match self {
+                        Builtin::Hex => format!("0x{:x}", magnitude),
+                        Builtin::Bin => format!("0b{:b}", magnitude),
+                        _ => format!("0o{:o}", magnitude)
+                    };
+                    Ok(Const::String(format!("{}{}", sign, digits)))
+                }
+                _ => Err(RunErr::Type("hex, bin and oct must be applied to an int", None))
+            },
+            // the name only tells which kind of value it is, so an array of ints and an array of strings are both arrays
+            Builtin::TypeOf => match args.as_slice() {
+                [arg] => {
+                    let name = match arg {
+                        Const::Int(_) | Const::BigInt(_) => "int",
+                        Const::Float(_) => "float",
+                        Const::Bool(_) => "bool",
+                        Const::Char(_) => "char",
+                        Const::String(_) => "string",
+                        Const::Tuple(_) => "tuple",
+                        Const::Array(_) => "array",
+                        Const::Struct(_, _) => "struct",
+                        Const::Range(_, _, _) => "range",
+                        Const::Closure(_) | Const::Func(_) | Const::Builtin(_) => "fn",
+                        Const::Nil => "nil",
+                    };
+                    Ok(Const::String(name.to_string()))
+                }
+                _ => Err(RunErr::Arity(format!("typeof expects 1 argument, got {}", args.len())))
+            },
+            // the same text print writes for the value, so a string is left as it is instead of being quoted
+            Builtin::Str => match args.as_slice() {
+                [arg] => Ok(Const::String(arg.to_string())),
+                _ => Err(RunErr::Arity(format!("str expects 1 argument, got {}", args.len())))
+            },
+            // whitespace around the number is ignored, but anything else that isn't part of it is an error
+            Builtin::ParseInt => match args.as_slice() {
+                [Const::String(str)] => str.trim().parse::<i64>().map(Const::Int).map_err(|err| match err.kind() {
+                    IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+                        RunErr::Overflow(format!("parse_int of {:?} is out of range for i64", str))
+                    }
+                    _ => RunErr::Range(format!("parse_int argument {:?} is not a valid int", str))
+                }),
+                _ => Err(RunErr::Type("parse_int must be applied to a string", None))
+            },
+            Builtin::ParseFloat => match args.as_slice() {
+                [Const::String(str)] => str.trim().parse::<f64>()
+                    .map(Const::Float)
+                    .map_err(|_| RunErr::Range(format!("parse_float argument {:?} is not a valid float", str))),
+                _ => Err(RunErr::Type("parse_float must be applied to a string", None))
+            },
+            // an assertion that holds gives back the empty tuple like print does
+            Builtin::Assert => match args.as_slice() {
+                [Const::Bool(true)] => Ok(Const::Tuple(vec![])),
+                [Const::Bool(false)] => Err(RunErr::Assertion("assertion failed".to_string())),
+                [_] => Err(RunErr::Type("assert must be applied to a bool", None)),
+                _ => Err(RunErr::Arity(format!("assert expects 1 argument, got {}", args.len())))
+            },
+            // the values are compared the same way as with ==
+            Builtin::AssertEq => match args.as_slice() {
+                [lhs, rhs] if lhs == rhs => Ok(Const::Tuple(vec![])),
+                [lhs, rhs] => Err(RunErr::Assertion(format!("assertion failed: {} != {}", lhs, rhs))),
+                _ => Err(RunErr::Arity(format!("assert_eq expects 2 arguments, got {}", args.len())))
+            },
+        }
+    }
+
+    // picks the argument the comparison prefers over the others, when any argument is a float the ints
+    // are converted so they can be compared with it
+    fn extremum(args: Vec<Const>, name: &str, prefer: fn(&Const, &Const) -> bool) -> ExprResult {
+        if args.len() < 2 {
+            return Err(RunErr::Arity(format!("{} expects at least 2 arguments, got {}", name, args.len())))
+        }
+        let has_float = args.iter().any(|arg| matches!(arg, Const::Float(_)));
+        let mut nums = vec![];
+        for arg in args {
+            let num = match arg {
+                Const::Int(n) if has_float => Const::Float(n as f64),
+                Const::Int(_) | Const::Float(_) => arg,
+                _ => return Err(RunErr::Type("min and max must be applied to ints or floats", None))
+            };
+            nums.push(num)
+        }
+        Ok(nums.into_iter().reduce(|acc, num| if prefer(&num, &acc) { num } else { acc }).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use crate::builtin::Builtin;
+    use crate::interpreter::{Interpreter, RunErr};
+    use crate::node::{Bop, Const, FuncNode};
+    use crate::node::Const::{Bool, Char, Int};
+    use crate::node::Node::{CallFunc, Constant};
+
+    #[test]
+    fn test_char_conversions() {
+        assert_eq!(Builtin::Ord.call(vec![Char('A')], &mut io::sink()), Ok(Int(65)));
+        assert_eq!(Builtin::Chr.call(vec![Int(66)], &mut io::sink()), Ok(Char('B')));
+        assert_eq!(Builtin::Chr.call(vec![Int(-1)], &mut io::sink()), Err(RunErr::Range("chr argument -1 is not a valid char code".to_string())));
+        assert!(Builtin::Ord.call(vec![Int(65)], &mut io::sink()).is_err());
+
+        let call = CallFunc(FuncNode { iden: "ord".to_string(), args: vec![Constant(Char('a'))] });
+        assert_eq!(Interpreter::new().eval_node(&call), Ok(Int(97)));
+
+        // chars are ordered by their code
+        assert_eq!(Interpreter::binary_op(&Bop::Lt, Char('A'), Char('B')), Ok(Bool(true)));
+        assert_eq!(Interpreter::binary_op(&Bop::Geq, Char('a'), Char('B')), Ok(Bool(true)));
+    }
+
+    #[test]
+    fn test_len() {
+        assert_eq!(Builtin::Len.call(vec![Const::String("héllo".to_string())], &mut io::sink()), Ok(Int(5)));
+        assert_eq!(Builtin::Len.call(vec![Const::Array(vec![Int(1), Int(2)])], &mut io::sink()), Ok(Int(2)));
+        assert_eq!(Builtin::Len.call(vec![Const::Range(0, 5, false)], &mut io::sink()), Ok(Int(5)));
+        assert_eq!(Builtin::Len.call(vec![Const::Range(0, 5, true)], &mut io::sink()), Ok(Int(6)));
+        assert_eq!(Builtin::Len.call(vec![Const::Range(5, 0, false)], &mut io::sink()), Ok(Int(0)));
+        assert!(Builtin::Len.call(vec![Int(1)], &mut io::sink()).is_err());
+    }
+
+    #[test]
+    fn test_numeric() {
+        let call = |builtin: Builtin, args: Vec<Const>| builtin.call(args, &mut io::sink());
+
+        assert_eq!(call(Builtin::Abs, vec![Int(-3)]), Ok(Int(3)));
+        assert_eq!(call(Builtin::Abs, vec![Const::Float(-2.5)]), Ok(Const::Float(2.5)));
+        assert!(matches!(call(Builtin::Abs, vec![Int(i64::MIN)]), Err(RunErr::Range(_))));
+        assert!(matches!(call(Builtin::Abs, vec![Bool(true)]), Err(RunErr::Type(_, _))));
+
+        assert_eq!(call(Builtin::Max, vec![Int(1), Int(2), Int(3)]), Ok(Int(3)));
+        assert_eq!(call(Builtin::Min, vec![Int(4), Int(-2), Int(3)]), Ok(Int(-2)));
+        assert_eq!(call(Builtin::Min, vec![Const::Float(1.5), Int(2)]), Ok(Const::Float(1.5)));
+        assert_eq!(call(Builtin::Max, vec![Const::Float(1.5), Int(2)]), Ok(Const::Float(2.0)));
+        assert_eq!(call(Builtin::Max, vec![Int(1)]), Err(RunErr::Arity("max expects at least 2 arguments, got 1".to_string())));
+        assert!(matches!(call(Builtin::Min, vec![Int(1), Char('a')]), Err(RunErr::Type(_, _))));
+    }
+
+    #[test]
+    fn test_strings() {
+        let call = |builtin: Builtin, args: Vec<Const>| builtin.call(args, &mut io::sink());
+        let str = |str: &str| Const::String(str.to_string());
+        let strs = |strs: &[&str]| Const::Array(strs.iter().map(|s| str(s)).collect());
+
+        assert_eq!(call(Builtin::Substring, vec![str("héllo"), Int(1), Int(4)]), Ok(str("éll")));
+        assert_eq!(call(Builtin::Substring, vec![str("abc"), Int(3), Int(3)]), Ok(str("")));
+        let err = Err(RunErr::Range("substring 2..4 is out of range for a string of length 3".to_string()));
+        assert_eq!(call(Builtin::Substring, vec![str("abc"), Int(2), Int(4)]), err);
+        assert!(matches!(call(Builtin::Substring, vec![str("abc"), Int(2), Int(1)]), Err(RunErr::Range(_))));
+        assert!(matches!(call(Builtin::Substring, vec![str("abc"), Int(-1), Int(1)]), Err(RunErr::Range(_))));
+
+        assert_eq!(call(Builtin::Split, vec![str("a,b,,c"), str(",")]), Ok(strs(&["a", "b", "", "c"])));
+        assert_eq!(call(Builtin::Split, vec![str("añb"), str("")]), Ok(strs(&["a", "ñ", "b"])));
+        assert_eq!(call(Builtin::Split, vec![str(""), str(",")]), Ok(strs(&[""])));
+        assert_eq!(call(Builtin::Split, vec![str(""), str("")]), Ok(strs(&[])));
+
+        assert_eq!(call(Builtin::ToUpper, vec![str("straße")]), Ok(str("STRASSE")));
+        assert_eq!(call(Builtin::ToLower, vec![str("ÀB")]), Ok(str("àb")));
+        assert!(matches!(call(Builtin::ToUpper, vec![Char('a')]), Err(RunErr::Type(_, _))));
+    }
+
+    #[test]
+    fn test_int_bases() {
+        let call = |builtin: Builtin, n: i64| builtin.call(vec![Int(n)], &mut io::sink());
+        let str = |str: &str| Ok(Const::String(str.to_string()));
+
+        assert_eq!(call(Builtin::Hex, 255), str("0xff"));
+        assert_eq!(call(Builtin::Bin, 10), str("0b1010"));
+        assert_eq!(call(Builtin::Oct, 8), str("0o10"));
+        assert_eq!(call(Builtin::Hex, 0), str("0x0"));
+        assert_eq!(call(Builtin::Hex, -255), str("-0xff"));
+        assert_eq!(call(Builtin::Hex, i64::MIN), str("-0x8000000000000000"));
+        assert!(Builtin::Bin.call(vec![Const::Float(1.0)], &mut io::sink()).is_err());
+    }
+
+    #[test]
+    fn test_typeof() {
+        let call = |arg: Const| Builtin::TypeOf.call(vec![arg], &mut io::sink());
+        let str = |str: &str| Ok(Const::String(str.to_string()));
+
+        assert_eq!(call(Int(1)), str("int"));
+        assert_eq!(call(Const::Array(vec![Int(1)])), str("array"));
+        assert_eq!(call(Const::Float(1.5)), str("float"));
+        assert_eq!(call(Const::String("a".to_string())), str("string"));
+        assert_eq!(call(Const::Tuple(vec![])), str("tuple"));
+        assert_eq!(call(Const::Struct("Point".to_string(), vec![])), str("struct"));
+        assert_eq!(call(Const::Builtin(Builtin::Len)), str("fn"));
+        assert_eq!(call(Const::Nil), str("nil"));
+        let err = Err(RunErr::Arity("typeof expects 1 argument, got 2".to_string()));
+        assert_eq!(Builtin::TypeOf.call(vec![Int(1), Int(2)], &mut io::sink()), err);
+
+        let call = CallFunc(FuncNode { iden: "typeof".to_string(), args: vec![Constant(Char('a'))] });
+        assert_eq!(Interpreter::new().eval_node(&call), str("char"));
+    }
+
+    #[test]
+    fn test_str() {
+        let call = |arg: Const| Builtin::Str.call(vec![arg], &mut io::sink());
+        let str = |str: &str| Ok(Const::String(str.to_string()));
+
+        assert_eq!(call(Int(42)), str("42"));
+        assert_eq!(call(Bool(true)), str("true"));
+        assert_eq!(call(Const::Float(1.5)), str("1.5"));
+        assert_eq!(call(Const::Float(2.0)), str("2.0"));
+        assert_eq!(call(Const::String("a".to_string())), str("a"));
+        assert_eq!(call(Const::Array(vec![Int(1), Int(2)])), str("[1, 2]"));
+        let err = Err(RunErr::Arity("str expects 1 argument, got 0".to_string()));
+        assert_eq!(Builtin::Str.call(vec![], &mut io::sink()), err);
+
+        let call = CallFunc(FuncNode { iden: "str".to_string(), args: vec![Constant(Char('a'))] });
+        assert_eq!(Interpreter::new().eval_node(&call), str("a"));
+    }
+
+    #[test]
+    fn test_parse_numbers() {
+        let call = |builtin: Builtin, str: &str| builtin.call(vec![Const::String(str.to_string())], &mut io::sink());
+
+        assert_eq!(call(Builtin::ParseInt, "42"), Ok(Int(42)));
+        assert_eq!(call(Builtin::ParseInt, " -7\n"), Ok(Int(-7)));
+        assert_eq!(call(Builtin::ParseInt, "x"), Err(RunErr::Range("parse_int argument \"x\" is not a valid int".to_string())));
+        assert!(matches!(call(Builtin::ParseInt, "1.5"), Err(RunErr::Range(_))));
+        assert!(matches!(call(Builtin::ParseInt, ""), Err(RunErr::Range(_))));
+        assert!(matches!(call(Builtin::ParseInt, "9223372036854775808"), Err(RunErr::Overflow(_))));
+
+        assert_eq!(call(Builtin::ParseFloat, "1.5"), Ok(Const::Float(1.5)));
+        assert_eq!(call(Builtin::ParseFloat, "\t2 "), Ok(Const::Float(2.0)));
+        assert!(matches!(call(Builtin::ParseFloat, "1.5x"), Err(RunErr::Range(_))));
+        assert!(matches!(Builtin::ParseInt.call(vec![Int(1)], &mut io::sink()), Err(RunErr::Type(_, _))));
+    }
+
+    #[test]
+    fn test_assert() {
+        let call = |builtin: Builtin, args: Vec<Const>| builtin.call(args, &mut io::sink());
+
+        let eq = Interpreter::binary_op(&Bop::Eq, Int(1), Int(1)).unwrap();
+        assert_eq!(call(Builtin::Assert, vec![eq]), Ok(Const::Tuple(vec![])));
+        assert_eq!(call(Builtin::Assert, vec![Bool(false)]), Err(RunErr::Assertion("assertion failed".to_string())));
+        assert!(matches!(call(Builtin::Assert, vec![Int(1)]), Err(RunErr::Type(_, _))));
+
+        assert_eq!(call(Builtin::AssertEq, vec![Int(1), Int(1)]), Ok(Const::Tuple(vec![])));
+        assert_eq!(call(Builtin::AssertEq, vec![Int(1), Int(2)]), Err(RunErr::Assertion("assertion failed: 1 != 2".to_string())));
+        let err = Err(RunErr::Arity("assert_eq expects 2 arguments, got 1".to_string()));
+        assert_eq!(call(Builtin::AssertEq, vec![Int(1)]), err);
+    }
+}
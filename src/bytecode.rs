@@ -0,0 +1,621 @@
+// Compiles function definitions to a stack based bytecode, and the vm that runs it with the same
+// operator semantics as the ast walker, which stays the reference for the parts of the language not compiled yet
+
+use std::io;
+use std::io::Write;
+use std::rc::Rc;
+use crate::builtin::Builtin;
+use crate::interpreter::{ExprResult, Interpreter, RunErr};
+use crate::lexer::Position;
+use crate::node::{BinopNode, Bop, Const, DefFuncNode, Node, Uop};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    Push(Const),
+    // reads and writes a slot of the frame, the parameters take the first slots
+    Load(usize),
+    Store(usize),
+    Pop,
+    Binop(Bop, Position),
+    Unop(Uop, Position),
+    // pops the lhs of a logical operator, and jumps past the rhs with the result when the lhs decides it
+    Logical(Bop, usize, Position),
+    ExpectBool(&'static str, Position),
+    Jump(usize),
+    JumpIfFalse(usize, &'static str),
+    Call(usize, usize),
+    CallBuiltin(Builtin, usize),
+    Return,
+    // the end of a function body that didn't return
+    NoReturn,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Func {
+    pub iden: String,
+    pub arity: usize,
+    pub slots: usize,
+    pub code: Vec<Instr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub funcs: Vec<Func>,
+}
+
+impl Chunk {
+    fn lookup(&self, iden: &str) -> Option<usize> {
+        self.funcs.iter().position(|func| func.iden == iden)
+    }
+}
+
+pub fn compile(program: &[Node]) -> Result<Chunk, String> {
+    let defs: Vec<&DefFuncNode> = program.iter()
+        .filter_map(|node| match node {
+            Node::DefFunc(func) => Some(func),
+            _ => None
+        })
+        .collect();
+    let funcs = defs.iter()
+        .map(|func| Compiler::new(&defs, func).compile_func(func))
+        .collect::<Result<_, _>>()?;
+    Ok(Chunk { funcs })
+}
+
+fn logical_err(op: &Bop) -> &'static str {
+    match op {
+        Bop::And => "And operator must be applied to 2 bools",
+        _ => "Or operator must be applied to 2 bools",
+    }
+}
+
+struct Compiler<'a> {
+    defs: &'a [&'a DefFuncNode],
+    // the latest slot of a name shadows the earlier ones, like a declaration in the ast walker
+    locals: Vec<(String, usize)>,
+    slots: usize,
+    code: Vec<Instr>,
+    // the start of each enclosing loop, and the jumps that break out of it
+    loops: Vec<(usize, Vec<usize>)>,
+    // the loops outside of each enclosing block, and the returns that jump to the end of it
+    blocks: Vec<(usize, Vec<usize>)>,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(defs: &'a [&'a DefFuncNode], func: &DefFuncNode) -> Compiler<'a> {
+        let locals: Vec<_> = func.args.iter().enumerate().map(|(slot, (iden, _))| (iden.clone(), slot)).collect();
+        let slots = locals.len();
+        Compiler { defs, locals, slots, code: vec![], loops: vec![], blocks: vec![] }
+    }
+
+    fn compile_func(mut self, func: &DefFuncNode) -> Result<Func, String> {
+        self.compile_body(&func.body)?;
+        if func.ret.is_none() {
+            self.code.push(Instr::Push(Const::Tuple(vec![])));
+            self.code.push(Instr::Return);
+        } else {
+            self.code.push(Instr::NoReturn);
+        }
+        Ok(Func { iden: func.iden.clone(), arity: func.args.len(), slots: self.slots, code: self.code })
+    }
+
+    fn emit(&mut self, instr: Instr) -> usize {
+        self.code.push(instr);
+        self.code.len() - 1
+    }
+
+    // points a jump emitted before its target was known at the next instruction
+    fn patch(&mut self, at: usize) {
+        let target = self.code.len();
+        match &mut self.code[at] {
+            Instr::Jump(to) | Instr::JumpIfFalse(to, _) | Instr::Logical(_, to, _) => *to = target,
+            instr => panic!("cannot patch {:?}, it is not a jump", instr)
+        }
+    }
+
+    fn lookup(&self, iden: &str) -> Option<usize> {
+        self.locals.iter().rev().find(|(name, _)| name == iden).map(|(_, slot)| *slot)
+    }
+
+    fn bind(&mut self, iden: &str) {
+        if iden == "_" {
+            self.emit(Instr::Pop);
+            return
+        }
+        let slot = self.slots;
+        self.slots += 1;
+        self.locals.push((iden.to_string(), slot));
+        self.emit(Instr::Store(slot));
+    }
+
+    fn compile_body(&mut self, body: &[Node]) -> Result<(), String> {
+        let mut nodes = body.iter().peekable();
+        while let Some(node) = nodes.next() {
+            match node {
+                // an else node is compiled with the if node right before it
+                Node::If(if_node) => {
+                    self.compile_expr(&if_node.cond)?;
+                    let skip = self.emit(Instr::JumpIfFalse(0, "If condition must be a bool"));
+                    self.compile_scoped(&if_node.body)?;
+                    match nodes.next_if(|node| matches!(node, Node::Else(_))) {
+                        Some(Node::Else(else_body)) => {
+                            let end = self.emit(Instr::Jump(0));
+                            self.patch(skip);
+                            self.compile_scoped(else_body)?;
+                            self.patch(end)
+                        }
+                        _ => self.patch(skip)
+                    }
+                }
+                node => self.compile_stmt(node)?
+            }
+        }
+        Ok(())
+    }
+
+    // the slots of the body stay allocated, but its names can't be looked up after it
+    fn compile_scoped(&mut self, body: &[Node]) -> Result<(), String> {
+        let len = self.locals.len();
+        let result = self.compile_body(body);
+        self.locals.truncate(len);
+        result
+    }
+
+    fn compile_stmt(&mut self, node: &Node) -> Result<(), String> {
+        match node {
+            Node::Return(expr) => {
+                self.compile_expr(expr)?;
+                self.compile_return()
+            }
+            Node::Guard(guard) => {
+                self.compile_expr(&guard.cond)?;
+                let skip = self.emit(Instr::JumpIfFalse(0, "Guard condition must be a bool"));
+                self.compile_expr(&guard.this)?;
+                self.compile_return();
+                self.patch(skip)
+            }
+            Node::While(node) => {
+                let start = self.code.len();
+                self.compile_expr(&node.cond)?;
+                let exit = self.emit(Instr::JumpIfFalse(0, "While condition must be a bool"));
+                self.loops.push((start, vec![exit]));
+                self.compile_scoped(&node.body)?;
+                self.emit(Instr::Jump(start));
+                if let Some((_, breaks)) = self.loops.pop() {
+                    for at in breaks {
+                        self.patch(at)
+                    }
+                }
+            }
+            Node::Break | Node::Continue => {
+                // the ast walker doesn't let a break or continue leave a block, so a loop outside of it doesn't count
+                let outside = self.blocks.last().map_or(0, |(loops, _)| *loops);
+                let Some((start, _)) = self.loops.get(outside..).and_then(|loops| loops.last()) else {
+                    return Err("break and continue must be used within a loop".to_string())
+                };
+                let start = *start;
+                if let Node::Break = node {
+                    let at = self.emit(Instr::Jump(0));
+                    if let Some((_, breaks)) = self.loops.last_mut() {
+                        breaks.push(at)
+                    }
+                } else {
+                    self.emit(Instr::Jump(start));
+                }
+            }
+            Node::Declare(iden, rhs) | Node::DeclareConst(iden, rhs) => {
+                self.compile_expr(rhs)?;
+                self.bind(iden)
+            }
+            Node::Assign(iden, rhs) => {
+                self.compile_expr(rhs)?;
+                let slot = self.lookup(iden).ok_or_else(|| format!("Undefined variable {}", iden))?;
+                self.emit(Instr::Store(slot));
+            }
+            Node::DefStruct(_) | Node::DefTypeAlias(_) | Node::Import(_) => {}
+            node => {
+                self.compile_expr(node)?;
+                self.emit(Instr::Pop);
+            }
+        }
+        Ok(())
+    }
+
+    // a return inside of a block ends the block with the value instead of the function, like it does in the ast walker,
+    // the statements before it leave the stack as it was, so the value is where the trailing expression would put it
+    fn compile_return(&mut self) {
+        if self.blocks.is_empty() {
+            self.emit(Instr::Return);
+        } else {
+            let at = self.emit(Instr::Jump(0));
+            if let Some((_, returns)) = self.blocks.last_mut() {
+                returns.push(at)
+            }
+        }
+    }
+
+    fn compile_expr(&mut self, node: &Node) -> Result<(), String> {
+        match node {
+            Node::Constant(c) => {
+                self.emit(Instr::Push(c.clone()));
+            }
+            Node::Variable(iden) => {
+                let instr = match self.lookup(iden) {
+                    Some(slot) => Instr::Load(slot),
+                    None => Instr::Push(self.global(iden)?)
+                };
+                self.emit(instr);
+            }
+            Node::Binop(node) => self.compile_binop(node)?,
+            Node::Unop(node) => {
+                self.compile_expr(&node.expr)?;
+                self.emit(Instr::Unop(node.op.clone(), node.pos));
+            }
+            Node::CallFunc(node) => {
+                if self.lookup(&node.iden).is_some() {
+                    return Err(format!("cannot compile a call to the variable {}, only named functions can be called", node.iden))
+                }
+                for arg in &node.args {
+                    self.compile_expr(arg)?
+                }
+                let argc = node.args.len();
+                let instr = match self.defs.iter().position(|def| def.iden == node.iden) {
+                    Some(func) => Instr::Call(func, argc),
+                    None => match Builtin::lookup(&node.iden) {
+                        Some(builtin) => Instr::CallBuiltin(builtin, argc),
+                        None => return Err(format!("Undefined variable {}", node.iden))
+                    }
+                };
+                self.emit(instr);
+            }
+            // the value of a return without one
+            Node::Tuple(elems) if elems.is_empty() => {
+                self.emit(Instr::Push(Const::Tuple(vec![])));
+            }
+            Node::Ternary(node) => {
+                self.compile_expr(&node.cond)?;
+                let otherwise = self.emit(Instr::JumpIfFalse(0, "Ternary condition must be a bool"));
+                self.compile_expr(&node.then)?;
+                let end = self.emit(Instr::Jump(0));
+                self.patch(otherwise);
+                self.compile_expr(&node.otherwise)?;
+                self.patch(end)
+            }
+            // blocks don't have a scope of their own, so the statements are compiled into the enclosing body
+            Node::Block(block) => {
+                self.blocks.push((self.loops.len(), vec![]));
+                let result = self.compile_body(&block.body);
+                let (_, returns) = self.blocks.pop().unwrap_or_default();
+                result?;
+                match &block.value {
+                    Some(value) => self.compile_expr(value)?,
+                    None => return Err("cannot compile a block without a value".to_string())
+                }
+                for at in returns {
+                    self.patch(at)
+                }
+            }
+            node => return Err(format!("cannot compile {}, it is only supported by the interpreter", node.kind()))
+        }
+        Ok(())
+    }
+
+    fn compile_binop(&mut self, node: &BinopNode) -> Result<(), String> {
+        self.compile_expr(&node.lhs)?;
+        match node.op {
+            Bop::And | Bop::Or => {
+                let end = self.emit(Instr::Logical(node.op.clone(), 0, node.pos));
+                self.compile_expr(&node.rhs)?;
+                self.emit(Instr::ExpectBool(logical_err(&node.op), node.pos));
+                self.patch(end)
+            }
+            _ => {
+                self.compile_expr(&node.rhs)?;
+                self.emit(Instr::Binop(node.op.clone(), node.pos));
+            }
+        }
+        Ok(())
+    }
+
+    fn global(&self, iden: &str) -> Result<Const, String> {
+        if let Some(def) = self.defs.iter().find(|def| def.iden == iden) {
+            return Ok(Const::Func(Rc::new((*def).clone())))
+        }
+        match Builtin::lookup(iden) {
+            Some(builtin) => Ok(Const::Builtin(builtin)),
+            None => Err(format!("Undefined variable {}", iden))
+        }
+    }
+}
+
+struct Frame {
+    func: usize,
+    ip: usize,
+    base: usize,
+}
+
+pub struct VM<'a> {
+    chunk: &'a Chunk,
+    stack: Vec<Const>,
+    frames: Vec<Frame>,
+    out: Box<dyn Write>,
+}
+
+impl<'a> VM<'a> {
+    pub fn new(chunk: &'a Chunk) -> VM<'a> {
+        VM { chunk, stack: vec![], frames: vec![], out: Box::new(io::stdout()) }
+    }
+
+    pub fn with_output(mut self, out: Box<dyn Write>) -> VM<'a> {
+        self.out = out;
+        self
+    }
+
+    pub fn call(&mut self, iden: &str, args: Vec<Const>) -> ExprResult {
+        let func = self.chunk.lookup(iden).ok_or_else(|| RunErr::undefined(iden))?;
+        let argc = args.len();
+        self.stack.extend(args);
+        let result = self.enter(func, argc).and_then(|_| self.execute());
+        // an error unwinds every frame at once
+        self.stack.clear();
+        self.frames.clear();
+        result
+    }
+
+    fn enter(&mut self, func: usize, argc: usize) -> Result<(), RunErr> {
+        let def = &self.chunk.funcs[func];
+        if def.arity != argc {
+            let noun = if def.arity == 1 { "argument" } else { "arguments" };
+            return Err(RunErr::Arity(format!("Function `{}` expects {} {}, got {}", def.iden, def.arity, noun, argc)))
+        }
+        let base = self.stack.len() - argc;
+        self.stack.resize(base + def.slots, Const::Tuple(vec![]));
+        self.frames.push(Frame { func, ip: 0, base });
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Const {
+        self.stack.pop().expect("the compiler keeps the stack balanced")
+    }
+
+    fn pop_args(&mut self, argc: usize) -> Vec<Const> {
+        self.stack.split_off(self.stack.len() - argc)
+    }
+
+    fn execute(&mut self) -> ExprResult {
+        let chunk = self.chunk;
+        let depth = self.frames.len();
+        loop {
+            let frame = self.frames.last_mut().expect("a function is running");
+            let instr = &chunk.funcs[frame.func].code[frame.ip];
+            frame.ip += 1;
+            let base = frame.base;
+
+            match instr {
+                Instr::Push(c) => self.stack.push(c.clone()),
+                Instr::Load(slot) => self.stack.push(self.stack[base + slot].clone()),
+                Instr::Store(slot) => {
+                    let value = self.pop();
+                    self.stack[base + slot] = value
+                }
+                Instr::Pop => {
+                    self.pop();
+                }
+                Instr::Binop(op, pos) => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    self.stack.push(Interpreter::binary_op(op, lhs, rhs).map_err(|err| err.at(*pos))?)
+                }
+                Instr::Unop(op, pos) => {
+                    let expr = self.pop();
+                    self.stack.push(Interpreter::unary_op(op, expr).map_err(|err| err.at(*pos))?)
+                }
+                Instr::Logical(op, end, pos) => {
+                    let short = matches!(op, Bop::Or);
+                    match self.pop() {
+                        Const::Bool(lhs) if lhs == short => {
+                            self.stack.push(Const::Bool(short));
+                            self.jump(*end)
+                        }
+                        Const::Bool(_) => {}
+                        _ => return Err(RunErr::Type(logical_err(op), Some(*pos)))
+                    }
+                }
+                Instr::ExpectBool(err, pos) => {
+                    if !matches!(self.stack.last(), Some(Const::Bool(_))) {
+                        return Err(RunErr::Type(err, Some(*pos)))
+                    }
+                }
+                Instr::Jump(to) => self.jump(*to),
+                Instr::JumpIfFalse(to, err) => match self.pop() {
+                    Const::Bool(true) => {}
+                    Const::Bool(false) => self.jump(*to),
+                    _ => return Err(RunErr::Type(err, None))
+                },
+                Instr::Call(func, argc) => self.enter(*func, *argc)?,
+                Instr::CallBuiltin(builtin, argc) => {
+                    let args = self.pop_args(*argc);
+                    self.stack.push(builtin.call(args, self.out.as_mut())?)
+                }
+                Instr::Return => {
+                    let value = self.pop();
+                    self.frames.pop();
+                    self.stack.truncate(base);
+                    if self.frames.len() < depth {
+                        return Ok(value)
+                    }
+                    self.stack.push(value)
+                }
+                Instr::NoReturn => return Err(RunErr::Type("Function ended without returning a value", None)),
+            }
+        }
+    }
+
+    fn jump(&mut self, to: usize) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.ip = to
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufReader, Cursor};
+    use crate::bytecode::{compile, VM};
+    use crate::interpreter::{Interpreter, RunErr};
+    use crate::lexer::Lexer;
+    use crate::node::{Const, FuncNode, Node};
+    use crate::parser::Parser;
+
+    fn parse(program: &str) -> Vec<Node> {
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+        Parser::new(tokens).parse_program().unwrap()
+    }
+
+    #[test]
+    fn test_compiled_fib() {
+        let program = parse("
+            fn fib(n int) -> int {
+                if n <= 1 {
+                    return n;
+                }
+                return fib(n - 1) + fib(n - 2);
+            }
+
+            fn fib_loop(n int) -> int {
+                a := 0;
+                b := 1;
+                i := 0;
+                while true {
+                    if i == n || n < 0 {
+                        break;
+                    }
+                    next := a + b;
+                    a = b;
+                    b = next;
+                    i += 1;
+                }
+                return a;
+            }
+        ");
+
+        let mut interpreter = Interpreter::new();
+        interpreter.load(&program);
+        let expected = interpreter.eval_node(&Node::CallFunc(FuncNode { iden: "fib".to_string(), args: vec![Node::Constant(Const::Int(20))] }));
+        assert_eq!(expected, Ok(Const::Int(6765)));
+
+        let chunk = compile(&program).unwrap();
+        let mut vm = VM::new(&chunk);
+        assert_eq!(vm.call("fib", vec![Const::Int(20)]), expected);
+        assert_eq!(vm.call("fib_loop", vec![Const::Int(20)]), expected);
+    }
+
+    #[test]
+    fn test_compiled_errors() {
+        let program = parse("
+            fn divide(a int, b int) -> int {
+                return a / b;
+            }
+
+            fn check(a int) -> bool {
+                return a > 0 && a;
+            }
+        ");
+        let chunk = compile(&program).unwrap();
+        let mut vm = VM::new(&chunk);
+        assert_eq!(vm.call("divide", vec![Const::Int(1), Const::Int(0)]), Err(RunErr::Range("Cannot divide 1 by zero".to_string())));
+        assert_eq!(vm.call("divide", vec![Const::Int(1)]), Err(RunErr::Arity("Function `divide` expects 2 arguments, got 1".to_string())));
+        assert!(matches!(vm.call("check", vec![Const::Int(1)]), Err(RunErr::Type("And operator must be applied to 2 bools", Some(_)))));
+        assert_eq!(vm.call("check", vec![Const::Int(0)]), Ok(Const::Bool(false)));
+
+        let program = parse("
+            fn main() -> int {
+                f := fn(x int) { x };
+                return f(1);
+            }
+        ");
+        assert_eq!(compile(&program), Err("cannot compile a lambda, it is only supported by the interpreter".to_string()));
+    }
+
+    #[test]
+    fn test_compiled_unit_return() {
+        let program = parse("
+            fn skip(n int) {
+                n + 1;
+            }
+
+            fn forget(n int) -> int {
+                n + 1;
+            }
+
+            fn early(n int) {
+                if n > 0 {
+                    return;
+                }
+                assert(false);
+            }
+        ");
+        let chunk = compile(&program).unwrap();
+        let mut vm = VM::new(&chunk);
+        assert_eq!(vm.call("skip", vec![Const::Int(1)]), Ok(Const::Tuple(vec![])));
+        assert_eq!(vm.call("forget", vec![Const::Int(1)]), Err(RunErr::Type("Function ended without returning a value", None)));
+        assert_eq!(vm.call("early", vec![Const::Int(1)]), Ok(Const::Tuple(vec![])));
+    }
+
+    #[test]
+    fn test_compiled_if_scope() {
+        let program = parse("
+            fn shadow(c bool) -> int {
+                x := 0;
+                if c {
+                    x := 2;
+                } else {
+                    x := 3;
+                }
+                return x;
+            }
+        ");
+        let mut interpreter = Interpreter::new();
+        interpreter.load(&program);
+        let call = |c: bool| Node::CallFunc(FuncNode { iden: "shadow".to_string(), args: vec![Node::Constant(Const::Bool(c))] });
+
+        let chunk = compile(&program).unwrap();
+        let mut vm = VM::new(&chunk);
+        for c in [true, false] {
+            assert_eq!(vm.call("shadow", vec![Const::Bool(c)]), interpreter.eval_node(&call(c)));
+            assert_eq!(vm.call("shadow", vec![Const::Bool(c)]), Ok(Const::Int(0)));
+        }
+        assert_eq!(compile(&parse("fn f() -> int { if true { y := 2; } return y; }")), Err("Undefined variable y".to_string()));
+    }
+
+    #[test]
+    fn test_compiled_block_return() {
+        let program = parse("
+            fn early(c bool) -> int {
+                x := {
+                    if c {
+                        return 1;
+                    }
+                    2
+                };
+                return x + 10;
+            }
+            fn nested(c bool) -> int {
+                return 100 + { y := { if c { return 1; } 2 }; return y * 3; 0 };
+            }
+        ");
+        let mut interpreter = Interpreter::new();
+        interpreter.load(&program);
+        let call = |iden: &str, c: bool| Node::CallFunc(FuncNode { iden: iden.to_string(), args: vec![Node::Constant(Const::Bool(c))] });
+
+        let chunk = compile(&program).unwrap();
+        let mut vm = VM::new(&chunk);
+        for (iden, c, expected) in [("early", true, 11), ("early", false, 12), ("nested", true, 103), ("nested", false, 106)] {
+            assert_eq!(vm.call(iden, vec![Const::Bool(c)]), interpreter.eval_node(&call(iden, c)));
+            assert_eq!(vm.call(iden, vec![Const::Bool(c)]), Ok(Const::Int(expected)));
+        }
+        let err = Err("break and continue must be used within a loop".to_string());
+        assert_eq!(compile(&parse("fn f() -> int { while true { x := { break; 1 }; } return 0; }")), err);
+    }
+}
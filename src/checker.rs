@@ -0,0 +1,1520 @@
+// Static type checker for the programming language, run over a parsed program before it is interpreted
+
+use std::collections::VecDeque;
+use std::io::{BufReader, Cursor};
+use std::mem;
+use crate::builtin::Builtin;
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{Lexer, Position, Token};
+use crate::parser::Parser;
+use crate::node::{BinopNode, Bop, Const, DefFuncNode, DefMethodNode, DefStructNode, DefTypeAliasNode, ImportNode, IndexNode, LambdaNode, MemberNode, Node, RangeNode, SliceNode, StructNode, TypeNode, UnopNode, Uop};
+
+pub type CheckResult = Result<TypeNode, String>;
+
+// each name with its type, and whether it was declared const so it can't be assigned to
+type Scope = Vec<(String, TypeNode, bool)>;
+
+pub struct Checker {
+    aliases: Vec<DefTypeAliasNode>,
+    funcs: Vec<(String, TypeNode)>,
+    structs: Vec<DefStructNode>,
+    methods: Vec<DefMethodNode>,
+    scope: Scope,
+    // the return type of the function or lambda being checked, inferred from the first return when unknown
+    ret: Option<TypeNode>,
+    // the paths a qualified call like math.add() can name, the same as the interpreter registers
+    imports: Vec<String>,
+    // the programs an import can name, given to the interpreter the same way
+    modules: Vec<(String, Vec<Node>)>,
+    // the type parameters of each generic function, which are bound again at each call
+    generics: Vec<(String, Vec<String>)>,
+    // the innermost node with a position that an error was found within, kept for the diagnostic
+    err_pos: Option<Position>,
+}
+
+fn iden(iden: &str) -> TypeNode {
+    TypeNode::Iden(iden.to_string())
+}
+
+// the type of a call to a function that doesn't return a value
+fn unit() -> TypeNode {
+    iden("unit")
+}
+
+// lexes, parses and checks the source without running any of it. every bad token, every definition that
+// fails to parse and every definition that fails to check is reported, and each stage only runs when the one
+// before it found nothing wrong
+pub fn check_str(source: &str) -> Result<(), Vec<Diagnostic>> {
+    let mut diagnostics = vec![];
+    let mut tokens = VecDeque::new();
+    for tok in Lexer::new(BufReader::new(Cursor::new(source))).with_recovery(true) {
+        match tok {
+            Ok(tok) => match tok.kind {
+                Token::Error(err) => diagnostics.push(Diagnostic::new(err, Some(tok.lpos), Some(tok.rpos))),
+                _ => tokens.push_back(tok)
+            },
+            Err(err) => diagnostics.push(Diagnostic::new(err, None, None))
+        }
+    }
+    if !diagnostics.is_empty() {
+        return Err(diagnostics)
+    }
+
+    let (nodes, diagnostics) = Parser::new(tokens).parse_recovering();
+    if !diagnostics.is_empty() {
+        return Err(diagnostics)
+    }
+    Checker::new().check_diagnostics(&nodes)
+}
+
+impl Checker {
+    pub fn new() -> Checker {
+        Checker { aliases: vec![], funcs: vec![], structs: vec![], methods: vec![], scope: vec![], ret: None, imports: vec![], modules: vec![], generics: vec![], err_pos: None }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_module(mut self, iden: &str, program: Vec<Node>) -> Checker {
+        self.modules.push((iden.to_string(), program));
+        self
+    }
+
+    #[cfg(test)]
+    pub fn check_program(&mut self, program: &[Node]) -> Result<(), String> {
+        self.check_diagnostics(program).map_err(|mut diagnostics| diagnostics.swap_remove(0).message)
+    }
+
+    // every alias, struct and definition is checked on its own, so an error in one doesn't hide the errors in
+    // the others, and each is reported at the innermost node with a position that it was found within, or at the
+    // statement or the definition it was found in when no node inside has one
+    pub fn check_diagnostics(&mut self, program: &[Node]) -> Result<(), Vec<Diagnostic>> {
+        let mut diagnostics = vec![];
+        self.register_defs(None, program);
+        for alias in &self.aliases {
+            if let Err(err) = self.check_alias(&alias.type_node, &mut vec![alias.iden.clone()]) {
+                diagnostics.push(Diagnostic::new(err, Some(alias.pos), Some(alias.pos)))
+            }
+        }
+        for def in &self.structs {
+            if let Err(err) = self.check_contained(&TypeNode::Iden(def.iden.clone()), &mut vec![]) {
+                diagnostics.push(Diagnostic::new(err, Some(def.pos), Some(def.pos)))
+            }
+        }
+        self.register_funcs(None, program);
+        self.check_defs(program, &mut diagnostics);
+        if diagnostics.is_empty() { Ok(()) } else { Err(diagnostics) }
+    }
+
+    fn check_defs(&mut self, program: &[Node], diagnostics: &mut Vec<Diagnostic>) {
+        for node in program {
+            let result = match node {
+                Node::DefFunc(func) => self.check_func(func),
+                Node::DefMethod(method) => self.check_method(method),
+                Node::Module(_, body) => {
+                    self.check_defs(body, diagnostics);
+                    Ok(())
+                }
+                _ => Ok(())
+            };
+            if let Err(err) = result {
+                let pos = self.err_pos.take().or(node.first_pos());
+                diagnostics.push(Diagnostic::new(err, pos, pos))
+            }
+        }
+    }
+
+    // the structs of a module and the methods defined on them are qualified by its path like its functions
+    fn register_defs(&mut self, path: Option<&str>, program: &[Node]) {
+        let qualify = |iden: &str| match path {
+            Some(path) => format!("{}.{}", path, iden),
+            None => iden.to_string()
+        };
+        for node in program {
+            match node {
+                Node::DefTypeAlias(alias) => self.aliases.push(alias.clone()),
+                Node::DefStruct(def) => self.structs.push(DefStructNode { iden: qualify(&def.iden), ..def.clone() }),
+                Node::DefMethod(method) => {
+                    self.methods.push(DefMethodNode { recv_type: qualify(&method.recv_type), ..method.clone() })
+                }
+                Node::Module(iden, body) => self.register_defs(Some(&qualify(iden)), body),
+                _ => {}
+            }
+        }
+    }
+
+    // the functions of a module are registered qualified by its path, so a module nested in another is
+    // flattened to outer.inner the same as the interpreter does
+    fn register_funcs(&mut self, path: Option<&str>, program: &[Node]) {
+        let qualify = |iden: &str| match path {
+            Some(path) => format!("{}.{}", path, iden),
+            None => iden.to_string()
+        };
+        for node in program {
+            match node {
+                Node::DefFunc(func) => {
+                    let type_node = self.func_type(func);
+                    if !func.type_params.is_empty() {
+                        self.generics.push((qualify(&func.iden), func.type_params.clone()))
+                    }
+                    self.funcs.push((qualify(&func.iden), type_node))
+                }
+                Node::Module(iden, body) => {
+                    let path = qualify(iden);
+                    self.register_funcs(Some(&path), body);
+                    self.imports.push(path)
+                }
+                Node::Import(import) => self.register_import(import),
+                _ => {}
+            }
+        }
+    }
+
+    // the functions of an imported module are registered qualified by the alias, or by the module name without one
+    fn register_import(&mut self, import: &ImportNode) {
+        let prefix = import.alias.as_ref().unwrap_or(&import.iden);
+        let funcs: Vec<_> = self.modules.iter()
+            .filter(|(iden, _)| iden == &import.iden)
+            .flat_map(|(_, program)| program.iter())
+            .filter_map(|node| match node {
+                Node::DefFunc(func) => Some((format!("{}.{}", prefix, func.iden), self.func_type(func))),
+                _ => None
+            })
+            .collect();
+        self.funcs.extend(funcs);
+        self.imports.push(prefix.clone())
+    }
+
+    // an alias that names itself through the aliases within its type could never be resolved
+    fn check_alias(&self, type_node: &TypeNode, path: &mut Vec<String>) -> Result<(), String> {
+        match type_node {
+            TypeNode::Array(elem) | TypeNode::Optional(elem) => self.check_alias(elem, path),
+            TypeNode::Fn(args, ret) => {
+                for type_node in args.iter().chain(ret.as_deref()) {
+                    self.check_alias(type_node, path)?
+                }
+                Ok(())
+            }
+            TypeNode::Tuple(elems) => {
+                for elem in elems {
+                    self.check_alias(elem, path)?
+                }
+                Ok(())
+            }
+            TypeNode::Iden(iden) => {
+                let Some(alias) = self.aliases.iter().find(|alias| &alias.iden == iden) else { return Ok(()) };
+                let cyclic = path.contains(iden);
+                path.push(iden.clone());
+                if cyclic {
+                    return Err(format!("cyclic type alias {}", path.join(" -> ")))
+                }
+                self.check_alias(&alias.type_node, path)?;
+                path.pop();
+                Ok(())
+            }
+        }
+    }
+
+    // a struct that holds itself through its fields, directly or in a tuple, would have no finite size,
+    // an optional, an array or a function refers to the value without holding it so it can point back
+    fn check_contained(&self, type_node: &TypeNode, path: &mut Vec<String>) -> Result<(), String> {
+        match type_node {
+            TypeNode::Tuple(elems) => {
+                for elem in elems {
+                    self.check_contained(elem, path)?
+                }
+                Ok(())
+            }
+            TypeNode::Iden(iden) => {
+                let Some(def) = self.structs.iter().find(|def| &def.iden == iden) else { return Ok(()) };
+                let cyclic = path.contains(iden);
+                path.push(iden.clone());
+                if cyclic {
+                    return Err(format!("recursive struct {} contains itself, use an optional or an array to refer to it", path.join(" -> ")))
+                }
+                for (_, field) in &def.fields {
+                    self.check_contained(&self.resolve(field), path)?
+                }
+                path.pop();
+                Ok(())
+            }
+            TypeNode::Array(_) | TypeNode::Optional(_) | TypeNode::Fn(_, _) => Ok(())
+        }
+    }
+
+    fn resolve(&self, type_node: &TypeNode) -> TypeNode {
+        type_node.resolve(&self.aliases)
+    }
+
+    fn func_type(&self, func: &DefFuncNode) -> TypeNode {
+        let args = func.args.iter().map(|(_, type_node)| self.resolve(type_node)).collect();
+        let ret = func.ret.as_ref().map(|ret| Box::new(self.resolve(ret)));
+        TypeNode::Fn(args, ret)
+    }
+
+    // a function body can only see its own parameters and itself, just like a call frame
+    fn check_func(&mut self, func: &DefFuncNode) -> Result<(), String> {
+        let scope = vec![(func.iden.clone(), self.func_type(func), false)];
+        self.check_func_in(func, scope)
+    }
+
+    // a method body sees its receiver instead of itself, since a method can only be called on a receiver
+    fn check_method(&mut self, method: &DefMethodNode) -> Result<(), String> {
+        if !self.structs.iter().any(|def| def.iden == method.recv_type) {
+            return Err(format!("method {} is defined on {}, which is not a struct", method.func.iden, method.recv_type))
+        }
+        let scope = vec![(method.recv.clone(), iden(&method.recv_type), false)];
+        self.check_func_in(&method.func, scope)
+    }
+
+    fn check_func_in(&mut self, func: &DefFuncNode, mut scope: Scope) -> Result<(), String> {
+        for (iden, type_node) in &func.args {
+            scope.push((iden.clone(), self.resolve(type_node), false))
+        }
+        if func.ret.is_some() && !Self::always_returns(&func.body) {
+            return Err(format!("missing return in function {}, it can end without returning a value", func.iden))
+        }
+        let ret = func.ret.as_ref().map(|ret| self.resolve(ret)).unwrap_or_else(unit);
+
+        let scope = mem::replace(&mut self.scope, scope);
+        let ret = self.ret.replace(ret);
+        let result = self.check_body(&func.body);
+        self.scope = scope;
+        self.ret = ret;
+        result
+    }
+
+    // a body always returns when it reaches a return, or an if and its else both always return
+    fn always_returns(body: &[Node]) -> bool {
+        body.iter().enumerate().any(|(i, node)| match (node, body.get(i + 1)) {
+            (Node::Return(_), _) => true,
+            (Node::If(if_node), Some(Node::Else(else_body))) => {
+                Self::always_returns(&if_node.body) && Self::always_returns(else_body)
+            }
+            _ => false
+        })
+    }
+
+    // an error without a node that has a position is reported at the statement it was found in
+    fn check_body(&mut self, body: &[Node]) -> Result<(), String> {
+        for node in body {
+            let result = self.check_stmt(node);
+            if result.is_err() && self.err_pos.is_none() {
+                self.err_pos = node.first_pos()
+            }
+            result?
+        }
+        Ok(())
+    }
+
+    fn check_stmt(&mut self, node: &Node) -> Result<(), String> {
+        match node {
+            Node::Return(expr) => self.check_return(expr),
+            Node::Guard(node) => {
+                self.check_cond(&node.cond)?;
+                self.check_return(&node.this)
+            }
+            // the names an if, else or loop body declares go out of scope when it ends
+            Node::If(node) => {
+                self.check_cond(&node.cond)?;
+                let narrowed = self.narrowed(&node.cond)?;
+                let len = self.scope.len();
+                self.scope.extend(narrowed);
+                let result = self.check_body(&node.body);
+                self.scope.truncate(len);
+                result
+            }
+            Node::Else(body) => {
+                let len = self.scope.len();
+                let result = self.check_body(body);
+                self.scope.truncate(len);
+                result
+            }
+            Node::While(node) => {
+                self.check_cond(&node.cond)?;
+                let len = self.scope.len();
+                let result = self.check_body(&node.body);
+                self.scope.truncate(len);
+                result
+            }
+            Node::Break | Node::Continue => Ok(()),
+            Node::DefFunc(func) => {
+                self.scope.push((func.iden.clone(), self.func_type(func), false));
+                self.check_func(func)
+            }
+            Node::For(node) => {
+                let collection = self.check_expr(&node.collection, None)?;
+                let element = match collection {
+                    TypeNode::Array(elem) => *elem,
+                    TypeNode::Iden(name) if name == "string" => iden("char"),
+                    TypeNode::Iden(name) if name == "range" => iden("int"),
+                    collection => return Err(format!("cannot iterate over {} in a for loop", collection))
+                };
+                let len = self.scope.len();
+                self.bind(&node.element, element);
+                if let Some(index) = &node.index {
+                    self.bind(index, iden("int"))
+                }
+                let result = self.check_body(&node.body);
+                self.scope.truncate(len);
+                result
+            }
+            Node::Declare(iden, rhs) => {
+                let type_node = self.check_expr(rhs, None)?;
+                self.bind(iden, type_node);
+                Ok(())
+            }
+            Node::DeclareConst(iden, rhs) => {
+                let type_node = self.check_expr(rhs, None)?;
+                if iden != "_" {
+                    self.scope.push((iden.to_string(), type_node, true))
+                }
+                Ok(())
+            }
+            Node::DeclareMany(idens, rhs) => match self.check_expr(rhs, None)? {
+                TypeNode::Tuple(elems) if elems.len() == idens.len() => {
+                    for (iden, elem) in idens.iter().zip(elems) {
+                        self.bind(iden, elem)
+                    }
+                    Ok(())
+                }
+                rhs => Err(format!("cannot declare {} names from {}", idens.len(), rhs))
+            },
+            Node::Assign(iden, rhs) => {
+                self.check_mutable(iden)?;
+                let type_node = self.lookup(iden)?;
+                self.expect(rhs, &type_node).map(|_| ())
+            }
+            // storing into an element or a field writes the whole value back to the variable
+            Node::Store(node) => {
+                let mut target = node.target.as_ref();
+                while let Node::Index(IndexNode { base, .. }) | Node::Member(MemberNode { base, .. }) = target {
+                    target = base
+                }
+                if let Node::Variable(iden) = target {
+                    self.check_mutable(iden)?
+                }
+                let type_node = self.check_expr(&node.target, None)?;
+                self.expect(&node.value, &type_node).map(|_| ())
+            }
+            node => self.check_expr(node, None).map(|_| ())
+        }
+    }
+
+    fn check_mutable(&self, iden: &str) -> Result<(), String> {
+        match self.scope.iter().rev().find(|(name, _, _)| name == iden) {
+            Some((_, _, true)) => Err(format!("cannot assign to {}, it is declared const", iden)),
+            _ => Ok(())
+        }
+    }
+
+    // an optional variable compared to nil with != holds a value in the body of the if, so it has the type of the value
+    fn narrowed(&self, cond: &Node) -> Result<Option<(String, TypeNode, bool)>, String> {
+        if let Node::Binop(BinopNode { op: Bop::Neq, lhs, rhs, .. }) = cond {
+            if let (Node::Variable(iden), Node::Constant(Const::Nil)) = (lhs.as_ref(), rhs.as_ref()) {
+                if let TypeNode::Optional(elem) = self.lookup(iden)? {
+                    let fixed = self.check_mutable(iden).is_err();
+                    return Ok(Some((iden.clone(), *elem, fixed)))
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // the '_' name throws away the value instead of binding it
+    fn bind(&mut self, iden: &str, type_node: TypeNode) {
+        if iden != "_" {
+            self.scope.push((iden.to_string(), type_node, false))
+        }
+    }
+
+    fn check_return(&mut self, expr: &Node) -> Result<(), String> {
+        // a return without a value is only allowed where nothing is returned
+        if matches!(expr, Node::Tuple(elems) if elems.is_empty()) {
+            return match self.ret.get_or_insert_with(unit) {
+                ret if *ret == unit() => Ok(()),
+                ret => Err(format!("missing return value, expected {}", ret))
+            }
+        }
+        match self.ret.clone() {
+            Some(ret) => self.expect(expr, &ret).map(|_| ()),
+            None => {
+                self.ret = Some(self.check_expr(expr, None)?);
+                Ok(())
+            }
+        }
+    }
+
+    fn check_cond(&mut self, cond: &Node) -> Result<(), String> {
+        self.expect(cond, &iden("bool")).map(|_| ())
+    }
+
+    // checks a node against the type expected from its context
+    fn expect(&mut self, node: &Node, expected: &TypeNode) -> CheckResult {
+        let actual = self.check_expr(node, Some(expected))?;
+        if actual.assignable_to(expected) {
+            Ok(actual)
+        } else {
+            Err(format!("expected {} but got {}", expected, actual))
+        }
+    }
+
+    // the expected type is only a hint used to infer types that can't be known from the node alone
+    pub fn check_expr(&mut self, node: &Node, expected: Option<&TypeNode>) -> CheckResult {
+        let result = self.check_node(node, expected);
+        if result.is_err() && self.err_pos.is_none() {
+            self.err_pos = node.pos()
+        }
+        result
+    }
+
+    fn check_node(&mut self, node: &Node, expected: Option<&TypeNode>) -> CheckResult {
+        match node {
+            Node::Constant(Const::Nil) => match expected {
+                Some(optional @ TypeNode::Optional(_)) => Ok(optional.clone()),
+                _ => self.const_type(&Const::Nil)
+            },
+            Node::Constant(c) => self.const_type(c),
+            Node::Variable(iden) => self.lookup(iden),
+            Node::Binop(node) => self.check_binop(node),
+            Node::Unop(node) => self.check_unop(node),
+            Node::CallFunc(node) if !self.shadows(&node.iden) && Builtin::lookup(&node.iden).is_some_and(|b| b.type_node().is_none()) => {
+                self.check_builtin(&node.iden, &node.args)
+            }
+            Node::CallFunc(node) => {
+                let callee = self.lookup(&node.iden)?;
+                match self.type_params(&node.iden) {
+                    Some(params) if !self.shadows_local(&node.iden) => self.check_generic_call(&node.iden, &params, callee, &node.args),
+                    _ => self.check_call(callee, &node.args)
+                }
+            }
+            Node::Call(node) => {
+                let callee = self.check_callee(&node.callee)?;
+                let qualified = match node.callee.as_ref() {
+                    Node::Member(member) => self.module_path(&member.base).map(|path| format!("{}.{}", path, member.field)),
+                    _ => None
+                };
+                match qualified.and_then(|name| Some((self.type_params(&name)?, name))) {
+                    Some((params, name)) => self.check_generic_call(&name, &params, callee, &node.args),
+                    None => self.check_call(callee, &node.args)
+                }
+            }
+            Node::Index(node) => self.check_index(node),
+            Node::Slice(node) => self.check_slice(node),
+            Node::Member(node) => self.check_member(node),
+            Node::Array(elems) => self.check_array(elems, expected),
+            Node::Tuple(elems) => self.check_tuple(elems, expected),
+            Node::Struct(node) => self.check_struct(node),
+            Node::Range(node) => self.check_range(node),
+            Node::Ternary(node) => {
+                self.check_cond(&node.cond)?;
+                let then = self.check_expr(&node.then, expected)?;
+                self.expect(&node.otherwise, &then)
+                    .map_err(|err| format!("the branches of a ternary must have the same type, {}", err))?;
+                Ok(then)
+            }
+            Node::Lambda(node) => self.check_lambda(node, expected),
+            Node::Block(node) => {
+                self.check_body(&node.body)?;
+                match &node.value {
+                    Some(value) => self.check_expr(value, expected),
+                    None => Err("block ended without a value".to_string())
+                }
+            }
+            node => Err(format!("{} can't be used as a value", node.kind()))
+        }
+    }
+
+    fn const_type(&self, c: &Const) -> CheckResult {
+        let type_node = match c {
+            Const::Int(_) | Const::BigInt(_) => iden("int"),
+            Const::Float(_) => iden("float"),
+            Const::Bool(_) => iden("bool"),
+            Const::Char(_) => iden("char"),
+            Const::String(_) => iden("string"),
+            Const::Tuple(elems) => TypeNode::Tuple(elems.iter().map(|elem| self.const_type(elem)).collect::<Result<_, _>>()?),
+            Const::Func(func) => self.func_type(func),
+            Const::Builtin(builtin) => return Self::builtin_type(builtin),
+            Const::Range(_, _, _) => iden("range"),
+            Const::Array(elems) => match elems.first() {
+                Some(elem) => TypeNode::Array(Box::new(self.const_type(elem)?)),
+                None => return Err("cannot infer the element type of an empty array".to_string())
+            },
+            Const::Struct(iden, _) => TypeNode::Iden(iden.clone()),
+            Const::Closure(_) => return Err("closures have no static type".to_string()),
+            Const::Nil => return Err("nil can only be used where an optional type is expected".to_string())
+        };
+        Ok(type_node)
+    }
+
+    fn builtin_type(builtin: &Builtin) -> CheckResult {
+        builtin.type_node().ok_or_else(|| format!("builtin {:?} can only be called directly", builtin))
+    }
+
+    // whether a variable or a named function hides the builtin with the same name
+    fn shadows(&self, iden: &str) -> bool {
+        self.scope.iter().any(|(name, _, _)| name == iden) || self.funcs.iter().any(|(name, _)| name == iden)
+    }
+
+    // the builtins without a single function type, which are checked against the arguments of each call
+    fn check_builtin(&mut self, name: &str, args: &[Node]) -> CheckResult {
+        let mut types = vec![];
+        for arg in args {
+            types.push(self.check_expr(arg, None)?)
+        }
+        let is_number = |type_node: &TypeNode| type_node == &iden("int") || type_node == &iden("float");
+        match (name, types.as_slice()) {
+            ("print", _) => Ok(unit()),
+            ("len", [TypeNode::Array(_)]) => Ok(iden("int")),
+            ("len", [TypeNode::Iden(arg)]) if arg == "string" || arg == "range" => Ok(iden("int")),
+            ("len", [arg]) => Err(format!("len cannot be applied to {}, expected a string, an array or a range", arg)),
+            ("abs", [arg]) if is_number(arg) => Ok(arg.clone()),
+            ("abs", [arg]) => Err(format!("abs cannot be applied to {}, expected an int or a float", arg)),
+            ("typeof" | "str", [_]) => Ok(iden("string")),
+            ("assert", [arg]) if arg == &iden("bool") => Ok(unit()),
+            ("assert", [arg]) => Err(format!("assert cannot be applied to {}, expected a bool", arg)),
+            ("len" | "abs" | "typeof" | "str" | "assert", _) => {
+                Err(format!("function called with {} arguments but expected 1", args.len()))
+            }
+            ("assert_eq", [lhs, rhs]) if lhs == rhs => Ok(unit()),
+            ("assert_eq", [lhs, rhs]) => Err(format!("assert_eq cannot compare {} and {}", lhs, rhs)),
+            ("assert_eq", _) => Err(format!("function called with {} arguments but expected 2", args.len())),
+            // the ints are converted when there is a float among the arguments
+            ("min" | "max", [_, _, ..]) => match types.iter().find(|arg| !is_number(arg)) {
+                Some(arg) => Err(format!("{} cannot be applied to {}, expected ints or floats", name, arg)),
+                None if types.contains(&iden("float")) => Ok(iden("float")),
+                None => Ok(iden("int"))
+            },
+            _ => Err(format!("function called with {} arguments but expected at least 2", args.len()))
+        }
+    }
+
+    fn lookup(&self, iden: &str) -> CheckResult {
+        if let Some((_, type_node, _)) = self.scope.iter().rev().find(|(name, _, _)| name == iden) {
+            return Ok(type_node.clone())
+        }
+        if let Some((_, type_node)) = self.funcs.iter().find(|(name, _)| name == iden) {
+            return Ok(type_node.clone())
+        }
+        match Builtin::lookup(iden) {
+            Some(builtin) => Self::builtin_type(&builtin),
+            None => Err(format!("undefined variable {}", iden))
+        }
+    }
+
+    fn check_binop(&mut self, node: &BinopNode) -> CheckResult {
+        let lhs = self.check_expr(&node.lhs, None)?;
+        // an empty array joined onto another, or nil compared to an optional, takes its type from the lhs
+        let expected = Some(&lhs).filter(|lhs| matches!(lhs, TypeNode::Array(_) | TypeNode::Optional(_)));
+        let rhs = self.check_expr(&node.rhs, expected)?;
+        // only a literal exponent is known statically, any other exponent is checked when it is evaluated
+        if let (Bop::Exp, Node::Constant(Const::Int(exp))) = (&node.op, node.rhs.as_ref()) {
+            if *exp < 0 {
+                return Err(format!("exponent {} must be a non-negative int", exp))
+            }
+        }
+        Self::binop_type(&node.op, &lhs, &rhs).ok_or_else(|| {
+            format!("operator {:?} cannot be applied to {} and {}", node.op, lhs, rhs)
+        })
+    }
+
+    // mirrors the operands accepted by the interpreter's binary operators
+    fn binop_type(op: &Bop, lhs: &TypeNode, rhs: &TypeNode) -> Option<TypeNode> {
+        let prim = |type_node: &TypeNode| match type_node {
+            TypeNode::Iden(iden) => iden.clone(),
+            _ => String::new()
+        };
+        let type_node = match (op, prim(lhs).as_str(), prim(rhs).as_str()) {
+            (Bop::Plus, "int" | "float" | "string", _) if lhs == rhs => lhs.clone(),
+            (Bop::Minus | Bop::Multiply | Bop::Divide | Bop::Mod, "int" | "float", _) if lhs == rhs => lhs.clone(),
+            (Bop::Multiply, "string", "int") => lhs.clone(),
+            (Bop::Plus, _, _) if matches!(lhs, TypeNode::Array(_)) && lhs == rhs => lhs.clone(),
+            (Bop::Multiply, _, "int") if matches!(lhs, TypeNode::Array(_)) => lhs.clone(),
+            (Bop::Exp, "int", "int") | (Bop::Exp, "float", "float") => lhs.clone(),
+            (Bop::And | Bop::Or, "bool", "bool") => iden("bool"),
+            (Bop::Eq | Bop::Neq, _, _) if lhs == rhs => iden("bool"),
+            // only numbers, strings and chars have an order
+            (Bop::Lt | Bop::Gt | Bop::Leq | Bop::Geq, "int" | "float" | "string" | "char", _) if lhs == rhs => iden("bool"),
+            _ => return None
+        };
+        Some(type_node)
+    }
+
+    fn check_unop(&mut self, node: &UnopNode) -> CheckResult {
+        let expr = self.check_expr(&node.expr, None)?;
+        match (&node.op, expr.to_string().as_str()) {
+            (Uop::Minus, "int" | "float") | (Uop::Not, "bool") => Ok(expr),
+            _ => Err(format!("operator {:?} cannot be applied to {}", node.op, expr))
+        }
+    }
+
+    fn check_call(&mut self, callee: TypeNode, args: &[Node]) -> CheckResult {
+        match callee {
+            TypeNode::Fn(params, ret) => {
+                if params.len() != args.len() {
+                    return Err(format!("function called with {} arguments but expected {}", args.len(), params.len()))
+                }
+                for (arg, param) in args.iter().zip(params.iter()) {
+                    self.expect(arg, param)?;
+                }
+                Ok(ret.map(|ret| *ret).unwrap_or_else(unit))
+            }
+            callee => Err(format!("only functions can be called, got {}", callee))
+        }
+    }
+
+    // the path of the module a node names, unless a variable shadows the outermost module
+    fn module_path(&self, node: &Node) -> Option<String> {
+        let path = match node {
+            Node::Variable(iden) if !self.scope.iter().any(|(name, _, _)| name == iden) => iden.clone(),
+            Node::Member(member) => format!("{}.{}", self.module_path(&member.base)?, member.field),
+            _ => return None
+        };
+        self.imports.contains(&path).then_some(path)
+    }
+
+    fn type_params(&self, name: &str) -> Option<Vec<String>> {
+        self.generics.iter().find(|(iden, _)| iden == name).map(|(_, params)| params.clone())
+    }
+
+    fn shadows_local(&self, iden: &str) -> bool {
+        self.scope.iter().any(|(name, _, _)| name == iden)
+    }
+
+    // each type parameter is bound to the type of the first argument that uses it, and then every other
+    // argument that uses it has to agree, the return type is the declared one with the bound types put in
+    fn check_generic_call(&mut self, name: &str, params: &[String], callee: TypeNode, args: &[Node]) -> CheckResult {
+        let TypeNode::Fn(arg_types, ret) = callee else { return self.check_call(callee, args) };
+        if arg_types.len() != args.len() {
+            return Err(format!("function called with {} arguments but expected {}", args.len(), arg_types.len()))
+        }
+        let mut bound = vec![];
+        for (arg, arg_type) in args.iter().zip(&arg_types) {
+            let hint = Self::instantiate(arg_type, &bound);
+            let hint = hint.as_ref().filter(|hint| !Self::mentions(hint, params));
+            let actual = self.check_expr(arg, hint)?;
+            Self::bind_params(arg_type, &actual, params, &mut bound);
+            let expected = Self::instantiate(arg_type, &bound).unwrap_or_else(|| arg_type.clone());
+            if !actual.assignable_to(&expected) {
+                return Err(format!("expected {} but got {}", expected, actual))
+            }
+        }
+        match ret {
+            Some(ret) => match Self::instantiate(&ret, &bound) {
+                Some(ret) if !Self::mentions(&ret, params) => Ok(ret),
+                _ => Err(format!("cannot infer the type parameters of {} from its arguments", name))
+            },
+            None => Ok(unit())
+        }
+    }
+
+    // binds the type parameters that are still unbound where they appear in the parameter type
+    fn bind_params(param: &TypeNode, actual: &TypeNode, params: &[String], bound: &mut Vec<(String, TypeNode)>) {
+        match (param, actual) {
+            (TypeNode::Iden(iden), _) if params.contains(iden) && !bound.iter().any(|(name, _)| name == iden) => {
+                bound.push((iden.clone(), actual.clone()))
+            }
+            (TypeNode::Array(param), TypeNode::Array(actual)) | (TypeNode::Optional(param), TypeNode::Optional(actual)) => {
+                Self::bind_params(param, actual, params, bound)
+            }
+            // a value is passed where an optional of its type is expected
+            (TypeNode::Optional(param), actual) => Self::bind_params(param, actual, params, bound),
+            (TypeNode::Tuple(params_elems), TypeNode::Tuple(actual_elems)) if params_elems.len() == actual_elems.len() => {
+                for (param, actual) in params_elems.iter().zip(actual_elems) {
+                    Self::bind_params(param, actual, params, bound)
+                }
+            }
+            (TypeNode::Fn(param_args, param_ret), TypeNode::Fn(actual_args, actual_ret)) if param_args.len() == actual_args.len() => {
+                for (param, actual) in param_args.iter().zip(actual_args) {
+                    Self::bind_params(param, actual, params, bound)
+                }
+                if let (Some(param), Some(actual)) = (param_ret, actual_ret) {
+                    Self::bind_params(param, actual, params, bound)
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // the type with each bound type parameter replaced, a parameter that isn't bound yet is left as it is
+    fn instantiate(type_node: &TypeNode, bound: &[(String, TypeNode)]) -> Option<TypeNode> {
+        let type_node = match type_node {
+            TypeNode::Iden(iden) => match bound.iter().find(|(name, _)| name == iden) {
+                Some((_, type_node)) => type_node.clone(),
+                None => type_node.clone()
+            },
+            TypeNode::Array(elem) => TypeNode::Array(Box::new(Self::instantiate(elem, bound)?)),
+            TypeNode::Optional(elem) => TypeNode::Optional(Box::new(Self::instantiate(elem, bound)?)),
+            TypeNode::Tuple(elems) => TypeNode::Tuple(elems.iter().map(|elem| Self::instantiate(elem, bound)).collect::<Option<_>>()?),
+            TypeNode::Fn(args, ret) => {
+                let args = args.iter().map(|arg| Self::instantiate(arg, bound)).collect::<Option<_>>()?;
+                let ret = match ret {
+                    Some(ret) => Some(Box::new(Self::instantiate(ret, bound)?)),
+                    None => None
+                };
+                TypeNode::Fn(args, ret)
+            }
+        };
+        Some(type_node)
+    }
+
+    fn mentions(type_node: &TypeNode, params: &[String]) -> bool {
+        match type_node {
+            TypeNode::Iden(iden) => params.contains(iden),
+            TypeNode::Array(elem) | TypeNode::Optional(elem) => Self::mentions(elem, params),
+            TypeNode::Tuple(elems) => elems.iter().any(|elem| Self::mentions(elem, params)),
+            TypeNode::Fn(args, ret) => args.iter().chain(ret.as_deref()).any(|type_node| Self::mentions(type_node, params))
+        }
+    }
+
+    // a member that isn't a field of the struct names one of the methods of the struct, and a member of a
+    // module names one of its functions
+    fn check_callee(&mut self, callee: &Node) -> CheckResult {
+        if let Node::Member(member) = callee {
+            if let Some(path) = self.module_path(&member.base) {
+                let qualified = format!("{}.{}", path, member.field);
+                return match self.funcs.iter().find(|(name, _)| name == &qualified) {
+                    Some((_, type_node)) => Ok(type_node.clone()),
+                    None => Err(format!("module {} has no function {}", path, member.field))
+                }
+            }
+            if let TypeNode::Iden(name) = self.check_expr(&member.base, None)? {
+                let is_field = self.structs.iter()
+                    .find(|def| def.iden == name)
+                    .is_some_and(|def| def.fields.iter().any(|(field, _)| field == &member.field));
+                let method = self.methods.iter().find(|method| method.recv_type == name && method.func.iden == member.field);
+                if let (false, Some(method)) = (is_field, method) {
+                    return Ok(self.func_type(&method.func))
+                }
+            }
+        }
+        self.check_expr(callee, None)
+    }
+
+    fn check_index(&mut self, node: &IndexNode) -> CheckResult {
+        let base = self.check_expr(&node.base, None)?;
+        self.expect(&node.index, &iden("int"))?;
+        match base {
+            TypeNode::Array(elem) => Ok(*elem),
+            TypeNode::Iden(name) if name == "string" => Ok(iden("char")),
+            base => Err(format!("cannot index into {}", base))
+        }
+    }
+
+    // a slice has the same type as what it is sliced from
+    fn check_slice(&mut self, node: &SliceNode) -> CheckResult {
+        let base = self.check_expr(&node.base, None)?;
+        for bound in node.start.iter().chain(&node.end) {
+            self.expect(bound, &iden("int"))?;
+        }
+        match base {
+            TypeNode::Array(_) => Ok(base),
+            TypeNode::Iden(ref name) if name == "string" => Ok(base),
+            base => Err(format!("cannot slice {}", base))
+        }
+    }
+
+    fn check_member(&mut self, node: &MemberNode) -> CheckResult {
+        let base = self.check_expr(&node.base, None)?;
+        let def = match &base {
+            TypeNode::Iden(iden) => self.structs.iter().find(|def| &def.iden == iden),
+            _ => None
+        };
+        match def.and_then(|def| def.fields.iter().find(|(field, _)| field == &node.field)) {
+            Some((_, type_node)) => Ok(self.resolve(type_node)),
+            None => Err(format!("{} has no field {}", base, node.field))
+        }
+    }
+
+    // the element type comes from the context when there is one, otherwise from the first element
+    fn check_array(&mut self, elems: &[Node], expected: Option<&TypeNode>) -> CheckResult {
+        let (elem, rest) = match (expected, elems.split_first()) {
+            (Some(TypeNode::Array(elem)), _) => (elem.as_ref().clone(), elems),
+            (_, Some((first, rest))) => (self.check_expr(first, None)?, rest),
+            (_, None) => return Err("cannot infer the element type of an empty array".to_string())
+        };
+        for node in rest {
+            let actual = self.check_expr(node, Some(&elem))?;
+            if !actual.assignable_to(&elem) {
+                return Err(format!("array elements must all have the same type, expected {} but got {}", elem, actual))
+            }
+        }
+        Ok(TypeNode::Array(Box::new(elem)))
+    }
+
+    fn check_tuple(&mut self, elems: &[Node], expected: Option<&TypeNode>) -> CheckResult {
+        let expected = match expected {
+            Some(TypeNode::Tuple(expected)) if expected.len() == elems.len() => expected.as_slice(),
+            _ => &[]
+        };
+        let mut types = vec![];
+        for (i, elem) in elems.iter().enumerate() {
+            types.push(self.check_expr(elem, expected.get(i))?)
+        }
+        Ok(TypeNode::Tuple(types))
+    }
+
+    // a range is iterated like an array of its ints
+    fn check_range(&mut self, node: &RangeNode) -> CheckResult {
+        self.expect(&node.start, &iden("int"))?;
+        self.expect(&node.end, &iden("int"))?;
+        Ok(iden("range"))
+    }
+
+    // a struct literal must give every declared field exactly once
+    fn check_struct(&mut self, node: &StructNode) -> CheckResult {
+        let def = match self.structs.iter().find(|def| def.iden == node.iden) {
+            Some(def) => def.clone(),
+            None => return Err(format!("undefined struct {}", node.iden))
+        };
+        for (i, (field, value)) in node.fields.iter().enumerate() {
+            let type_node = match def.fields.iter().find(|(name, _)| name == field) {
+                Some((_, type_node)) => self.resolve(type_node),
+                None => return Err(format!("struct {} has no field {}", def.iden, field))
+            };
+            if node.fields[..i].iter().any(|(name, _)| name == field) {
+                return Err(format!("field {} of struct {} is given more than once", field, def.iden))
+            }
+            self.expect(value, &type_node)?;
+        }
+        if let Some((name, _)) = def.fields.iter().find(|(name, _)| !node.fields.iter().any(|(field, _)| field == name)) {
+            return Err(format!("struct {} is missing field {}", def.iden, name))
+        }
+        Ok(TypeNode::Iden(def.iden))
+    }
+
+    // untyped lambda arguments and the return type are taken from the expected fn type
+    fn check_lambda(&mut self, node: &LambdaNode, expected: Option<&TypeNode>) -> CheckResult {
+        let (params, ret) = match expected {
+            Some(TypeNode::Fn(params, ret)) => (params.as_slice(), Some(ret.as_ref().map(|ret| *ret.clone()).unwrap_or_else(unit))),
+            _ => (&[] as &[TypeNode], None)
+        };
+
+        let mut scope = self.scope.clone();
+        let mut args = vec![];
+        for (i, (iden, type_node)) in node.args.iter().enumerate() {
+            let arg = match (type_node, params.get(i)) {
+                (Some(type_node), _) => self.resolve(type_node),
+                (None, Some(param)) => param.clone(),
+                (None, None) => return Err(format!("cannot infer the type of lambda argument {}", iden))
+            };
+            scope.push((iden.clone(), arg.clone(), false));
+            args.push(arg)
+        }
+
+        let scope = mem::replace(&mut self.scope, scope);
+        let ret = mem::replace(&mut self.ret, ret);
+        let result = self.check_lambda_body(&node.body);
+        self.scope = scope;
+        let ret = mem::replace(&mut self.ret, ret);
+
+        result?;
+        let ret = ret.ok_or_else(|| "lambda ended without returning a value".to_string())?;
+        Ok(TypeNode::Fn(args, Some(Box::new(ret))))
+    }
+
+    // a lambda's trailing value is checked like a return
+    fn check_lambda_body(&mut self, body: &Node) -> Result<(), String> {
+        match body {
+            Node::Block(block) => {
+                self.check_body(&block.body)?;
+                match &block.value {
+                    Some(value) => self.check_return(value),
+                    None => Ok(())
+                }
+            }
+            body => self.check_return(body)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufReader, Cursor};
+    use crate::checker::{check_str, Checker};
+    use crate::diagnostic::Diagnostic;
+    use crate::lexer::Position;
+    use crate::lexer::Lexer;
+    use crate::node::Node;
+    use crate::parser::Parser;
+
+    fn parse(program: &str) -> Vec<Node> {
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+        Parser::new(tokens).parse_program().unwrap()
+    }
+
+    fn check(program: &str) -> Result<(), String> {
+        Checker::new().check_program(&parse(program))
+    }
+
+    #[test]
+    fn test_check_array() {
+        let program = "
+            fn sum(arr []int) -> int {
+                return arr[0] + arr[1];
+            }
+
+            fn main() {
+                arr := [1, 2, 3];
+                arr = [4, 5];
+                sum(arr);
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+    }
+
+    #[test]
+    fn test_check_mixed_array() {
+        let program = "
+            fn main() {
+                arr := [1, \"x\"];
+            }
+        ";
+        assert_eq!(check(program), Err("array elements must all have the same type, expected int but got string".to_string()));
+
+        let program = "
+            fn main(arr []int) {
+                arr = [true];
+            }
+        ";
+        assert_eq!(check(program), Err("array elements must all have the same type, expected int but got bool".to_string()));
+    }
+
+    #[test]
+    fn test_check_empty_array() {
+        let program = "
+            fn main(arr []int) {
+                arr = [];
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+
+        let program = "
+            fn main() {
+                arr := [];
+            }
+        ";
+        assert_eq!(check(program), Err("cannot infer the element type of an empty array".to_string()));
+    }
+
+    #[test]
+    fn test_check_tuple() {
+        let program = "
+            fn divmod(a int, b int) -> (int, int) {
+                q := a / b;
+                return (q, a - q * b);
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+
+        let program = "
+            fn divmod(a int, b int) -> (int, int) {
+                return (a, true);
+            }
+        ";
+        assert_eq!(check(program), Err("expected (int, int) but got (int, bool)".to_string()));
+    }
+
+    #[test]
+    fn test_check_negative_exponent() {
+        let program = "
+            fn f(n int) -> int {
+                return 2 ** -1;
+            }
+        ";
+        assert_eq!(check(program), Err("exponent -1 must be a non-negative int".to_string()));
+
+        let program = "
+            fn f(n int) -> int {
+                return 2 ** 3 + 2 ** n;
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+    }
+
+    #[test]
+    fn test_check_missing_return() {
+        let program = "
+            fn sign(n int) -> int {
+                if n < 0 -> -1;
+                if n > 0 {
+                    return 1;
+                }
+            }
+        ";
+        assert_eq!(check(program), Err("missing return in function sign, it can end without returning a value".to_string()));
+
+        let program = "
+            fn sign(n int) -> int {
+                if n < 0 -> -1;
+                if n > 0 {
+                    return 1;
+                } else if n == 0 {
+                    return 0;
+                } else {
+                    return -1;
+                }
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+    }
+
+    #[test]
+    fn test_check_bare_return() {
+        assert_eq!(check("fn f(n int) { if n > 0 { return; } print(n); }"), Ok(()));
+        assert_eq!(check("fn f() -> int { return; }"), Err("missing return value, expected int".to_string()));
+    }
+
+    #[test]
+    fn test_check_struct() {
+        let program = "
+            struct Point { x int, y int }
+
+            fn origin() -> Point {
+                return Point{ y = 0, x = 0 };
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+
+        let program = "
+            struct Point { x int, y int }
+
+            fn origin() -> Point {
+                return Point{ x = 0 };
+            }
+        ";
+        assert_eq!(check(program), Err("struct Point is missing field y".to_string()));
+    }
+
+    #[test]
+    fn test_check_store() {
+        let program = "
+            struct Point { x int, y int }
+
+            fn main() {
+                a := [1, 2, 3];
+                a[0] += 5;
+                p := Point{ x = 1, y = 2 };
+                p.x = a[1];
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+
+        let program = "
+            struct Point { x int, y int }
+
+            fn main() {
+                p := Point{ x = 1, y = 2 };
+                p.x = true;
+            }
+        ";
+        assert_eq!(check(program), Err("expected int but got bool".to_string()));
+    }
+
+    #[test]
+    fn test_check_comparison() {
+        let program = "
+            fn main(a string, b string, x bool, y bool) {
+                c := a < b;
+                d := x == y;
+                e := a != b;
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+
+        let program = "
+            fn main() {
+                c := 1 < \"x\";
+            }
+        ";
+        assert_eq!(check(program), Err("operator Lt cannot be applied to int and string".to_string()));
+
+        let program = "
+            fn main() {
+                c := true < false;
+            }
+        ";
+        assert_eq!(check(program), Err("operator Lt cannot be applied to bool and bool".to_string()));
+    }
+
+    #[test]
+    fn test_check_method() {
+        let program = "
+            struct Point { x int, y int }
+
+            fn (p Point) sum() -> int {
+                return p.x + p.y;
+            }
+
+            fn main() -> int {
+                p := Point{ x = 1, y = 2 };
+                return p.sum();
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+
+        let program = "
+            struct Point { x int, y int }
+
+            fn (p Point) sum() -> int {
+                return p.x + p.y;
+            }
+
+            fn main() -> bool {
+                p := Point{ x = 1, y = 2 };
+                return p.sum();
+            }
+        ";
+        assert_eq!(check(program), Err("expected bool but got int".to_string()));
+    }
+
+    #[test]
+    fn test_check_range_value() {
+        let program = "
+            fn main() -> int {
+                r := 0..5;
+                for i in r {
+                    r = 0..i;
+                }
+                return len(r) + len(\"abc\") + len([1, 2]);
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+
+        let program = "
+            fn main() -> int {
+                return len(1);
+            }
+        ";
+        assert_eq!(check(program), Err("len cannot be applied to int, expected a string, an array or a range".to_string()));
+    }
+
+    #[test]
+    fn test_check_array_operators() {
+        let program = "
+            fn main() {
+                arr := [1, 2] + [3];
+                arr = arr + [];
+                arr = [0] * 3;
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+
+        let program = "
+            fn main() {
+                arr := [1] + [true];
+            }
+        ";
+        assert_eq!(check(program), Err("array elements must all have the same type, expected int but got bool".to_string()));
+
+        let program = "
+            fn main(arr []int, strs []string) {
+                arr = arr + strs;
+            }
+        ";
+        assert_eq!(check(program), Err("operator Plus cannot be applied to []int and []string".to_string()));
+    }
+
+    #[test]
+    fn test_check_optional() {
+        let program = "
+            fn inc(x ?int) -> int {
+                if x != nil {
+                    return x + 1;
+                }
+                return 0;
+            }
+
+            fn main() {
+                y := inc(nil);
+                z := inc(y);
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+
+        let program = "
+            fn inc(x ?int) -> int {
+                return x + 1;
+            }
+        ";
+        assert_eq!(check(program), Err("operator Plus cannot be applied to ?int and int".to_string()));
+
+        let program = "
+            fn main() {
+                x := nil;
+            }
+        ";
+        assert_eq!(check(program), Err("nil can only be used where an optional type is expected".to_string()));
+    }
+
+    #[test]
+    fn test_check_numeric_builtins() {
+        let program = "
+            fn main() -> float {
+                n := abs(-3) + max(1, 2, 3);
+                return min(1.5, n);
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+
+        let program = "
+            fn main() -> int {
+                return min(1, 2.0);
+            }
+        ";
+        assert_eq!(check(program), Err("expected int but got float".to_string()));
+
+        let program = "
+            fn main() -> int {
+                return max(1);
+            }
+        ";
+        assert_eq!(check(program), Err("function called with 1 arguments but expected at least 2".to_string()));
+    }
+
+    #[test]
+    fn test_check_type_alias() {
+        let program = "
+            type Celsius float
+
+            fn warm(c Celsius) -> float {
+                return c + 1.5;
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+
+        // an alias can name an alias defined after it
+        let program = "
+            type Id Key
+            type Key int
+            struct User { id Id }
+
+            fn next(user User) -> int {
+                return user.id + 1;
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+
+        let program = "
+            type A B
+            type B []A
+        ";
+        assert_eq!(check(program), Err("cyclic type alias A -> B -> A".to_string()));
+        assert_eq!(check("type Self (int, Self)"), Err("cyclic type alias Self -> Self".to_string()));
+    }
+
+    #[test]
+    fn test_check_recursive_struct() {
+        let err = Err("recursive struct Node -> Node contains itself, use an optional or an array to refer to it".to_string());
+        assert_eq!(check("struct Node { value int, next Node }"), err);
+        assert_eq!(check("struct Node { value int, next ?Node }"), Ok(()));
+        assert_eq!(check("struct Tree { children []Tree }"), Ok(()));
+
+        // the cycle can go through other structs, tuples and aliases
+        let program = "
+            type Pair (int, B)
+            struct A { pair Pair }
+            struct B { a A }
+        ";
+        let err = Err("recursive struct A -> B -> A contains itself, use an optional or an array to refer to it".to_string());
+        assert_eq!(check(program), err);
+        assert_eq!(check("struct A { b ?B } struct B { a A }"), Ok(()));
+    }
+
+    #[test]
+    fn test_check_slice() {
+        assert_eq!(check("fn f(a []int) -> []int { return a[1..len(a)]; }"), Ok(()));
+        assert_eq!(check("fn f(s string) -> string { return s[..2]; }"), Ok(()));
+        assert_eq!(check("fn f(a []int) -> int { return a[..]; }"), Err("expected int but got []int".to_string()));
+        assert_eq!(check("fn f(a []int) -> []int { return a[true..]; }"), Err("expected int but got bool".to_string()));
+        assert!(check("fn f(x int) -> int { return x[0..1]; }").unwrap_err().starts_with("cannot slice int"));
+    }
+
+    #[test]
+    fn test_check_const() {
+        let err = Err("cannot assign to x, it is declared const".to_string());
+        assert_eq!(check("fn f() -> int { const x := 1; x = 2; return x; }"), err);
+        assert_eq!(check("fn f() -> int { const x := 1; x += 2; return x; }"), err);
+        assert_eq!(check("fn f() -> int { x := 1; x = 2; return x; }"), Ok(()));
+        let err = Err("cannot assign to a, it is declared const".to_string());
+        assert_eq!(check("fn f() -> int { const a := [1]; a[0] = 2; return a[0]; }"), err);
+
+        // a declaration without const shadows the const, and the const is still enforced in a nested body
+        assert_eq!(check("fn f() -> int { const x := 1; x := 2; x = 3; return x; }"), Ok(()));
+        assert!(check("fn f(b bool) -> int { const x := 1; if b { x = 2; } return x; }").is_err());
+        assert!(check("fn f(x ?int) -> int { const y := x; if y != nil { y = 1; return y; } return 0; }").is_err());
+    }
+
+    #[test]
+    fn test_check_ternary() {
+        assert_eq!(check("fn sign(x int) -> int { return x > 0 ? 1 : -1; }"), Ok(()));
+        assert_eq!(check("fn f(x ?int, b bool) -> ?int { return b ? nil : x; }"), Ok(()));
+        assert_eq!(check("fn f(x int) -> int { return x ? 1 : 2; }"), Err("expected bool but got int".to_string()));
+        let err = "the branches of a ternary must have the same type, expected int but got string".to_string();
+        assert_eq!(check("fn f(b bool) -> int { return b ? 1 : \"one\"; }"), Err(err));
+    }
+
+    #[test]
+    fn test_check_loop_scope() {
+        assert_eq!(check("fn f() -> int { n := 0; while n < 3 { step := 1; n += step; } return n; }"), Ok(()));
+        let err = Err("undefined variable step".to_string());
+        assert_eq!(check("fn f() -> int { n := 0; while n < 3 { step := 1; n += step; } return step; }"), err);
+        assert_eq!(check("fn f() -> int { for e in [1] {} return e; }"), Err("undefined variable e".to_string()));
+        assert_eq!(check("fn f() -> int { x := 0; if true { x := \"s\"; } else { x := true; } return x; }"), Ok(()));
+        assert_eq!(check("fn f() -> int { if true { y := 2; } return y; }"), Err("undefined variable y".to_string()));
+    }
+
+    #[test]
+    fn test_check_typeof() {
+        assert_eq!(check("fn f(x [](int, bool)) -> string { return typeof(x); }"), Ok(()));
+        let err = Err("function called with 2 arguments but expected 1".to_string());
+        assert_eq!(check("fn f() -> string { return typeof(1, 2); }"), err);
+    }
+
+    #[test]
+    fn test_check_str() {
+        assert_eq!(check("fn f(n int) -> string { return \"count: \" + str(n); }"), Ok(()));
+        let err = Err("function called with 2 arguments but expected 1".to_string());
+        assert_eq!(check("fn f() -> string { return str(1, 2); }"), err);
+        assert!(check("fn f(n int) -> int { return str(n); }").is_err());
+    }
+
+    #[test]
+    fn test_check_assert() {
+        assert_eq!(check("fn f(n int) { assert(n > 0); assert_eq(n, 1); }"), Ok(()));
+        assert_eq!(check("fn f() { assert(1); }"), Err("assert cannot be applied to int, expected a bool".to_string()));
+        assert_eq!(check("fn f() { assert_eq(1, true); }"), Err("assert_eq cannot compare int and bool".to_string()));
+    }
+
+    #[test]
+    fn test_check_source() {
+        assert_eq!(check_str("fn main() { x := 1 + 2; print(x); }"), Ok(()));
+
+        // the call to print is checked but never run, so nothing is written
+        let diagnostics = check_str("fn main() { print(\"ran\"); x := 1 + true; }").unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.starts_with("operator Plus cannot be applied to int and bool"), "{:?}", diagnostics);
+
+        // both bad tokens are reported with the span they cover
+        let diagnostics = check_str("fn main() {\n    x := 12ab;\n    y := 1 <> 2;\n}").unwrap_err();
+        assert_eq!(diagnostics.len(), 2, "{:?}", diagnostics);
+        assert_eq!(diagnostics[0].lpos, Some(Position { line: 2, col: 10 }));
+        assert_eq!(diagnostics[1].lpos.map(|pos| pos.line), Some(3));
+
+        let diagnostics = check_str("fn f() { x := ; }\nfn g() -> int { return 1; }\nstruct { }").unwrap_err();
+        assert_eq!(diagnostics.len(), 2, "{:?}", diagnostics);
+        assert!(diagnostics.iter().all(|diagnostic| diagnostic.lpos.is_some()));
+
+        // each definition is checked on its own, so both type errors are reported where they were found
+        let source = "fn f() -> int {\n    return 1 + true;\n}\nfn g() -> int { return 1; }\nfn h(xs []int) -> bool {\n    return xs[0] && false;\n}";
+        let diagnostics = check_str(source).unwrap_err();
+        assert_eq!(diagnostics.len(), 2, "{:?}", diagnostics);
+        assert!(diagnostics[0].message.starts_with("operator Plus cannot be applied to int and bool"), "{:?}", diagnostics);
+        assert_eq!(diagnostics[0].lpos, Some(Position { line: 2, col: 14 }));
+        assert!(diagnostics[1].message.starts_with("operator And cannot be applied to int and bool"), "{:?}", diagnostics);
+        assert_eq!(diagnostics[1].lpos.map(|pos| pos.line), Some(6));
+    }
+
+    #[test]
+    fn test_check_diagnostic_positions() {
+        let pos = |line, col| Some(Position { line, col });
+
+        // nothing in the assignment has a position of its own, so it is reported at the function
+        let diagnostics = check_str("fn f() {\n    x := 1;\n    x = \"s\";\n}").unwrap_err();
+        assert_eq!(diagnostics, vec![Diagnostic::new("expected int but got string".to_string(), pos(1, 4), pos(1, 4))]);
+
+        // a mismatch found after the value checked is reported at the statement
+        let diagnostics = check_str("fn f() -> bool {\n    return 1 + 2;\n}").unwrap_err();
+        assert_eq!(diagnostics, vec![Diagnostic::new("expected bool but got int".to_string(), pos(2, 14), pos(2, 14))]);
+
+        let diagnostics = check_str("type A B\ntype B A\nstruct P { p P }").unwrap_err();
+        assert_eq!(diagnostics.len(), 3, "{:?}", diagnostics);
+        assert_eq!(diagnostics.iter().map(|diagnostic| diagnostic.lpos).collect::<Vec<_>>(), vec![pos(1, 6), pos(2, 6), pos(3, 8)]);
+    }
+
+    #[test]
+    fn test_check_module_bodies() {
+        let err = Err("expected int but got bool".to_string());
+        assert_eq!(check("mod math { fn one() -> int { return true; } }"), err);
+        assert_eq!(check("mod outer { mod inner { fn one() -> int { return true; } } }"), err);
+
+        let program = "mod math { fn add(a int, b int) -> int { return a + b; } } fn main() -> int { return math.add(1, 2); }";
+        assert_eq!(check(program), Ok(()));
+    }
+
+    #[test]
+    fn test_check_qualified_call() {
+        let program = "
+            mod math {
+                fn add(a int, b int) -> int {
+                    return a + b;
+                }
+                mod consts {
+                    fn one() -> int {
+                        return 1;
+                    }
+                }
+            }
+            fn main() -> int {
+                return math.add(1, math.consts.one());
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+
+        let err = Err("expected int but got bool".to_string());
+        assert_eq!(check("mod math { fn add(a int, b int) -> int { return a + b; } } fn main() -> int { return math.add(1, true); }"), err);
+        let err = Err("module math has no function sub".to_string());
+        assert_eq!(check("mod math { fn add(a int, b int) -> int { return a + b; } } fn main() -> int { return math.sub(1, 2); }"), err);
+        // a function of a module is only reachable through its path
+        let err = Err("undefined variable add".to_string());
+        assert_eq!(check("mod math { fn add(a int, b int) -> int { return a + b; } } fn main() -> int { return add(1, 2); }"), err);
+    }
+
+    #[test]
+    fn test_check_import_alias() {
+        let check_with = |program: &str| {
+            let module = parse("fn square(n int) -> int { return n * n; }");
+            Checker::new().with_module("math", module).check_program(&parse(program))
+        };
+
+        assert_eq!(check_with("import math as m fn main() -> int { return m.square(3); }"), Ok(()));
+        assert_eq!(check_with("import math fn main() -> int { return math.square(3); }"), Ok(()));
+        assert_eq!(check_with("import math as m fn main() -> bool { return m.square(3); }"), Err("expected bool but got int".to_string()));
+        // the module is only reachable through its alias
+        let err = Err("undefined variable math".to_string());
+        assert_eq!(check_with("import math as m fn main() -> int { return math.square(3); }"), err);
+    }
+
+    #[test]
+    fn test_check_generic_call() {
+        let program = "
+            fn id[T](x T) -> T {
+                return x;
+            }
+            fn first[T](xs []T, fallback T) -> T {
+                return len(xs) > 0 ? xs[0] : fallback;
+            }
+            fn main() -> int {
+                s := id(\"s\");
+                b := id(true);
+                return id(1) + first([2, 3], 4) + len(s);
+            }
+        ";
+        assert_eq!(check(program), Ok(()));
+
+        // every use of a type parameter has to agree on the type it is bound to
+        let err = Err("expected int but got string".to_string());
+        assert_eq!(check("fn first[T](xs []T, fallback T) -> T { return fallback; } fn main() -> int { return first([1], \"s\"); }"), err);
+        let err = Err("expected string but got int".to_string());
+        assert_eq!(check("fn id[T](x T) -> T { return x; } fn main() -> string { return id(1); }"), err);
+        // inside of the body a type parameter is opaque, so it can't be used as the type it will be bound to
+        assert!(check("fn inc[T](x T) -> T { return x + 1; }").is_err());
+        let err = Err("cannot infer the type parameters of make from its arguments".to_string());
+        assert_eq!(check("fn make[T]() -> ?T { return nil; } fn main() { x := make(); }"), err);
+    }
+
+    #[test]
+    fn test_check_statement_as_value() {
+        let err = Err("a break can't be used as a value".to_string());
+        assert_eq!(Checker::new().check_expr(&Node::Break, None), err);
+    }
+}
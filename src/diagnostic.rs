@@ -0,0 +1,102 @@
+// Renders an error with the line of source it points at, and carets under the columns it spans
+
+use crate::lexer::Position;
+
+// an error found before the program runs, with the span of the source it points at when that is known
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub lpos: Option<Position>,
+    pub rpos: Option<Position>,
+}
+
+impl Diagnostic {
+    pub fn new(message: String, lpos: Option<Position>, rpos: Option<Position>) -> Diagnostic {
+        Diagnostic { message, lpos, rpos }
+    }
+
+    pub fn render(&self, source: &str) -> String {
+        match (self.lpos, self.rpos) {
+            (Some(lpos), Some(rpos)) => render(source, &self.message, lpos, rpos),
+            (Some(pos), None) | (None, Some(pos)) => render_at(source, &self.message, pos),
+            (None, None) => format!("error: {}\n", self.message)
+        }
+    }
+}
+
+pub fn render_at(source: &str, message: &str, pos: Position) -> String {
+    render(source, message, pos, pos)
+}
+
+// the carets stop at the end of the first line when the span covers more than one
+pub fn render(source: &str, message: &str, lpos: Position, rpos: Position) -> String {
+    let mut rendered = format!("error: {}\n", message);
+    let line = match source.lines().nth(lpos.line.saturating_sub(1) as usize) {
+        Some(line) => line,
+        None => return rendered
+    };
+
+    let gutter = lpos.line.to_string();
+    let margin = " ".repeat(gutter.len());
+    rendered += &format!("{}--> {}\n", margin, lpos);
+    rendered += &format!("{} |\n", margin);
+    rendered += &format!("{} | {}\n", gutter, line);
+
+    // columns count chars like the lexer does, and tabs are kept so the carets line up with the source
+    let start = (lpos.col.max(1) - 1) as usize;
+    let len = line.chars().count();
+    let end = if rpos.line == lpos.line { (rpos.col as usize).max(start + 1) } else { len.max(start + 1) };
+    let prefix = line.chars()
+        .take(start)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    rendered += &format!("{} | {}{}\n", margin, prefix, "^".repeat(end - start));
+    rendered
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufReader, Cursor};
+    use crate::diagnostic::{render, render_at, Diagnostic};
+    use crate::lexer::{Lexer, Position};
+
+    #[test]
+    fn test_render_lex_error() {
+        let source = "fn main() {\n    x := 12ab;\n}";
+        let reader = BufReader::new(Cursor::new(source));
+        let err = Lexer::new(reader).read_tokens().unwrap_err();
+        assert_eq!(err, "Invalid int: cannot lex 12ab between line: 2, col: 10 and line: 2, col: 13");
+
+        let rendered = render(source, "cannot lex 12ab", Position { line: 2, col: 10 }, Position { line: 2, col: 13 });
+        let expected = "\
+error: cannot lex 12ab
+ --> line: 2, col: 10
+  |
+2 |     x := 12ab;
+  |          ^^^^
+";
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn test_render_at() {
+        let source = "\tx := \"abc";
+        let rendered = render_at(source, "unterminated string", Position { line: 1, col: 7 });
+        assert_eq!(rendered, "error: unterminated string\n --> line: 1, col: 7\n  |\n1 | \tx := \"abc\n  | \t     ^\n");
+
+        // a wide char before the span is still a single column
+        let rendered = render("π := ?", "unexpected ?", Position { line: 1, col: 6 }, Position { line: 1, col: 6 });
+        assert_eq!(rendered, "error: unexpected ?\n --> line: 1, col: 6\n  |\n1 | π := ?\n  |      ^\n");
+
+        // a position past the end of the source only renders the message
+        assert_eq!(render_at(source, "unexpected end", Position { line: 3, col: 1 }), "error: unexpected end\n");
+    }
+
+    #[test]
+    fn test_render_diagnostic() {
+        let source = "x := 1 <> 2;";
+        let diagnostic = Diagnostic::new("invalid token".to_string(), Some(Position { line: 1, col: 8 }), Some(Position { line: 1, col: 9 }));
+        assert_eq!(diagnostic.render(source), "error: invalid token\n --> line: 1, col: 8\n  |\n1 | x := 1 <> 2;\n  |        ^^\n");
+        assert_eq!(Diagnostic::new("no position".to_string(), None, None).render(source), "error: no position\n");
+    }
+}
@@ -2,16 +2,71 @@
 // 3/15/2024
 // Implementation of an ast walker for the interpreter
 
-use crate::node::{Const, Uop, FuncNode, Node, UnopNode, BinopNode, Bop};
+use std::cmp::Ordering;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::rc::Rc;
+use crate::bigint::BigInt;
+use crate::builtin::Builtin;
+use crate::lexer::Position;
+use crate::node::{Const, Uop, FuncNode, Node, UnopNode, BinopNode, Bop, Closure, DefFuncNode, DefMethodNode, CallNode, BlockNode, IndexNode, DefStructNode, StructNode, MemberNode, RangeNode, ImportNode, SliceNode};
 
+// the most elements or bytes repeating an array or a string can make, so a huge count fails instead of
+// exhausting memory
+const MAX_REPEAT_LEN: usize = 1 << 28;
+
+// the most bits a power of a bigint can have, for the same reason
+const MAX_BIGINT_BITS: u64 = 1 << 24;
+
+#[derive(Debug, PartialEq)]
 pub enum RunErr {
-    Type(&'static str),
-    Undefined(String),
+    Type(&'static str, Option<Position>),
+    // a variable that was read or written without being declared, and where when it is known
+    Undefined { iden: String, pos: Option<Position> },
+    // a definition the program refers to that doesn't exist
+    Missing(String),
+    Range(String),
+    // an operation on finite numbers whose result can't be represented
+    Overflow(String),
+    Arity(String),
+    Field(String),
+    Io(String),
+    // an assert in the program that didn't hold
+    Assertion(String),
 }
 
 impl RunErr {
-    fn undefined(iden: &str) -> RunErr {
-        RunErr::Undefined(format!("Undefined variable {}", iden))
+    pub fn undefined(iden: &str) -> RunErr {
+        RunErr::Undefined { iden: iden.to_string(), pos: None }
+    }
+
+    // attaches a position to an error that doesn't have one yet
+    pub fn at(self, pos: Position) -> RunErr {
+        match self {
+            RunErr::Type(msg, None) => RunErr::Type(msg, Some(pos)),
+            RunErr::Undefined { iden, pos: None } => RunErr::Undefined { iden, pos: Some(pos) },
+            err => err
+        }
+    }
+}
+
+impl Display for RunErr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RunErr::Type(msg, Some(pos)) => write!(f, "type error at line {}, col {}: {}", pos.line, pos.col, msg),
+            RunErr::Type(msg, None) => write!(f, "type error: {}", msg),
+            RunErr::Undefined { iden, .. } => write!(f, "Undefined variable {}", iden),
+            RunErr::Missing(msg) => write!(f, "{}", msg),
+            RunErr::Range(msg) => write!(f, "{}", msg),
+            RunErr::Overflow(msg) => write!(f, "{}", msg),
+            RunErr::Arity(msg) => write!(f, "{}", msg),
+            RunErr::Field(msg) => write!(f, "{}", msg),
+            RunErr::Io(msg) => write!(f, "failed to write output: {}", msg),
+            RunErr::Assertion(msg) => write!(f, "{}", msg),
+        }
     }
 }
 
@@ -22,6 +77,10 @@ pub struct Environment {
 }
 
 impl Environment {
+    pub fn new() -> Environment {
+        Environment { frames: vec![] }
+    }
+
     pub fn push(&mut self) {
         self.frames.push(vec![])
     }
@@ -31,16 +90,31 @@ impl Environment {
     }
 
     pub fn top(&mut self) -> &mut StackFrame {
-        if self.frames.len() == 0 {
+        if self.frames.is_empty() {
             self.push()
         }
         let len = self.frames.len();
         &mut self.frames[len - 1]
     }
 
+    // the bindings after a mark are dropped when it is restored, so a loop body gets a fresh scope on each
+    // iteration while the bindings before the mark can still be written to
+    pub fn mark(&mut self) -> usize {
+        self.top().len()
+    }
+
+    pub fn restore(&mut self, mark: usize) {
+        self.top().truncate(mark)
+    }
+
+    // a declaration shadows any earlier binding with the same name in the frame
+    pub fn declare(&mut self, iden: &str, constant: Const) {
+        self.top().push((iden.to_string(), constant))
+    }
+
     pub fn write(&mut self, iden: &str, constant: Const) -> Result<(), RunErr> {
         let frame = self.top();
-        for pair in frame {
+        for pair in frame.iter_mut().rev() {
             if iden == pair.0 {
                 pair.1 = constant;
                 return Ok(())
@@ -49,9 +123,10 @@ impl Environment {
         Err(RunErr::undefined(iden))
     }
 
+    // only the frame of the running call is searched, so a function never sees the locals of its caller
     pub fn read(&mut self, iden: &str) -> Result<&Const, RunErr> {
         let frame = self.top();
-        for pair in frame {
+        for pair in frame.iter().rev() {
             if iden == pair.0 {
                 return Ok(&pair.1)
             }
@@ -62,102 +137,2131 @@ impl Environment {
 
 pub type ExprResult = Result<Const, RunErr>;
 
-pub fn eval_node(node: &Node) -> ExprResult {
-    match node {
-        Node::Constant(constant) => Ok(constant.clone()),
-        Node::Variable(v) => panic!("Variable access not yet implemented"),
-        Node::Binop(node) => eval_binary_expr(node),
-        Node::Unop(node) => eval_unary_expr(node),
-        Node::CallFunc(node) => eval_func(node),
-        _ => panic!("Not yet implemented")
-    }
+// signals how control leaves a sequence of statements
+// a return leaves every loop up to the function it is in, while a break or continue is stopped by the
+// innermost loop and is an error when it reaches the function
+pub enum Flow {
+    Next,
+    Return(Const),
+    // a return of a call to a named function, which the caller makes in place of its own frame
+    TailCall(Rc<DefFuncNode>, Vec<Const>),
+    Break,
+    Continue,
+}
+
+pub struct Interpreter {
+    env: Environment,
+    funcs: Vec<(String, Rc<DefFuncNode>)>,
+    structs: Vec<DefStructNode>,
+    // keyed by the name of the struct the method is defined on
+    methods: Vec<(String, Rc<DefMethodNode>)>,
+    // the programs that can be imported, by the name they are imported with
+    modules: Vec<(String, Vec<Node>)>,
+    // the names that qualify the functions of the imported modules and the modules defined in the program
+    imports: Vec<String>,
+    // where the builtins write their output
+    out: Box<dyn Write>,
+    // the value of each expression that has a position, in the order they finish evaluating, kept only
+    // when tracing is turned on
+    trace: Option<Vec<(Position, Const)>>,
+    // whether an int that leaves the range of an i64 becomes a bigint instead of an overflow error
+    bigint: bool,
 }
 
-pub fn eval_binary_expr(node: &BinopNode) -> ExprResult {
-    let lhs = eval_node(node.lhs.as_ref())?;
-    let rhs = eval_node(node.rhs.as_ref())?;
-    match node.op {
-        Bop::Plus => match (lhs, rhs) {
-            (Const::Int(lhs), Const::Int(rhs)) => Ok(Const::Int(lhs + rhs)),
-            (Const::Float(lhs), Const::Float(rhs)) => Ok(Const::Float(lhs + rhs)),
-            (Const::String(lhs), Const::String(rhs)) => {
-                let mut s_new = lhs.to_owned();
-                s_new.push_str(&rhs);
-                Ok(Const::String(s_new))
-            }
-            _ => Err(RunErr::Type("Add operator must be applied to 2 ints, floats, or strings"))
-        },
-        Bop::Multiply => match (lhs, rhs) {
-            (Const::Int(lhs), Const::Int(rhs)) => Ok(Const::Int(lhs * rhs)),
-            (Const::Float(lhs), Const::Float(rhs)) => Ok(Const::Float(lhs * rhs)),
-            (Const::String(lhs), Const::Int(rhs)) => {
-                let mut s_new = String::new();
-                for _ in 0..rhs {
-                    s_new.push_str(&lhs)
-                }
-                Ok(Const::String(s_new))
-            }
-            _ => Err(RunErr::Type("Subtract operator must be applied to 2 ints, 2 floats, or between a string and an int"))
-        },
-        Bop::Minus => match (lhs, rhs) {
-            (Const::Int(lhs), Const::Int(rhs)) => Ok(Const::Int(lhs - rhs)),
-            (Const::Float(lhs), Const::Float(rhs)) => Ok(Const::Float(lhs - rhs)),
-            _ => Err(RunErr::Type("Subtract operator must be applied to 2 ints or 2 floats"))
-        },
-        Bop::Divide => match (lhs, rhs) {
-            (Const::Int(lhs), Const::Int(rhs)) => Ok(Const::Int(lhs / rhs)),
-            (Const::Float(lhs), Const::Float(rhs)) => Ok(Const::Float(lhs / rhs)),
-            _ => Err(RunErr::Type("Divide operator must be applied to 2 ints or 2 floats"))
-        },
-        Bop::Exp => match (lhs, rhs) {
-            (Const::Int(lhs), Const::Int(rhs)) => {
-                if rhs < 0 {
-                    Err(RunErr::Type("Exponent operator rhs must be a positive int"))
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter {
+            env: Environment::new(),
+            funcs: vec![],
+            structs: vec![],
+            methods: vec![],
+            modules: vec![],
+            imports: vec![],
+            out: Box::new(io::stdout()),
+            trace: None,
+            bigint: false,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_output(mut self, out: Box<dyn Write>) -> Interpreter {
+        self.out = out;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_trace(mut self, trace: bool) -> Interpreter {
+        self.trace = if trace { Some(vec![]) } else { None };
+        self
+    }
+
+    // the values recorded since the interpreter was created, which is empty when tracing is off
+    #[allow(dead_code)]
+    pub fn trace(&self) -> &[(Position, Const)] {
+        self.trace.as_deref().unwrap_or(&[])
+    }
+
+    #[allow(dead_code)]
+    pub fn with_bigint(mut self, bigint: bool) -> Interpreter {
+        self.bigint = bigint;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_module(mut self, iden: &str, program: Vec<Node>) -> Interpreter {
+        self.modules.push((iden.to_string(), program));
+        self
+    }
+
+    pub fn load(&mut self, program: &[Node]) {
+        for node in program {
+            match node {
+                Node::DefFunc(func) => self.funcs.push((func.iden.clone(), Rc::new(func.clone()))),
+                Node::DefStruct(def) => self.structs.push(def.clone()),
+                Node::DefMethod(method) => self.methods.push((method.recv_type.clone(), Rc::new(method.clone()))),
+                Node::Import(import) => self.load_import(import),
+                Node::Module(iden, body) => self.load_module(iden, body),
+                _ => {}
+            }
+        }
+    }
+
+    // the definitions of a module are registered qualified by its path, so a module nested in another is
+    // flattened to outer.inner
+    fn load_module(&mut self, path: &str, body: &[Node]) {
+        for node in body {
+            match node {
+                Node::DefFunc(func) => self.funcs.push((format!("{}.{}", path, func.iden), Rc::new(func.clone()))),
+                Node::DefStruct(def) => {
+                    self.structs.push(DefStructNode { iden: format!("{}.{}", path, def.iden), ..def.clone() })
+                }
+                Node::DefMethod(method) => {
+                    self.methods.push((format!("{}.{}", path, method.recv_type), Rc::new(method.clone())))
+                }
+                Node::Import(import) => self.load_import(import),
+                Node::Module(iden, body) => self.load_module(&format!("{}.{}", path, iden), body),
+                _ => {}
+            }
+        }
+        self.imports.push(path.to_string())
+    }
+
+    // the functions of a module are registered qualified by the alias, or by the module name without one,
+    // so they only resolve through a qualified call like alias.func()
+    fn load_import(&mut self, import: &ImportNode) {
+        let prefix = import.alias.as_ref().unwrap_or(&import.iden);
+        let funcs: Vec<_> = self.modules.iter()
+            .filter(|(iden, _)| iden == &import.iden)
+            .flat_map(|(_, program)| program.iter())
+            .filter_map(|node| match node {
+                Node::DefFunc(func) => Some((format!("{}.{}", prefix, func.iden), Rc::new(func.clone()))),
+                _ => None
+            })
+            .collect();
+        self.funcs.extend(funcs);
+        self.imports.push(prefix.clone())
+    }
+
+    // runs a program from its main function, an empty program is reported separately from one missing a main
+    pub fn interpret(&mut self, program: &[Node]) -> ExprResult {
+        if program.is_empty() {
+            return Err(RunErr::Missing("Program is empty, expected a main function".to_string()))
+        }
+        self.load(program);
+        match self.lookup_func("main") {
+            Some(main) => self.call_def(&main, vec![]),
+            None => Err(RunErr::Missing("Program has no main function".to_string()))
+        }
+    }
+
+    fn lookup_func(&self, iden: &str) -> Option<Rc<DefFuncNode>> {
+        self.funcs.iter()
+            .find(|(name, _)| name == iden)
+            .map(|(_, func)| func.clone())
+    }
+
+    fn lookup_method(&self, recv_type: &str, iden: &str) -> Option<Rc<DefMethodNode>> {
+        self.methods.iter()
+            .find(|(name, method)| name == recv_type && method.func.iden == iden)
+            .map(|(_, method)| method.clone())
+    }
+
+    pub fn exec_body(&mut self, body: &[Node]) -> Result<Flow, RunErr> {
+        // an else node only runs when the if node right before it didn't
+        let mut run_else = false;
+        for node in body {
+            let flow = match node {
+                Node::If(if_node) => {
+                    let cond = self.eval_cond(&if_node.cond, "If condition must be a bool")?;
+                    run_else = !cond;
+                    if cond {
+                        self.exec_scoped(&if_node.body)?
+                    } else {
+                        Flow::Next
+                    }
+                }
+                Node::Else(body) => {
+                    let flow = if run_else { self.exec_scoped(body)? } else { Flow::Next };
+                    run_else = false;
+                    flow
+                }
+                _ => {
+                    run_else = false;
+                    self.exec_stmt(node)?
+                }
+            };
+            if !matches!(flow, Flow::Next) {
+                return Ok(flow)
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    // the names an if or else body declares go out of scope when it ends, the same as a loop body
+    fn exec_scoped(&mut self, body: &[Node]) -> Result<Flow, RunErr> {
+        let mark = self.env.mark();
+        let flow = self.exec_body(body);
+        self.env.restore(mark);
+        flow
+    }
+
+    fn exec_return(&mut self, expr: &Node) -> Result<Flow, RunErr> {
+        if let Node::CallFunc(call) = expr {
+            match self.eval_variable(&call.iden)? {
+                Const::Func(func) => return Ok(Flow::TailCall(func, self.eval_args(&call.args)?)),
+                callee => {
+                    let args = self.eval_args(&call.args)?;
+                    return Ok(Flow::Return(self.call_value(callee, args)?))
+                }
+            }
+        }
+        Ok(Flow::Return(self.eval_node(expr)?))
+    }
+
+    fn exec_stmt(&mut self, node: &Node) -> Result<Flow, RunErr> {
+        match node {
+            Node::Return(expr) => self.exec_return(expr),
+            // a guard whose condition holds returns exactly like a return statement
+            Node::Guard(guard) => {
+                if self.eval_cond(&guard.cond, "Guard condition must be a bool")? {
+                    self.exec_return(&guard.this)
                 } else {
-                    Ok(Const::Int(lhs.pow(rhs as u32)))
+                    Ok(Flow::Next)
+                }
+            }
+            Node::While(node) => {
+                while self.eval_cond(&node.cond, "While condition must be a bool")? {
+                    let mark = self.env.mark();
+                    let flow = self.exec_body(&node.body);
+                    self.env.restore(mark);
+                    match flow? {
+                        flow @ (Flow::Return(_) | Flow::TailCall(_, _)) => return Ok(flow),
+                        Flow::Break => break,
+                        Flow::Next | Flow::Continue => {}
+                    }
+                }
+                Ok(Flow::Next)
+            }
+            Node::For(node) => {
+                // a range is iterated without building the list of its elements
+                let elements: Box<dyn Iterator<Item = Const>> = match self.eval_node(&node.collection)? {
+                    Const::Range(start, end, true) => Box::new((start..=end).map(Const::Int)),
+                    Const::Range(start, end, false) => Box::new((start..end).map(Const::Int)),
+                    collection => Box::new(Self::iterate(collection)?.into_iter())
+                };
+                for (i, element) in elements.enumerate() {
+                    let mark = self.env.mark();
+                    self.bind(&node.element, element);
+                    if let Some(index) = &node.index {
+                        self.bind(index, Const::Int(i as i64));
+                    }
+                    let flow = self.exec_body(&node.body);
+                    self.env.restore(mark);
+                    match flow? {
+                        flow @ (Flow::Return(_) | Flow::TailCall(_, _)) => return Ok(flow),
+                        Flow::Break => break,
+                        // a continue moves on to the next element and index rather than running the body again
+                        Flow::Next | Flow::Continue => {}
+                    }
+                }
+                Ok(Flow::Next)
+            }
+            Node::Break => Ok(Flow::Break),
+            Node::Continue => Ok(Flow::Continue),
+            // a nested function is only visible in the scope it is defined in
+            Node::DefFunc(func) => {
+                self.env.declare(&func.iden, Const::Func(Rc::new(func.clone())));
+                Ok(Flow::Next)
+            }
+            // a const is only enforced by the checker
+            Node::Declare(iden, expr) | Node::DeclareConst(iden, expr) => {
+                let constant = self.eval_node(expr)?;
+                self.bind(iden, constant);
+                Ok(Flow::Next)
+            }
+            Node::DeclareMany(idens, expr) => match self.eval_node(expr)? {
+                Const::Tuple(elems) if elems.len() == idens.len() => {
+                    for (iden, elem) in idens.iter().zip(elems) {
+                        self.bind(iden, elem)
+                    }
+                    Ok(Flow::Next)
+                }
+                Const::Tuple(elems) => {
+                    Err(RunErr::Arity(format!("Cannot declare {} names from a tuple of {} elements", idens.len(), elems.len())))
                 }
+                _ => Err(RunErr::Type("Only a tuple can be declared into several names", None))
             },
-            _ => Err(RunErr::Type("Exponent operator must be applied to an int and a positive int"))
-        },
-        Bop::Eq => Ok(Const::Bool(lhs == rhs)),
-        Bop::Neq => Ok(Const::Bool(lhs != rhs)),
-        Bop::Leq => Ok(Const::Bool(lhs <= rhs)),
-        Bop::Geq => Ok(Const::Bool(lhs >= rhs)),
-        Bop::Lt => Ok(Const::Bool(lhs < rhs)),
-        Bop::Gt => Ok(Const::Bool(lhs > rhs)),
-        Bop::And => match (lhs, rhs) {
-            (Const::Bool(lhs), Const::Bool(rhs)) => Ok(Const::Bool(lhs && rhs)),
-            _ => Err(RunErr::Type("And operator must be applied to 2 bools"))
-        },
-        Bop::Or => match (lhs, rhs) {
-            (Const::Bool(lhs), Const::Bool(rhs)) => Ok(Const::Bool(lhs && rhs)),
-            _ => Err(RunErr::Type("Or operator must be applied to 2 bools"))
-        },
+            Node::Assign(iden, expr) => {
+                let constant = self.eval_node(expr)?;
+                self.env.write(iden, constant)?;
+                Ok(Flow::Next)
+            }
+            Node::Store(node) => {
+                let constant = self.eval_node(&node.value)?;
+                self.store(&node.target, constant)?;
+                Ok(Flow::Next)
+            }
+            _ => {
+                self.eval_node(node)?;
+                Ok(Flow::Next)
+            }
+        }
     }
-}
-pub fn eval_unary_expr(node: &UnopNode) -> ExprResult {
-    match node.op {
-        Uop::Not => match eval_node(node.expr.as_ref())? {
-            Const::Bool(b) => Ok(Const::Bool(b)),
-            _ => Err(RunErr::Type("Not operator must be applied to a bool"))
+
+    // values are copied when read, so storing into an element or field rebuilds the value it belongs to and writes it back
+    fn store(&mut self, target: &Node, constant: Const) -> Result<(), RunErr> {
+        match target {
+            Node::Variable(iden) => self.env.write(iden, constant),
+            Node::Index(node) => {
+                let base = self.eval_node(&node.base)?;
+                let index = self.eval_node(&node.index)?;
+                let base = match (base, index) {
+                    (Const::Array(mut elems), Const::Int(i)) => {
+                        let len = elems.len();
+                        match usize::try_from(i).ok().and_then(|i| elems.get_mut(i)) {
+                            Some(elem) => *elem = constant,
+                            None => return Err(RunErr::Range(format!("Index {} out of range for array of length {}", i, len)))
+                        }
+                        Const::Array(elems)
+                    }
+                    _ => return Err(RunErr::Type("Index assignment must be applied to an array and an int", Some(node.pos)))
+                };
+                self.store(&node.base, base)
+            }
+            Node::Member(node) => {
+                let base = match self.eval_node(&node.base)? {
+                    Const::Struct(iden, mut fields) => {
+                        match fields.iter_mut().find(|(field, _)| field == &node.field) {
+                            Some((_, value)) => *value = constant,
+                            None => return Err(RunErr::Field(format!("Struct {} has no field {}", iden, node.field)))
+                        }
+                        Const::Struct(iden, fields)
+                    }
+                    _ => return Err(RunErr::Type("Member assignment must be applied to a struct", Some(node.pos)))
+                };
+                self.store(&node.base, base)
+            }
+            _ => Err(RunErr::Type("Only variables, indexes, and members can be assigned to", None))
+        }
+    }
+
+    // the '_' name throws away the value instead of binding it
+    fn bind(&mut self, iden: &str, constant: Const) {
+        if iden != "_" {
+            self.env.declare(iden, constant)
+        }
+    }
+
+    fn iterate(collection: Const) -> Result<Vec<Const>, RunErr> {
+        match collection {
+            Const::String(str) => Ok(str.chars().map(Const::Char).collect()),
+            Const::Array(elems) => Ok(elems),
+            _ => Err(RunErr::Type("For loop must iterate over a string, an array or a range", None))
+        }
+    }
+
+    fn eval_range(&mut self, node: &RangeNode) -> ExprResult {
+        match (self.eval_node(&node.start)?, self.eval_node(&node.end)?) {
+            (Const::Int(start), Const::Int(end)) => Ok(Const::Range(start, end, node.inclusive)),
+            _ => Err(RunErr::Type("Range bounds must be ints", Some(node.pos)))
+        }
+    }
+
+    fn eval_cond(&mut self, cond: &Node, err: &'static str) -> Result<bool, RunErr> {
+        match self.eval_node(cond)? {
+            Const::Bool(b) => Ok(b),
+            _ => Err(RunErr::Type(err, None))
+        }
+    }
+
+    pub fn eval_node(&mut self, node: &Node) -> ExprResult {
+        let result = self.eval_expr(node);
+        if let (Some(trace), Ok(value), Some(pos)) = (&mut self.trace, &result, node.pos()) {
+            trace.push((pos, value.clone()))
+        }
+        result
+    }
+
+    fn eval_expr(&mut self, node: &Node) -> ExprResult {
+        match node {
+            Node::Constant(constant) => Ok(constant.clone()),
+            Node::Variable(iden) => self.eval_variable(iden),
+            Node::Binop(node) => self.eval_binary_expr(node),
+            Node::Unop(node) => self.eval_unary_expr(node),
+            Node::CallFunc(node) => self.eval_func(node),
+            Node::Call(node) => self.eval_call(node),
+            Node::Index(node) => self.eval_index(node),
+            Node::Slice(node) => self.eval_slice(node),
+            Node::Member(node) => self.eval_member(node),
+            Node::Array(elems) => Ok(Const::Array(self.eval_args(elems)?)),
+            Node::Lambda(lambda) => {
+                let env = self.env.top().clone();
+                let closure = Closure { lambda: Rc::new(lambda.clone()), env };
+                Ok(Const::Closure(closure))
+            }
+            Node::Block(block) => self.eval_block(block),
+            Node::Tuple(elems) => Ok(Const::Tuple(self.eval_args(elems)?)),
+            Node::Struct(node) => self.eval_struct(node),
+            Node::Range(node) => self.eval_range(node),
+            Node::Ternary(node) => {
+                let cond = self.eval_cond(&node.cond, "Ternary condition must be a bool")?;
+                self.eval_node(if cond { &node.then } else { &node.otherwise })
+            }
+            _ => panic!("Not yet implemented")
+        }
+    }
+
+    // an explicit return ends the block early with its value, otherwise the block has the value of its trailing expression
+    pub fn eval_block(&mut self, block: &BlockNode) -> ExprResult {
+        match self.exec_body(&block.body)? {
+            Flow::Return(result) => Ok(result),
+            Flow::TailCall(func, args) => self.call_def(&func, args),
+            Flow::Next => match &block.value {
+                Some(value) => self.eval_node(value),
+                None => Err(RunErr::Type("Block ended without a value", None))
+            }
+            Flow::Break | Flow::Continue => Err(RunErr::Type("Break and continue must be used within a loop", None))
+        }
+    }
+
+    // every declared field must be given exactly once, and the fields are stored in declaration order
+    pub fn eval_struct(&mut self, node: &StructNode) -> ExprResult {
+        let def = match self.structs.iter().find(|def| def.iden == node.iden) {
+            Some(def) => def.clone(),
+            None => return Err(RunErr::Missing(format!("Undefined struct {}", node.iden)))
+        };
+        for (i, (field, _)) in node.fields.iter().enumerate() {
+            if !def.fields.iter().any(|(name, _)| name == field) {
+                return Err(RunErr::Field(format!("Struct {} has no field {}", def.iden, field)))
+            }
+            if node.fields[..i].iter().any(|(name, _)| name == field) {
+                return Err(RunErr::Field(format!("Field {} of struct {} is given more than once", field, def.iden)))
+            }
+        }
+
+        if let Some((name, _)) = def.fields.iter().find(|(name, _)| !node.fields.iter().any(|(field, _)| field == name)) {
+            return Err(RunErr::Field(format!("Struct {} is missing field {}", def.iden, name)))
+        }
+
+        // values are evaluated in the order they are written
+        let mut values = vec![];
+        for (field, value) in &node.fields {
+            values.push((field.clone(), self.eval_node(value)?))
+        }
+        let fields = def.fields.iter()
+            .filter_map(|(name, _)| values.iter().find(|(field, _)| field == name).cloned())
+            .collect();
+        Ok(Const::Struct(def.iden, fields))
+    }
+
+    // strings are indexed by unicode scalar, and a negative index is always out of range
+    pub fn eval_index(&mut self, node: &IndexNode) -> ExprResult {
+        let base = self.eval_node(&node.base)?;
+        let index = self.eval_node(&node.index)?;
+        match (base, index) {
+            (Const::String(str), Const::Int(i)) => usize::try_from(i).ok()
+                .and_then(|i| str.chars().nth(i))
+                .map(Const::Char)
+                .ok_or_else(|| RunErr::Range(format!("Index {} out of range for string of length {}", i, str.chars().count()))),
+            (Const::Array(elems), Const::Int(i)) => usize::try_from(i).ok()
+                .and_then(|i| elems.get(i).cloned())
+                .ok_or_else(|| RunErr::Range(format!("Index {} out of range for array of length {}", i, elems.len()))),
+            _ => Err(RunErr::Type("Index operator must be applied to a string or an array and an int", Some(node.pos)))
+        }
+    }
+
+    // the bounds count chars of a string like an index does, and the sliced part of the base is copied
+    pub fn eval_slice(&mut self, node: &SliceNode) -> ExprResult {
+        let base = self.eval_node(&node.base)?;
+        let type_err = || RunErr::Type("Slice operator must be applied to a string or an array", Some(node.pos));
+        let (kind, len) = match &base {
+            Const::String(str) => ("string", str.chars().count()),
+            Const::Array(elems) => ("array", elems.len()),
+            _ => return Err(type_err())
+        };
+        let mut bound = |bound: &Option<Box<Node>>, default: i64| match bound {
+            Some(bound) => match self.eval_node(bound)? {
+                Const::Int(i) => Ok(i),
+                _ => Err(RunErr::Type("Slice bounds must be ints", Some(node.pos)))
+            },
+            None => Ok(default)
+        };
+        let start = bound(&node.start, 0)?;
+        let end = bound(&node.end, len as i64)?;
+        let range = format!("{}..{}{}", start, if node.inclusive { "=" } else { "" }, end);
+        let end = if node.inclusive { end.checked_add(1) } else { Some(end) };
+        let (start, end) = match (usize::try_from(start), end.map(usize::try_from)) {
+            (Ok(start), Some(Ok(end))) if start <= end && end <= len => (start, end),
+            _ => return Err(RunErr::Range(format!("Slice {} out of range for {} of length {}", range, kind, len)))
+        };
+        match base {
+            Const::String(str) => Ok(Const::String(str.chars().skip(start).take(end - start).collect())),
+            Const::Array(elems) => Ok(Const::Array(elems[start..end].to_vec())),
+            _ => Err(type_err())
+        }
+    }
+
+    pub fn eval_member(&mut self, node: &MemberNode) -> ExprResult {
+        let base = self.eval_node(&node.base)?;
+        Self::member(base, node)
+    }
+
+    fn member(base: Const, node: &MemberNode) -> ExprResult {
+        match base {
+            Const::Struct(iden, fields) => fields.into_iter()
+                .find(|(field, _)| field == &node.field)
+                .map(|(_, value)| value)
+                .ok_or_else(|| RunErr::Field(format!("Struct {} has no field {}", iden, node.field))),
+            _ => Err(RunErr::Type("Member operator must be applied to a struct", Some(node.pos)))
+        }
+    }
+
+    pub fn eval_binary_expr(&mut self, node: &BinopNode) -> ExprResult {
+        let lhs = self.eval_node(node.lhs.as_ref())?;
+        if let Bop::And | Bop::Or = node.op {
+            return self.eval_logical_expr(node, lhs)
+        }
+        let rhs = self.eval_node(node.rhs.as_ref())?;
+        let result = if self.bigint { Self::bigint_op(&node.op, lhs, rhs) } else { Self::binary_op(&node.op, lhs, rhs) };
+        result.map_err(|err| err.at(node.pos))
+    }
+
+    // the arithmetic on ints stays on i64 until it would overflow or an operand is already a bigint, and a
+    // result that fits in an i64 is an int again, so both kinds compare equal when they hold the same value
+    fn bigint_op(op: &Bop, lhs: Const, rhs: Const) -> ExprResult {
+        if !matches!(op, Bop::Plus | Bop::Minus | Bop::Multiply | Bop::Divide | Bop::Mod | Bop::Exp) {
+            return Self::binary_op(op, lhs, rhs)
+        }
+        let (l, r) = match (&lhs, &rhs) {
+            // the quotient of the minimum int and -1 is the only division that overflows
+            (Const::Int(l), Const::Int(r)) if matches!(op, Bop::Divide | Bop::Mod) && *l == i64::MIN && *r == -1 => {
+                (BigInt::from(*l), BigInt::from(*r))
+            }
+            (Const::Int(l), Const::Int(r)) => match Self::binary_op(op, lhs.clone(), rhs.clone()) {
+                Err(RunErr::Overflow(_)) => (BigInt::from(*l), BigInt::from(*r)),
+                result => return result
+            },
+            (Const::BigInt(l), Const::Int(r)) => (l.clone(), BigInt::from(*r)),
+            (Const::Int(l), Const::BigInt(r)) => (BigInt::from(*l), r.clone()),
+            (Const::BigInt(l), Const::BigInt(r)) => (l.clone(), r.clone()),
+            _ => return Self::binary_op(op, lhs, rhs)
+        };
+        let result = match op {
+            Bop::Plus => l.add(&r),
+            Bop::Minus => l.sub(&r),
+            Bop::Multiply => l.mul(&r),
+            Bop::Divide | Bop::Mod => match l.div_rem(&r) {
+                Some((quot, _)) if *op == Bop::Divide => quot,
+                Some((_, rem)) => rem,
+                None => return Err(RunErr::Range(format!("Cannot divide {} by zero", l)))
+            },
+            _ => match r.to_i64().and_then(|exp| u32::try_from(exp).ok()) {
+                Some(exp) if l.bits() as u64 * exp as u64 > MAX_BIGINT_BITS => {
+                    return Err(RunErr::Overflow(format!("{} ** {} is too large for a bigint", l, r)))
+                }
+                Some(exp) => l.pow(exp),
+                None => return Err(RunErr::Type("Exponent operator rhs must be a positive int", None))
+            }
+        };
+        Ok(Self::int_or_bigint(result))
+    }
+
+    fn int_or_bigint(n: BigInt) -> Const {
+        match n.to_i64() {
+            Some(n) => Const::Int(n),
+            None => Const::BigInt(n)
+        }
+    }
+
+    // the lhs must be a bool before deciding to short circuit, the rhs is only checked if it is evaluated
+    fn eval_logical_expr(&mut self, node: &BinopNode, lhs: Const) -> ExprResult {
+        let (short, err) = match node.op {
+            Bop::And => (false, "And operator must be applied to 2 bools"),
+            _ => (true, "Or operator must be applied to 2 bools"),
+        };
+        match lhs {
+            Const::Bool(lhs) if lhs == short => Ok(Const::Bool(short)),
+            Const::Bool(_) => match self.eval_node(node.rhs.as_ref())? {
+                Const::Bool(rhs) => Ok(Const::Bool(rhs)),
+                _ => Err(RunErr::Type(err, Some(node.pos)))
+            }
+            _ => Err(RunErr::Type(err, Some(node.pos)))
+        }
+    }
+
+    // arrays hold a single type of element, an empty array can be joined with any other
+    fn same_elem_type(lhs: &[Const], rhs: &[Const]) -> bool {
+        match (lhs.first(), rhs.first()) {
+            (Some(Const::Struct(lhs, _)), Some(Const::Struct(rhs, _))) => lhs == rhs,
+            (Some(lhs), Some(rhs)) => mem::discriminant(lhs) == mem::discriminant(rhs),
+            _ => true
+        }
+    }
+
+    // strings are ordered by comparing their unicode scalar values one at a time, so every uppercase ascii
+    // letter comes before every lowercase one and an accented letter comes after all of ascii. values that
+    // have no ordering, like an int and a string, are neither less nor greater than each other
+    fn compare(lhs: &Const, rhs: &Const) -> Option<Ordering> {
+        match (lhs, rhs) {
+            (Const::String(lhs), Const::String(rhs)) => Some(lhs.chars().cmp(rhs.chars())),
+            _ => lhs.partial_cmp(rhs)
+        }
+    }
+
+    pub fn binary_op(op: &Bop, lhs: Const, rhs: Const) -> ExprResult {
+        match op {
+            Bop::Plus => match (lhs, rhs) {
+                (Const::Int(lhs), Const::Int(rhs)) => Self::checked_int(lhs.checked_add(rhs), lhs, "+", rhs),
+                (Const::Float(lhs), Const::Float(rhs)) => Ok(Const::Float(lhs + rhs)),
+                (Const::String(lhs), Const::String(rhs)) => {
+                    let mut s_new = lhs.to_owned();
+                    s_new.push_str(&rhs);
+                    Ok(Const::String(s_new))
+                }
+                (Const::Array(mut lhs), Const::Array(rhs)) => {
+                    if !Self::same_elem_type(&lhs, &rhs) {
+                        return Err(RunErr::Type("Add operator must be applied to 2 arrays with the same element type", None))
+                    }
+                    lhs.extend(rhs);
+                    Ok(Const::Array(lhs))
+                }
+                _ => Err(RunErr::Type("Add operator must be applied to 2 ints, floats, strings or arrays", None))
+            },
+            Bop::Multiply => match (lhs, rhs) {
+                (Const::Int(lhs), Const::Int(rhs)) => Self::checked_int(lhs.checked_mul(rhs), lhs, "*", rhs),
+                (Const::Float(lhs), Const::Float(rhs)) => Ok(Const::Float(lhs * rhs)),
+                (Const::String(lhs), Const::Int(rhs)) => {
+                    let count = Self::repeat_count(lhs.len(), rhs, "a string")?;
+                    Ok(Const::String(lhs.repeat(count)))
+                }
+                // repeats the array, a count that isn't positive gives an empty array
+                (Const::Array(lhs), Const::Int(rhs)) => {
+                    let count = Self::repeat_count(lhs.len(), rhs, "an array")?;
+                    Ok(Const::Array(lhs.iter().cloned().cycle().take(lhs.len() * count).collect()))
+                }
+                _ => Err(RunErr::Type("Multiply operator must be applied to 2 ints, 2 floats, or between a string or an array and an int", None))
+            },
+            Bop::Minus => match (lhs, rhs) {
+                (Const::Int(lhs), Const::Int(rhs)) => Self::checked_int(lhs.checked_sub(rhs), lhs, "-", rhs),
+                (Const::Float(lhs), Const::Float(rhs)) => Ok(Const::Float(lhs - rhs)),
+                _ => Err(RunErr::Type("Subtract operator must be applied to 2 ints or 2 floats", None))
+            },
+            Bop::Divide => match (lhs, rhs) {
+                (Const::Int(lhs), Const::Int(rhs)) => Ok(Const::Int(Self::checked_int_div(lhs, rhs)?)),
+                (Const::Float(lhs), Const::Float(rhs)) => Ok(Const::Float(lhs / rhs)),
+                _ => Err(RunErr::Type("Divide operator must be applied to 2 ints or 2 floats", None))
+            },
+            Bop::Mod => match (lhs, rhs) {
+                (Const::Int(lhs), Const::Int(rhs)) => Ok(Const::Int(Self::checked_int_rem(lhs, rhs)?)),
+                (Const::Float(lhs), Const::Float(rhs)) => Ok(Const::Float(lhs % rhs)),
+                _ => Err(RunErr::Type("Modulo operator must be applied to 2 ints or 2 floats", None))
+            },
+            Bop::Exp => match (lhs, rhs) {
+                (Const::Int(lhs), Const::Int(rhs)) => match u32::try_from(rhs) {
+                    Ok(exp) => Self::checked_int(lhs.checked_pow(exp), lhs, "**", rhs),
+                    Err(_) => Err(RunErr::Type("Exponent operator rhs must be a positive int", None))
+                },
+                // powf of finite floats gives inf when the result is too large and nan when it isn't real
+                (Const::Float(lhs), Const::Float(rhs)) => match lhs.powf(rhs) {
+                    result if result.is_finite() || !lhs.is_finite() || !rhs.is_finite() => Ok(Const::Float(result)),
+                    _ => Err(RunErr::Overflow(format!("{:?} ** {:?} is not a finite float", lhs, rhs)))
+                },
+                _ => Err(RunErr::Type("Exponent operator must be applied to 2 floats, or an int and a positive int", None))
+            },
+            Bop::Eq => Ok(Const::Bool(lhs == rhs)),
+            Bop::Neq => Ok(Const::Bool(lhs != rhs)),
+            Bop::Leq | Bop::Geq | Bop::Lt | Bop::Gt => {
+                let ordering = Self::compare(&lhs, &rhs);
+                Ok(Const::Bool(match op {
+                    Bop::Leq => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                    Bop::Geq => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+                    Bop::Lt => ordering == Some(Ordering::Less),
+                    _ => ordering == Some(Ordering::Greater)
+                }))
+            }
+            Bop::And => match (lhs, rhs) {
+                (Const::Bool(lhs), Const::Bool(rhs)) => Ok(Const::Bool(lhs && rhs)),
+                _ => Err(RunErr::Type("And operator must be applied to 2 bools", None))
+            },
+            Bop::Or => match (lhs, rhs) {
+                (Const::Bool(lhs), Const::Bool(rhs)) => Ok(Const::Bool(lhs || rhs)),
+                _ => Err(RunErr::Type("Or operator must be applied to 2 bools", None))
+            },
+        }
+    }
+
+    pub fn eval_unary_expr(&mut self, node: &UnopNode) -> ExprResult {
+        let expr = self.eval_node(node.expr.as_ref())?;
+        match (&node.op, expr) {
+            (Uop::Minus, Const::Int(i64::MIN)) if self.bigint => Ok(Const::BigInt(BigInt::from(i64::MIN).neg())),
+            (Uop::Minus, Const::BigInt(n)) => Ok(Self::int_or_bigint(n.neg())),
+            (op, expr) => Self::unary_op(op, expr).map_err(|err| err.at(node.pos))
+        }
+    }
+
+    // a count that isn't positive repeats nothing, and a repeat longer than the limit is an error
+    fn repeat_count(len: usize, count: i64, kind: &str) -> Result<usize, RunErr> {
+        let count = usize::try_from(count).unwrap_or(0);
+        match len.checked_mul(count) {
+            Some(total) if total <= MAX_REPEAT_LEN => Ok(count),
+            _ => Err(RunErr::Range(format!("Repeating {} of length {} {} times is too large", kind, len, count)))
+        }
+    }
+
+    // an int operation whose result doesn't fit in an i64 is an error instead of wrapping around
+    fn checked_int(result: Option<i64>, lhs: i64, op: &str, rhs: i64) -> ExprResult {
+        result.map(Const::Int).ok_or_else(|| RunErr::Overflow(format!("{} {} {} is out of range for an int", lhs, op, rhs)))
+    }
+
+    // integer division and remainder share the same errors for a zero divisor and for the quotient that overflows
+    fn check_divisor(lhs: i64, rhs: i64) -> Result<(), RunErr> {
+        if rhs == 0 {
+            Err(RunErr::Range(format!("Cannot divide {} by zero", lhs)))
+        } else if lhs == i64::MIN && rhs == -1 {
+            Err(RunErr::Range(format!("Dividing {} by {} overflows an int", lhs, rhs)))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn checked_int_div(lhs: i64, rhs: i64) -> Result<i64, RunErr> {
+        Self::check_divisor(lhs, rhs)?;
+        Ok(lhs / rhs)
+    }
+
+    fn checked_int_rem(lhs: i64, rhs: i64) -> Result<i64, RunErr> {
+        Self::check_divisor(lhs, rhs)?;
+        Ok(lhs % rhs)
+    }
+
+    pub fn unary_op(op: &Uop, expr: Const) -> ExprResult {
+        match op {
+            Uop::Not => match expr {
+                Const::Bool(b) => Ok(Const::Bool(!b)),
+                _ => Err(RunErr::Type("Not operator must be applied to a bool", None))
+            }
+            Uop::Minus => match expr {
+                // the minimum int has no positive counterpart in an i64
+                Const::Int(n) => n.checked_neg()
+                    .map(Const::Int)
+                    .ok_or_else(|| RunErr::Overflow(format!("-({}) is out of range for an int", n))),
+                Const::Float(n) => Ok(Const::Float(-n)),
+                _ => Err(RunErr::Type("Unary minus must be applied to an int or a float", None))
+            }
+        }
+    }
+
+    // a name that isn't a variable may still refer to a named function, or else a builtin
+    pub fn eval_variable(&mut self, iden: &str) -> ExprResult {
+        match self.env.read(iden) {
+            Ok(constant) => Ok(constant.clone()),
+            Err(err) => self.lookup_func(iden)
+                .map(Const::Func)
+                .or_else(|| Builtin::lookup(iden).map(Const::Builtin))
+                .ok_or(err)
         }
-        Uop::Minus => match eval_node(node.expr.as_ref())? {
-            Const::Int(n) => Ok(Const::Int(-n)),
-            Const::Float(n) => Ok(Const::Float(-n)),
-            _ => Err(RunErr::Type("Unary minus must be applied to an int or a float"))
+    }
+
+    fn eval_args(&mut self, args: &[Node]) -> Result<Vec<Const>, RunErr> {
+        let mut results = vec![];
+        for arg in args.iter() {
+            results.push(self.eval_node(arg)?)
+        }
+        Ok(results)
+    }
+
+    pub fn eval_func(&mut self, func: &FuncNode) -> ExprResult {
+        let callee = self.eval_variable(&func.iden)?;
+        let args = self.eval_args(&func.args)?;
+        self.call_value(callee, args)
+    }
+
+    pub fn eval_call(&mut self, call: &CallNode) -> ExprResult {
+        if let Node::Member(member) = call.callee.as_ref() {
+            return self.eval_member_call(member, &call.args)
+        }
+        let callee = self.eval_node(&call.callee)?;
+        let args = self.eval_args(&call.args)?;
+        self.call_value(callee, args)
+    }
+
+    // the path of the module a node names, unless a variable shadows the outermost module
+    fn module_path(&mut self, node: &Node) -> Option<String> {
+        let path = match node {
+            Node::Variable(iden) if self.env.read(iden).is_err() => iden.clone(),
+            Node::Member(member) => format!("{}.{}", self.module_path(&member.base)?, member.field),
+            _ => return None
+        };
+        self.imports.contains(&path).then_some(path)
+    }
+
+    // a field holding a function is called like any other value, otherwise the member names a method of the struct
+    fn eval_member_call(&mut self, member: &MemberNode, args: &[Node]) -> ExprResult {
+        // the dot after the path of a module separates the module from its function
+        if let Some(path) = self.module_path(&member.base) {
+            let func = self.lookup_func(&format!("{}.{}", path, member.field))
+                .ok_or_else(|| RunErr::Missing(format!("Module {} has no function {}", path, member.field)))?;
+            let args = self.eval_args(args)?;
+            return self.call_def(&func, args)
+        }
+        let base = self.eval_node(&member.base)?;
+        let method = match &base {
+            Const::Struct(iden, fields) if !fields.iter().any(|(field, _)| field == &member.field) => {
+                let method = self.lookup_method(iden, &member.field)
+                    .ok_or_else(|| RunErr::Field(format!("Struct {} has no field or method {}", iden, member.field)))?;
+                Some(method)
+            }
+            _ => None
+        };
+        let args = self.eval_args(args)?;
+        match method {
+            Some(method) => self.call_method(&method, base, args),
+            None => {
+                let callee = Self::member(base, member)?;
+                self.call_value(callee, args)
+            }
+        }
+    }
+
+    pub fn call_value(&mut self, callee: Const, args: Vec<Const>) -> ExprResult {
+        match callee {
+            Const::Closure(closure) => self.call_closure(&closure, args),
+            Const::Func(func) => self.call_def(&func, args),
+            Const::Builtin(builtin) => builtin.call(args, self.out.as_mut()),
+            _ => Err(RunErr::Type("Only functions and closures can be called", None))
+        }
+    }
+
+    fn bind_args(callee: &str, params: Vec<String>, args: Vec<Const>) -> Result<StackFrame, RunErr> {
+        if params.len() != args.len() {
+            let noun = if params.len() == 1 { "argument" } else { "arguments" };
+            return Err(RunErr::Arity(format!("{} expects {} {}, got {}", callee, params.len(), noun, args.len())))
+        }
+        Ok(params.into_iter().zip(args).collect())
+    }
+
+    // functions don't capture the scope they are defined in, but can always call themselves
+    // a tail call replaces the frame of the function making it, so recursing in tail position doesn't grow the stack
+    fn call_def(&mut self, func: &Rc<DefFuncNode>, args: Vec<Const>) -> ExprResult {
+        let (mut func, mut args) = (func.clone(), args);
+        loop {
+            let params = func.args.iter().map(|(iden, _)| iden.clone()).collect();
+            let mut frame = vec![(func.iden.clone(), Const::Func(func.clone()))];
+            frame.extend(Self::bind_args(&format!("Function `{}`", func.iden), params, args)?);
+            match self.run_frame(frame, &func.body)? {
+                Flow::TailCall(next, next_args) => {
+                    func = next;
+                    args = next_args
+                }
+                flow => return self.returned(flow, &func)
+            }
+        }
+    }
+
+    // the receiver is a copy of the struct, so a method can't modify the value it was called on
+    fn call_method(&mut self, method: &Rc<DefMethodNode>, recv: Const, args: Vec<Const>) -> ExprResult {
+        let func = &method.func;
+        let params = func.args.iter().map(|(iden, _)| iden.clone()).collect();
+        let mut frame = vec![(method.recv.clone(), recv)];
+        frame.extend(Self::bind_args(&format!("Method `{}.{}`", method.recv_type, func.iden), params, args)?);
+        let flow = self.run_frame(frame, &func.body)?;
+        self.returned(flow, func)
+    }
+
+    fn run_frame(&mut self, frame: StackFrame, body: &[Node]) -> Result<Flow, RunErr> {
+        self.env.frames.push(frame);
+        let flow = self.exec_body(body);
+        self.env.pop();
+        flow
+    }
+
+    // a function without a return type gives the empty tuple when it ends without returning
+    fn returned(&mut self, flow: Flow, func: &DefFuncNode) -> ExprResult {
+        match flow {
+            Flow::Return(result) => Ok(result),
+            Flow::TailCall(func, args) => self.call_def(&func, args),
+            Flow::Next if func.ret.is_none() => Ok(Const::Tuple(vec![])),
+            Flow::Next => Err(RunErr::Type("Function ended without returning a value", None)),
+            Flow::Break | Flow::Continue => Err(RunErr::Type("Break and continue must be used within a loop", None))
         }
     }
+
+    fn call_closure(&mut self, closure: &Closure, args: Vec<Const>) -> ExprResult {
+        let params = closure.lambda.args.iter().map(|(iden, _)| iden.clone()).collect();
+        // parameters come last so they shadow any captured variable of the same name
+        let mut frame = closure.env.clone();
+        frame.extend(Self::bind_args("Lambda", params, args)?);
+
+        self.env.frames.push(frame);
+        let result = self.eval_node(&closure.lambda.body);
+        self.env.pop();
+        result
+    }
 }
 
-pub fn eval_func(func: &FuncNode) -> Result<Const, RunErr> {
-    let mut results = vec![];
-    for arg in func.args.iter() {
-        match eval_node(&arg) {
-            Ok(result) => results.push(result),
-            Err(err) => {
-                return Err(err)
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::io;
+    use std::io::{BufReader, Cursor, Write};
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+    use crate::interpreter::{Environment, ExprResult, Interpreter, RunErr};
+    use crate::lexer::{Lexer, Position};
+    use crate::parser::Parser;
+    use crate::node::{BinopNode, Bop, CallNode, Const, DefFuncNode, FuncNode, LambdaNode, Node, TypeNode, Uop};
+    use crate::node::Const::Int;
+    use crate::node::Node::{Binop, Call, CallFunc, Constant, DefFunc, Lambda, Return, Variable};
+
+    const POS: Position = Position { line: 0, col: 0 };
+
+    fn parse(program: &str) -> Vec<Node> {
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+        Parser::new(tokens).parse_program().unwrap()
+    }
+
+    fn load_program(program: &str) -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        interpreter.load(&parse(program));
+        interpreter
+    }
+
+    fn interpret(program: &str) -> ExprResult {
+        Interpreter::new().interpret(&parse(program))
+    }
+
+    fn call(interpreter: &mut Interpreter, iden: &str, args: Vec<Const>) -> ExprResult {
+        let args = args.into_iter().map(Constant).collect();
+        interpreter.eval_node(&CallFunc(FuncNode { iden: iden.to_string(), args }))
+    }
+
+    #[test]
+    fn test_closure_capture() {
+        let mut interpreter = Interpreter::new();
+
+        interpreter.env.push();
+        interpreter.env.top().push(("n".to_string(), Int(5)));
+        let lambda = Lambda(LambdaNode {
+            args: vec![("x".to_string(), None)],
+            body: Box::new(Binop(BinopNode {
+                op: Bop::Plus,
+                lhs: Box::new(Variable("n".to_string())),
+                rhs: Box::new(Variable("x".to_string())),
+                pos: POS,
+            })),
+        });
+        let closure = interpreter.eval_node(&lambda).unwrap();
+        interpreter.env.pop();
+
+        // the defining scope is gone, but the closure still sees n
+        interpreter.env.push();
+        interpreter.env.top().push(("add_n".to_string(), closure));
+        let call = CallFunc(FuncNode {
+            iden: "add_n".to_string(),
+            args: vec![Constant(Int(2))],
+        });
+        assert_eq!(interpreter.eval_node(&call), Ok(Int(7)));
+        assert!(interpreter.env.read("n").is_err());
+    }
+
+    #[test]
+    fn test_call_function_value() {
+        let mut interpreter = Interpreter::new();
+        interpreter.load(&[
+            DefFunc(DefFuncNode {
+                iden: "add".to_string(),
+                type_params: vec![],
+                args: vec![
+                    ("a".to_string(), TypeNode::Iden("int".to_string())),
+                    ("b".to_string(), TypeNode::Iden("int".to_string()))
+                ],
+                ret: Some(TypeNode::Iden("int".to_string())),
+                body: vec![
+                    Return(Box::new(Binop(BinopNode {
+                        op: Bop::Plus,
+                        lhs: Box::new(Variable("a".to_string())),
+                        rhs: Box::new(Variable("b".to_string())),
+                        pos: POS,
+                    })))
+                ],
+                pos: POS,
+            })
+        ]);
+
+        let func = interpreter.eval_node(&Variable("add".to_string())).unwrap();
+        interpreter.env.top().push(("g".to_string(), func));
+        interpreter.env.top().push(("n".to_string(), Int(1)));
+
+        let call = CallFunc(FuncNode {
+            iden: "g".to_string(),
+            args: vec![Constant(Int(1)), Constant(Int(2))],
+        });
+        assert_eq!(interpreter.eval_node(&call), Ok(Int(3)));
+
+        let call = Call(CallNode {
+            callee: Box::new(Variable("g".to_string())),
+            args: vec![Constant(Int(1))],
+        });
+        assert_eq!(interpreter.eval_node(&call), Err(RunErr::Arity("Function `add` expects 2 arguments, got 1".to_string())));
+
+        let call = CallFunc(FuncNode {
+            iden: "n".to_string(),
+            args: vec![],
+        });
+        assert_eq!(interpreter.eval_node(&call), Err(RunErr::Type("Only functions and closures can be called", None)));
+    }
+
+    #[test]
+    fn test_guard_return() {
+        let mut interpreter = load_program("
+            fn sign(n int) -> int {
+                if n < 0 -> -1;
+                if n == 0 -> 0;
+                return 1;
             }
+            fn sum(n int) -> int {
+                if n <= 0 -> 0;
+                return sum(n - 1) + 1;
+            }
+        ");
+
+        // guards are tried in order and only fall through to the return when none hold
+        assert_eq!(call(&mut interpreter, "sign", vec![Int(-5)]), Ok(Int(-1)));
+        assert_eq!(call(&mut interpreter, "sign", vec![Int(0)]), Ok(Int(0)));
+        assert_eq!(call(&mut interpreter, "sign", vec![Int(5)]), Ok(Int(1)));
+        assert_eq!(call(&mut interpreter, "sum", vec![Int(0)]), Ok(Int(0)));
+        assert_eq!(call(&mut interpreter, "sum", vec![Int(4)]), Ok(Int(4)));
+    }
+
+    #[test]
+    fn test_else_if_chain() {
+        let mut interpreter = load_program("
+            fn chain(a bool, b bool, n int) -> int {
+                if a {
+                    n = n + 1;
+                } else if b {
+                    n = n + 10;
+                } else {
+                    n = n + 100;
+                }
+                return n;
+            }
+        ");
+
+        let cases = [
+            (true, true, 1),
+            (true, false, 1),
+            (false, true, 10),
+            (false, false, 100),
+        ];
+        for (a, b, expected) in cases {
+            let args = vec![Const::Bool(a), Const::Bool(b), Int(0)];
+            assert_eq!(call(&mut interpreter, "chain", args), Ok(Int(expected)));
         }
     }
-    panic!("Function call not yet implemented")
-}
\ No newline at end of file
+
+    #[test]
+    fn test_min_int_literal() {
+        let mut interpreter = load_program("
+            fn min_int() -> int {
+                return -2147483648;
+            }
+            fn negate_max_int() -> int {
+                return -2147483647;
+            }
+            fn negate_min_int() -> int {
+                x := -9223372036854775807 - 1;
+                return -x;
+            }
+        ");
+
+        assert_eq!(call(&mut interpreter, "min_int", vec![]), Ok(Int(i32::MIN as i64)));
+        assert_eq!(call(&mut interpreter, "negate_max_int", vec![]), Ok(Int(-i32::MAX as i64)));
+        let err = Err(RunErr::Overflow("-(-9223372036854775808) is out of range for an int".to_string()));
+        assert_eq!(call(&mut interpreter, "negate_min_int", vec![]), err);
+        assert_eq!(Interpreter::unary_op(&Uop::Minus, Int(i64::MIN + 1)), Ok(Int(i64::MAX)));
+    }
+
+    #[test]
+    fn test_wide_int() {
+        let mut interpreter = load_program("
+            fn big() -> int {
+                return 5_000_000_000 + 1;
+            }
+            fn min_int() -> int {
+                return -9223372036854775808;
+            }
+            fn past_max() -> int {
+                x := 9223372036854775807;
+                return x + 1;
+            }
+            fn pow_overflow() -> int {
+                return 2 ** 64;
+            }
+        ");
+
+        assert_eq!(call(&mut interpreter, "big", vec![]), Ok(Int(5_000_000_001)));
+        assert_eq!(call(&mut interpreter, "min_int", vec![]), Ok(Int(i64::MIN)));
+        let err = Err(RunErr::Overflow("9223372036854775807 + 1 is out of range for an int".to_string()));
+        assert_eq!(call(&mut interpreter, "past_max", vec![]), err);
+        assert_eq!(call(&mut interpreter, "pow_overflow", vec![]), Err(RunErr::Overflow("2 ** 64 is out of range for an int".to_string())));
+
+        let op = |op: Bop, lhs: i64, rhs: i64| Interpreter::binary_op(&op, Int(lhs), Int(rhs));
+        assert!(matches!(op(Bop::Minus, i64::MIN, 1), Err(RunErr::Overflow(_))));
+        assert!(matches!(op(Bop::Multiply, i64::MAX, 2), Err(RunErr::Overflow(_))));
+        assert_eq!(op(Bop::Exp, 2, 62), Ok(Int(1 << 62)));
+        assert_eq!(op(Bop::Minus, i64::MAX, i64::MAX), Ok(Int(0)));
+    }
+
+    #[test]
+    fn test_bigint() {
+        let program = "
+            fn fact(n int) -> int {
+                x := 1;
+                for i in 1..=n {
+                    x = x * i;
+                }
+                return x;
+            }
+            fn shrink() -> bool {
+                return fact(25) / fact(24) == 25 && -(fact(30) - fact(30) + 1) == -1;
+            }
+            fn compare() -> bool {
+                return fact(21) > 9223372036854775807 && -fact(21) < -9223372036854775807;
+            }
+            fn min_int() -> int {
+                x := -9223372036854775807 - 1;
+                return -x + x / -1 - x % -1;
+            }
+        ";
+        let nodes = parse(program);
+        let mut interpreter = Interpreter::new().with_bigint(true);
+        interpreter.load(&nodes);
+
+        let fact = call(&mut interpreter, "fact", vec![Int(30)]).unwrap();
+        assert!(matches!(fact, Const::BigInt(_)));
+        assert_eq!(fact.to_string(), "265252859812191058636308480000000");
+        let fact = call(&mut interpreter, "fact", vec![Int(50)]).unwrap();
+        assert_eq!(fact.to_string(), "30414093201713378043612608166064768844377641568960512000000000000");
+        // a result that fits is an int again
+        assert_eq!(call(&mut interpreter, "fact", vec![Int(20)]), Ok(Int(2432902008176640000)));
+        assert_eq!(call(&mut interpreter, "shrink", vec![]), Ok(Const::Bool(true)));
+        assert_eq!(call(&mut interpreter, "compare", vec![]), Ok(Const::Bool(true)));
+        assert_eq!(call(&mut interpreter, "min_int", vec![]).map(|n| n.to_string()), Ok("18446744073709551616".to_string()));
+
+        // without bigint the same program overflows
+        let mut interpreter = Interpreter::new();
+        interpreter.load(&nodes);
+        assert!(matches!(call(&mut interpreter, "fact", vec![Int(30)]), Err(RunErr::Overflow(_))));
+    }
+
+    #[test]
+    fn test_type_error_position() {
+        let mut interpreter = load_program("
+            fn add_bool() -> int {
+                return 1 + true;
+            }
+        ");
+
+        let err = call(&mut interpreter, "add_bool", vec![]).unwrap_err();
+        let msg = "Add operator must be applied to 2 ints, floats, strings or arrays";
+        assert_eq!(err, RunErr::Type(msg, Some(Position { line: 3, col: 26 })));
+        assert_eq!(err.to_string(), format!("type error at line 3, col 26: {}", msg));
+    }
+
+    #[test]
+    fn test_declare_write() {
+        let mut env = Environment::new();
+        env.declare("x", Int(1));
+        env.declare("x", Int(2));
+        assert_eq!(env.read("x"), Ok(&Int(2)));
+
+        assert!(env.write("x", Int(3)).is_ok());
+        assert_eq!(env.read("x"), Ok(&Int(3)));
+        assert_eq!(env.write("y", Int(1)), Err(RunErr::undefined("y")));
+
+        let mut interpreter = load_program("
+            fn declare() -> int {
+                x := 5;
+                x = x + 1;
+                return x;
+            }
+            fn assign() -> int {
+                y = 1;
+                return y;
+            }
+        ");
+        assert_eq!(call(&mut interpreter, "declare", vec![]), Ok(Int(6)));
+        assert_eq!(call(&mut interpreter, "assign", vec![]), Err(RunErr::undefined("y")));
+    }
+
+    #[test]
+    fn test_block_value() {
+        let mut interpreter = load_program("
+            fn body() -> int {
+                x := 2;
+                x * 3
+            }
+            fn lambda() -> int {
+                g := fn(y) {
+                    if y < 0 -> 0;
+                    x := 2;
+                    x * y
+                };
+                return g(3) + g(-1);
+            }
+        ");
+
+        assert_eq!(call(&mut interpreter, "body", vec![]), Ok(Int(6)));
+        assert_eq!(call(&mut interpreter, "lambda", vec![]), Ok(Int(6)));
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        let mut interpreter = load_program("
+            fn and_int() -> bool { return 1 && true; }
+            fn and_short() -> bool { return false && 1; }
+            fn or_short() -> bool { return true || 1; }
+            fn or_int() -> bool { return false || 1; }
+            fn or_not() -> bool { return false || !false; }
+        ");
+
+        let and_err = RunErr::Type("And operator must be applied to 2 bools", Some(Position { line: 2, col: 45 }));
+        assert_eq!(call(&mut interpreter, "and_int", vec![]), Err(and_err));
+        assert_eq!(call(&mut interpreter, "and_short", vec![]), Ok(Const::Bool(false)));
+        assert_eq!(call(&mut interpreter, "or_short", vec![]), Ok(Const::Bool(true)));
+        assert!(call(&mut interpreter, "or_int", vec![]).is_err());
+        assert_eq!(call(&mut interpreter, "or_not", vec![]), Ok(Const::Bool(true)));
+    }
+
+    #[test]
+    fn test_nested_func() {
+        let mut interpreter = load_program("
+            fn outer(n int) -> int {
+                fn fact(n int) -> int {
+                    if n <= 1 -> 1;
+                    return n * fact(n - 1);
+                }
+                return fact(n) + 1;
+            }
+            fn capture(n int) -> int {
+                fn read_n() -> int {
+                    return n;
+                }
+                return read_n();
+            }
+        ");
+
+        assert_eq!(call(&mut interpreter, "outer", vec![Int(4)]), Ok(Int(25)));
+        assert_eq!(call(&mut interpreter, "capture", vec![Int(4)]), Err(RunErr::undefined("n")));
+        assert!(interpreter.eval_node(&Variable("fact".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_string_index() {
+        let mut interpreter = load_program("
+            fn at(s string, i int) -> char {
+                return s[i];
+            }
+        ");
+
+        let str = Const::String("héllo".to_string());
+        assert_eq!(call(&mut interpreter, "at", vec![str.clone(), Int(1)]), Ok(Const::Char('é')));
+        assert_eq!(call(&mut interpreter, "at", vec![str.clone(), Int(4)]), Ok(Const::Char('o')));
+        assert_eq!(call(&mut interpreter, "at", vec![str.clone(), Int(5)]), Err(RunErr::Range("Index 5 out of range for string of length 5".to_string())));
+        assert_eq!(call(&mut interpreter, "at", vec![str, Int(-1)]), Err(RunErr::Range("Index -1 out of range for string of length 5".to_string())));
+    }
+
+    #[test]
+    fn test_while_loop() {
+        let mut interpreter = load_program("
+            fn exp(x int, n int) -> int {
+                acc := 1;
+                i := 0;
+                while i < n {
+                    acc = acc * x;
+                    i = i + 1;
+                }
+                return acc;
+            }
+
+            fn first_even(n int) -> int {
+                while true {
+                    if n / 2 * 2 == n {
+                        break;
+                    }
+                    n = n + 1;
+                }
+                return n;
+            }
+        ");
+
+        assert_eq!(call(&mut interpreter, "exp", vec![Int(3), Int(4)]), Ok(Int(81)));
+        assert_eq!(call(&mut interpreter, "first_even", vec![Int(7)]), Ok(Int(8)));
+    }
+
+    #[test]
+    fn test_return_tuple() {
+        let mut interpreter = load_program("
+            fn divmod(a int, b int) -> (int, int) {
+                q := a / b;
+                return (q, a - q * b);
+            }
+        ");
+
+        assert_eq!(call(&mut interpreter, "divmod", vec![Int(17), Int(5)]), Ok(Const::Tuple(vec![Int(3), Int(2)])));
+    }
+
+    #[test]
+    fn test_interpret_empty() {
+        let empty = Err(RunErr::Missing("Program is empty, expected a main function".to_string()));
+        assert_eq!(interpret(""), empty);
+        assert_eq!(interpret("   \n  "), empty);
+        assert_eq!(interpret("fn f() -> int { 1 }"), Err(RunErr::Missing("Program has no main function".to_string())));
+        assert_eq!(interpret("fn main() -> int { 1 }"), Ok(Int(1)));
+    }
+
+    #[test]
+    fn test_divide_by_zero() {
+        assert_eq!(Interpreter::binary_op(&Bop::Divide, Int(1), Int(0)), Err(RunErr::Range("Cannot divide 1 by zero".to_string())));
+        assert_eq!(Interpreter::binary_op(&Bop::Mod, Int(1), Int(0)), Err(RunErr::Range("Cannot divide 1 by zero".to_string())));
+        assert!(matches!(Interpreter::binary_op(&Bop::Divide, Int(i64::MIN), Int(-1)), Err(RunErr::Range(_))));
+        assert!(matches!(Interpreter::binary_op(&Bop::Mod, Int(i64::MIN), Int(-1)), Err(RunErr::Range(_))));
+
+        assert_eq!(Interpreter::binary_op(&Bop::Divide, Int(7), Int(2)), Ok(Int(3)));
+        assert_eq!(Interpreter::binary_op(&Bop::Mod, Int(7), Int(2)), Ok(Int(1)));
+    }
+
+    #[test]
+    fn test_throwaway_binding() {
+        let mut interpreter = load_program("
+            fn discard(n int) -> int {
+                _ := n / 0;
+                return n;
+            }
+
+            fn read() -> int {
+                _ := 1;
+                return _;
+            }
+
+            fn count(s string) -> int {
+                n := 0;
+                for _, i in s {
+                    n = i + 1;
+                }
+                return n;
+            }
+        ");
+
+        // the value is still evaluated even though it isn't bound
+        assert_eq!(call(&mut interpreter, "discard", vec![Int(1)]), Err(RunErr::Range("Cannot divide 1 by zero".to_string())));
+        assert_eq!(call(&mut interpreter, "read", vec![]), Err(RunErr::undefined("_")));
+        assert_eq!(call(&mut interpreter, "count", vec![Const::String("abc".to_string())]), Ok(Int(3)));
+    }
+
+    #[test]
+    fn test_eval_tuple() {
+        let mut interpreter = load_program("
+            fn tuple() -> (int, string, bool) {
+                return (1, \"x\", true);
+            }
+        ");
+
+        let tuple = Const::Tuple(vec![Int(1), Const::String("x".to_string()), Const::Bool(true)]);
+        assert_eq!(call(&mut interpreter, "tuple", vec![]), Ok(tuple.clone()));
+        assert_ne!(tuple, Const::Tuple(vec![Int(1), Const::String("x".to_string()), Const::Bool(false)]));
+        assert_eq!(tuple.to_string(), "(1, x, true)");
+    }
+
+    #[test]
+    fn test_call_arity() {
+        let mut interpreter = load_program("
+            fn add(a int, b int) -> int {
+                return a + b;
+            }
+
+            fn negate(a int) -> int {
+                f := fn(x) { -x };
+                return f(a, a);
+            }
+        ");
+
+        assert_eq!(call(&mut interpreter, "add", vec![Int(1)]), Err(RunErr::Arity("Function `add` expects 2 arguments, got 1".to_string())));
+        assert_eq!(call(&mut interpreter, "add", vec![Int(1), Int(2), Int(3)]), Err(RunErr::Arity("Function `add` expects 2 arguments, got 3".to_string())));
+        assert_eq!(call(&mut interpreter, "negate", vec![Int(1)]), Err(RunErr::Arity("Lambda expects 1 argument, got 2".to_string())));
+    }
+
+    #[test]
+    fn test_struct_literal() {
+        let mut interpreter = load_program("
+            struct Point { x int, y int }
+
+            fn point(x int, y int) -> Point {
+                return Point{ y = y, x = x };
+            }
+
+            fn partial() -> Point {
+                return Point{ x = 1 };
+            }
+
+            fn extra() -> Point {
+                return Point{ x = 1, y = 2, z = 3 };
+            }
+        ");
+
+        // fields are stored in the order they are declared in
+        let point = Const::Struct("Point".to_string(), vec![("x".to_string(), Int(1)), ("y".to_string(), Int(2))]);
+        assert_eq!(call(&mut interpreter, "point", vec![Int(1), Int(2)]), Ok(point));
+        assert_eq!(call(&mut interpreter, "partial", vec![]), Err(RunErr::Field("Struct Point is missing field y".to_string())));
+        assert_eq!(call(&mut interpreter, "extra", vec![]), Err(RunErr::Field("Struct Point has no field z".to_string())));
+    }
+
+    #[test]
+    fn test_store_lvalue() {
+        let mut interpreter = load_program("
+            struct Point { x int, y int }
+
+            fn set_first() -> int {
+                a := [1, 2, 3];
+                a[0] = 5;
+                a[1] += 10;
+                return a[0] + a[1];
+            }
+
+            fn move_point() -> Point {
+                p := Point{ x = 1, y = 2 };
+                p.x += 1;
+                p.y = p.y * 3;
+                return p;
+            }
+
+            fn out_of_range() -> int {
+                a := [1];
+                a[1] = 2;
+                return a[0];
+            }
+        ");
+
+        assert_eq!(call(&mut interpreter, "set_first", vec![]), Ok(Int(17)));
+        let point = Const::Struct("Point".to_string(), vec![("x".to_string(), Int(2)), ("y".to_string(), Int(6))]);
+        assert_eq!(call(&mut interpreter, "move_point", vec![]), Ok(point));
+        assert_eq!(call(&mut interpreter, "out_of_range", vec![]), Err(RunErr::Range("Index 1 out of range for array of length 1".to_string())));
+    }
+
+    #[test]
+    fn test_for_range() {
+        let mut interpreter = load_program("
+            fn count(start int, end int) -> int {
+                n := 0;
+                for i in start..end {
+                    n = n + 1;
+                }
+                return n;
+            }
+
+            fn sum(n int) -> int {
+                acc := 0;
+                for i in 0..n + 1 {
+                    acc += i;
+                }
+                return acc;
+            }
+        ");
+
+        assert_eq!(call(&mut interpreter, "count", vec![Int(0), Int(3)]), Ok(Int(3)));
+        // a range that starts past its end is empty rather than counting down
+        assert_eq!(call(&mut interpreter, "count", vec![Int(3), Int(0)]), Ok(Int(0)));
+        assert_eq!(call(&mut interpreter, "sum", vec![Int(4)]), Ok(Int(10)));
+    }
+
+    #[test]
+    fn test_for_range_inclusive() {
+        let mut interpreter = load_program("
+            fn digits(n int) -> int {
+                acc := 0;
+                for i in 0..n {
+                    acc = acc * 10 + i;
+                }
+                return acc;
+            }
+
+            fn digits_inclusive(n int) -> int {
+                acc := 0;
+                for i in 0..=n {
+                    acc = acc * 10 + i;
+                }
+                return acc;
+            }
+        ");
+
+        // the digits of the result are the values iterated over, in order
+        assert_eq!(call(&mut interpreter, "digits", vec![Int(3)]), Ok(Int(12)));
+        assert_eq!(call(&mut interpreter, "digits_inclusive", vec![Int(3)]), Ok(Int(123)));
+        assert_eq!(call(&mut interpreter, "digits_inclusive", vec![Int(-1)]), Ok(Int(0)));
+    }
+
+    #[test]
+    fn test_block_expr() {
+        let mut interpreter = load_program("
+            fn double(n int) -> int {
+                return n * 2;
+            }
+
+            fn f(n int) -> int {
+                if { m := n - 1; m > 0 } {
+                    return double({ x := n + 1; x * 3 });
+                }
+                return 0;
+            }
+        ");
+
+        assert_eq!(call(&mut interpreter, "f", vec![Int(2)]), Ok(Int(18)));
+        assert_eq!(call(&mut interpreter, "f", vec![Int(1)]), Ok(Int(0)));
+    }
+
+    #[test]
+    fn test_compound_assign() {
+        let mut interpreter = load_program("
+            fn ops(n int) -> (int, int, int, int, int, int) {
+                a := n;
+                a += 2;
+                b := n;
+                b -= 2;
+                c := n;
+                c *= 2;
+                d := n;
+                d /= 2;
+                e := n;
+                e **= 2;
+                f := n;
+                f %= 4;
+                return (a, b, c, d, e, f);
+            }
+
+            fn undeclared() -> int {
+                x += 1;
+                return 0;
+            }
+        ");
+
+        let results = Const::Tuple(vec![Int(9), Int(5), Int(14), Int(3), Int(49), Int(3)]);
+        assert_eq!(call(&mut interpreter, "ops", vec![Int(7)]), Ok(results));
+        assert_eq!(call(&mut interpreter, "undeclared", vec![]), Err(RunErr::undefined("x")));
+    }
+
+    #[test]
+    fn test_method_call() {
+        let mut interpreter = load_program("
+            struct Point { x int, y int }
+
+            fn (p Point) dist(q Point) -> int {
+                return (p.x - q.x) ** 2 + (p.y - q.y) ** 2;
+            }
+
+            fn dist(x1 int, y1 int, x2 int, y2 int) -> int {
+                p := Point{ x = x1, y = y1 };
+                return p.dist(Point{ x = x2, y = y2 });
+            }
+
+            fn missing() -> int {
+                p := Point{ x = 1, y = 2 };
+                return p.norm();
+            }
+        ");
+
+        assert_eq!(call(&mut interpreter, "dist", vec![Int(0), Int(0), Int(3), Int(4)]), Ok(Int(25)));
+        assert_eq!(call(&mut interpreter, "missing", vec![]), Err(RunErr::Field("Struct Point has no field or method norm".to_string())));
+    }
+
+    #[test]
+    fn test_range_value() {
+        let mut interpreter = load_program("
+            fn range() -> int {
+                r := 0..5;
+                return len(r);
+            }
+
+            fn sum() -> int {
+                r := 1..=4;
+                acc := 0;
+                for i in r {
+                    acc += i;
+                }
+                return acc;
+            }
+        ");
+
+        assert_eq!(call(&mut interpreter, "range", vec![]), Ok(Int(5)));
+        assert_eq!(call(&mut interpreter, "sum", vec![]), Ok(Int(10)));
+        assert_eq!(Const::Range(0, 5, false).to_string(), "0..5");
+        assert_eq!(Const::Range(1, 4, true).to_string(), "1..=4");
+    }
+
+    #[test]
+    fn test_import_alias() {
+        let module = parse("
+            fn square(n int) -> int {
+                return n * n;
+            }
+        ");
+        let program = parse("
+            import math as m
+
+            fn main() -> int {
+                return m.square(3);
+            }
+
+            fn unaliased() -> int {
+                return math.square(3);
+            }
+        ");
+
+        let mut interpreter = Interpreter::new().with_module("math", module);
+        assert_eq!(interpreter.interpret(&program), Ok(Int(9)));
+        // the module is only reachable through its alias
+        assert_eq!(call(&mut interpreter, "unaliased", vec![]), Err(RunErr::undefined("math")));
+    }
+
+    #[test]
+    fn test_module_member() {
+        let module = parse("
+            fn helper(n int) -> int {
+                return n + 1;
+            }
+        ");
+        let program = parse("
+            import mymod
+
+            struct Wrapper { helper fn(int) -> int }
+
+            fn main() -> int {
+                return mymod.helper(1);
+            }
+
+            fn missing() -> int {
+                return mymod.other(1);
+            }
+
+            fn shadowed() -> int {
+                mymod := Wrapper{ helper = fn(n int) { n * 10 } };
+                return mymod.helper(1);
+            }
+        ");
+
+        let mut interpreter = Interpreter::new().with_module("mymod", module);
+        assert_eq!(interpreter.interpret(&program), Ok(Int(2)));
+        assert_eq!(call(&mut interpreter, "missing", vec![]), Err(RunErr::Missing("Module mymod has no function other".to_string())));
+        // a variable with the name of the module is accessed like any other struct
+        assert_eq!(call(&mut interpreter, "shadowed", vec![]), Ok(Int(10)));
+    }
+
+    #[test]
+    fn test_tail_call() {
+        let mut interpreter = load_program("
+            fn countdown(n int) -> int {
+                if n == 0 {
+                    return 0;
+                }
+                return countdown(n - 1);
+            }
+
+            fn is_even(n int) -> bool {
+                if n == 0 -> true;
+                return is_odd(n - 1);
+            }
+
+            fn is_odd(n int) -> bool {
+                if n == 0 -> false;
+                return is_even(n - 1);
+            }
+        ");
+
+        // deep enough to overflow the stack if every call nested another frame
+        assert_eq!(call(&mut interpreter, "countdown", vec![Int(100000)]), Ok(Int(0)));
+        assert_eq!(call(&mut interpreter, "is_even", vec![Int(100001)]), Ok(Const::Bool(false)));
+    }
+
+    #[test]
+    fn test_print_output() {
+        // shares the buffer with the interpreter so it can be read after the program runs
+        #[derive(Clone)]
+        struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+        impl Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let program = parse("
+            fn main() -> int {
+                print(\"hi\");
+                print(1, 2.0, 'c');
+                return 0;
+            }
+        ");
+
+        let buffer = Buffer(Rc::new(RefCell::new(vec![])));
+        let mut interpreter = Interpreter::new().with_output(Box::new(buffer.clone()));
+        assert_eq!(interpreter.interpret(&program), Ok(Int(0)));
+        assert_eq!(buffer.0.borrow().as_slice(), b"hi\n1 2.0 c\n");
+    }
+
+    #[test]
+    fn test_array_operators() {
+        let array = |elems: &[i64]| Const::Array(elems.iter().map(|elem| Int(*elem)).collect());
+
+        assert_eq!(Interpreter::binary_op(&Bop::Plus, array(&[1, 2]), array(&[3])), Ok(array(&[1, 2, 3])));
+        assert_eq!(Interpreter::binary_op(&Bop::Plus, array(&[]), array(&[3])), Ok(array(&[3])));
+        assert_eq!(Interpreter::binary_op(&Bop::Multiply, array(&[1]), Int(3)), Ok(array(&[1, 1, 1])));
+        assert_eq!(Interpreter::binary_op(&Bop::Multiply, array(&[1, 2]), Int(-1)), Ok(array(&[])));
+        let err = Err(RunErr::Range("Repeating an array of length 1 4611686018427387904 times is too large".to_string()));
+        assert_eq!(Interpreter::binary_op(&Bop::Multiply, array(&[1]), Int(4611686018427387904)), err);
+        assert!(matches!(Interpreter::binary_op(&Bop::Multiply, array(&[1, 2]), Int(i64::MAX)), Err(RunErr::Range(_))));
+        let repeated = Interpreter::binary_op(&Bop::Multiply, Const::String("ab".to_string()), Int(i64::MAX));
+        assert!(matches!(repeated, Err(RunErr::Range(_))));
+
+        let mixed = Interpreter::binary_op(&Bop::Plus, array(&[1]), Const::Array(vec![Const::Bool(true)]));
+        assert!(matches!(mixed, Err(RunErr::Type(_, _))));
+    }
+
+    #[test]
+    fn test_unit_return() {
+        assert_eq!(interpret("fn main() { x := 1; }"), Ok(Const::Tuple(vec![])));
+        assert_eq!(interpret("fn f(n int) { n + 1; } fn main() { f(1); }"), Ok(Const::Tuple(vec![])));
+        assert_eq!(interpret("fn main() -> int { x := 1; }"), Err(RunErr::Type("Function ended without returning a value", None)));
+
+        // a return without a value leaves early with the empty tuple
+        assert_eq!(interpret("fn f(n int) { if n > 0 { return; } assert(false); } fn main() { f(1); return }"), Ok(Const::Tuple(vec![])));
+    }
+
+    #[test]
+    fn test_nil_value() {
+        assert_eq!(interpret("fn main() -> ?int { x := nil; return x; }"), Ok(Const::Nil));
+        assert_eq!(interpret("fn main() -> bool { x := nil; return x == nil; }"), Ok(Const::Bool(true)));
+        assert_eq!(interpret("fn main() -> bool { x := 1; return x != nil; }"), Ok(Const::Bool(true)));
+    }
+
+    #[test]
+    fn test_inline_module() {
+        let program = parse("
+            mod math {
+                fn add(a int, b int) -> int {
+                    return a + b;
+                }
+
+                mod trig {
+                    fn zero() -> int {
+                        return 0;
+                    }
+                }
+            }
+
+            fn main() -> int {
+                return math.add(1, 2) + math.trig.zero();
+            }
+
+            fn add() -> int {
+                return math.sub(1, 2);
+            }
+        ");
+
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.interpret(&program), Ok(Int(3)));
+        // the functions of a module don't collide with the functions outside of it
+        assert_eq!(call(&mut interpreter, "add", vec![]), Err(RunErr::Missing("Module math has no function sub".to_string())));
+    }
+
+    #[test]
+    fn test_nested_flow() {
+        let program = "
+            fn main() -> int {
+                i := 0;
+                if true {
+                    while true {
+                        i += 1;
+                        if i == 3 {
+                            return i * 10;
+                        }
+                    }
+                }
+                return -1;
+            }
+        ";
+        assert_eq!(interpret(program), Ok(Int(30)));
+
+        // the break leaves the inner loop and the outer loop runs each of its iterations
+        let program = "
+            fn main() -> int {
+                count := 0;
+                for i in 0..3 {
+                    for j in 0..10 {
+                        if j == 2 {
+                            break;
+                        }
+                        count += 1;
+                    }
+                    count += 100;
+                }
+                return count;
+            }
+        ";
+        assert_eq!(interpret(program), Ok(Int(306)));
+
+        let err = Err(RunErr::Type("Break and continue must be used within a loop", None));
+        assert_eq!(interpret("fn main() -> int { if true { break; } return 0; }"), err);
+    }
+
+    #[test]
+    fn test_ternary() {
+        let mut interpreter = load_program("
+            fn sign(x int) -> int {
+                return x > 0 ? 1 : -1;
+            }
+
+            fn lazy(x int) -> int {
+                return x == 0 ? 0 : 10 / x;
+            }
+
+            fn bad() -> int {
+                return 1 ? 2 : 3;
+            }
+        ");
+        assert_eq!(call(&mut interpreter, "sign", vec![Int(5)]), Ok(Int(1)));
+        assert_eq!(call(&mut interpreter, "sign", vec![Int(-5)]), Ok(Int(-1)));
+        // the branch that isn't chosen is never evaluated
+        assert_eq!(call(&mut interpreter, "lazy", vec![Int(0)]), Ok(Int(0)));
+        assert_eq!(call(&mut interpreter, "bad", vec![]), Err(RunErr::Type("Ternary condition must be a bool", None)));
+    }
+
+    #[test]
+    fn test_undefined_fields() {
+        let mut interpreter = Interpreter::new();
+        let err = interpreter.eval_node(&Variable("missing".to_string())).unwrap_err();
+        let RunErr::Undefined { iden, pos } = &err else { panic!("expected an undefined variable, got {:?}", err) };
+        assert_eq!((iden.as_str(), *pos), ("missing", None));
+        assert_eq!(err.to_string(), "Undefined variable missing");
+
+        let err = err.at(Position { line: 2, col: 5 });
+        assert_eq!(err, RunErr::Undefined { iden: "missing".to_string(), pos: Some(Position { line: 2, col: 5 }) });
+        assert_eq!(err.to_string(), "Undefined variable missing");
+    }
+
+    #[test]
+    fn test_float_exponent() {
+        let exp = |lhs: f64, rhs: f64| Interpreter::binary_op(&Bop::Exp, Const::Float(lhs), Const::Float(rhs));
+
+        assert_eq!(exp(2.0, -2.0), Ok(Const::Float(0.25)));
+        assert_eq!(exp(9.0, 0.5), Ok(Const::Float(3.0)));
+        assert_eq!(exp(10.0, 400.0), Err(RunErr::Overflow("10.0 ** 400.0 is not a finite float".to_string())));
+        assert!(matches!(exp(-8.0, 0.5), Err(RunErr::Overflow(_))));
+        // a result that is already infinite in the operands isn't an overflow
+        assert_eq!(exp(f64::INFINITY, 2.0), Ok(Const::Float(f64::INFINITY)));
+    }
+
+    #[test]
+    fn test_declare_many() {
+        let mut interpreter = load_program("
+            fn both() -> int {
+                x, y := 1, 2;
+                return x * 10 + y;
+            }
+
+            fn swap() -> (int, int) {
+                a, b := 1, 2;
+                a, b := b, a;
+                return (a, b);
+            }
+
+            fn pair() -> int {
+                p := (3, 4);
+                _, y := p;
+                return y;
+            }
+
+            fn mismatch() -> int {
+                x, y := (1, 2, 3);
+                return x;
+            }
+        ");
+
+        assert_eq!(call(&mut interpreter, "both", vec![]), Ok(Int(12)));
+        assert_eq!(call(&mut interpreter, "swap", vec![]), Ok(Const::Tuple(vec![Int(2), Int(1)])));
+        assert_eq!(call(&mut interpreter, "pair", vec![]), Ok(Int(4)));
+        let err = Err(RunErr::Arity("Cannot declare 2 names from a tuple of 3 elements".to_string()));
+        assert_eq!(call(&mut interpreter, "mismatch", vec![]), err);
+    }
+
+    #[test]
+    fn test_loop_scope() {
+        let mut interpreter = load_program("
+            fn count() -> int {
+                total := 0;
+                i := 0;
+                while i < 3 {
+                    step := i * 2;
+                    total += step;
+                    i += 1;
+                }
+                for j in 0..2 {
+                    total += j;
+                }
+                return total;
+            }
+
+            fn fresh() -> int {
+                seen := 0;
+                for i in 0..3 {
+                    x := 1;
+                    x += i;
+                    seen = x;
+                }
+                return seen;
+            }
+
+            fn leak() -> int {
+                i := 0;
+                while i < 1 {
+                    inner := 5;
+                    i += 1;
+                }
+                return inner;
+            }
+
+            fn element() -> int {
+                for e in [1, 2] {}
+                return e;
+            }
+
+            fn shadow_in_if() -> int {
+                x := 0;
+                if true {
+                    x := 2;
+                } else {
+                    x := 3;
+                }
+                return x;
+            }
+
+            fn leak_from_if() -> int {
+                if true {
+                    y := 2;
+                }
+                return y;
+            }
+        ");
+
+        assert_eq!(call(&mut interpreter, "count", vec![]), Ok(Int(7)));
+        // each iteration declares its own x instead of seeing the one from the iteration before
+        assert_eq!(call(&mut interpreter, "fresh", vec![]), Ok(Int(3)));
+        let err = call(&mut interpreter, "leak", vec![]).unwrap_err();
+        assert!(matches!(&err, RunErr::Undefined { iden, .. } if iden == "inner"), "got {:?}", err);
+        let err = call(&mut interpreter, "element", vec![]).unwrap_err();
+        assert!(matches!(&err, RunErr::Undefined { iden, .. } if iden == "e"), "got {:?}", err);
+        // an if body is scoped the same way, so declaring in it only shadows
+        assert_eq!(call(&mut interpreter, "shadow_in_if", vec![]), Ok(Int(0)));
+        let err = call(&mut interpreter, "leak_from_if", vec![]).unwrap_err();
+        assert!(matches!(&err, RunErr::Undefined { iden, .. } if iden == "y"), "got {:?}", err);
+    }
+
+    #[test]
+    fn test_string_ordering() {
+        let cmp = |op: Bop, lhs: &str, rhs: &str| Interpreter::binary_op(&op, Const::String(lhs.to_string()), Const::String(rhs.to_string()));
+
+        assert_eq!(cmp(Bop::Lt, "Z", "a"), Ok(Const::Bool(true)));
+        assert_eq!(cmp(Bop::Lt, "é", "f"), Ok(Const::Bool(false)));
+        assert_eq!(cmp(Bop::Gt, "é", "f"), Ok(Const::Bool(true)));
+        // a prefix is ordered before the longer string
+        assert_eq!(cmp(Bop::Lt, "ab", "abc"), Ok(Const::Bool(true)));
+        assert_eq!(cmp(Bop::Leq, "abc", "abc"), Ok(Const::Bool(true)));
+        assert_eq!(cmp(Bop::Geq, "", "a"), Ok(Const::Bool(false)));
+    }
+
+    #[test]
+    fn test_for_continue() {
+        let mut interpreter = load_program("
+            fn odds() -> (int, int) {
+                visited := 0;
+                sum := 0;
+                for x in 0..5 {
+                    visited += 1;
+                    if x % 2 == 0 {
+                        continue;
+                    }
+                    sum += x;
+                }
+                return (visited, sum);
+            }
+
+            fn indices() -> int {
+                total := 0;
+                for x, i in [10, 20, 30, 40] {
+                    if i == 1 {
+                        continue;
+                    }
+                    total += x + i;
+                }
+                return total;
+            }
+        ");
+
+        // every element is visited even though the even ones skip the rest of the body
+        assert_eq!(call(&mut interpreter, "odds", vec![]), Ok(Const::Tuple(vec![Int(5), Int(4)])));
+        assert_eq!(call(&mut interpreter, "indices", vec![]), Ok(Int(10 + 30 + 40 + 2 + 3)));
+    }
+
+    #[test]
+    fn test_trace() {
+        let program = parse("fn f(x int) -> int {\n    return (x + 1) * -x;\n}");
+
+        let mut interpreter = Interpreter::new().with_trace(true);
+        interpreter.load(&program);
+        assert_eq!(call(&mut interpreter, "f", vec![Int(3)]), Ok(Int(-12)));
+        let values: Vec<(u32, Const)> = interpreter.trace().iter().map(|(pos, value)| (pos.col, value.clone())).collect();
+        assert_eq!(values, vec![(15, Int(4)), (22, Int(-3)), (20, Int(-12))]);
+        assert!(interpreter.trace().iter().all(|(pos, _)| pos.line == 2));
+
+        let mut interpreter = Interpreter::new();
+        interpreter.load(&program);
+        assert_eq!(call(&mut interpreter, "f", vec![Int(3)]), Ok(Int(-12)));
+        assert!(interpreter.trace().is_empty());
+    }
+
+    #[test]
+    fn test_slice() {
+        let eval = |expr: &str| {
+            let program = format!("fn f() -> int {{ return {}; }}", expr);
+            let mut interpreter = load_program(&program);
+            call(&mut interpreter, "f", vec![])
+        };
+        let array = |elems: &[i64]| Ok(Const::Array(elems.iter().map(|i| Int(*i)).collect()));
+
+        assert_eq!(eval("[1, 2, 3, 4][1..3]"), array(&[2, 3]));
+        assert_eq!(eval("[1, 2, 3, 4][1..=3]"), array(&[2, 3, 4]));
+        assert_eq!(eval("[1, 2, 3, 4][..]"), array(&[1, 2, 3, 4]));
+        assert_eq!(eval("[1, 2, 3, 4][2..]"), array(&[3, 4]));
+        assert_eq!(eval("[1, 2, 3, 4][..1]"), array(&[1]));
+        assert_eq!(eval("[1, 2, 3, 4][2..2]"), array(&[]));
+        assert_eq!(eval("\"héllo\"[1..4]"), Ok(Const::String("éll".to_string())));
+
+        let err = Err(RunErr::Range("Slice 2..5 out of range for array of length 4".to_string()));
+        assert_eq!(eval("[1, 2, 3, 4][2..5]"), err);
+        assert!(matches!(eval("[1, 2, 3, 4][3..1]"), Err(RunErr::Range(_))));
+        assert!(matches!(eval("[1, 2, 3, 4][-1..]"), Err(RunErr::Range(_))));
+        assert!(matches!(eval("5[0..1]"), Err(RunErr::Type("Slice operator must be applied to a string or an array", Some(_)))));
+    }
+
+    #[test]
+    fn test_caller_locals_hidden() {
+        let mut interpreter = load_program("
+            fn peek() -> int {
+                return x;
+            }
+
+            fn outer() -> int {
+                x := 1;
+                return peek();
+            }
+
+            fn nested() -> int {
+                x := 1;
+                if true {
+                    y := x + 1;
+                    while y < 5 {
+                        y = y + x;
+                    }
+                    return y;
+                }
+                return 0;
+            }
+
+            fn helper() -> int {
+                return nested() + 1;
+            }
+        ");
+        // a block sees the locals of the blocks around it, but a call only sees its own locals and the definitions
+        let err = call(&mut interpreter, "outer", vec![]).unwrap_err();
+        assert!(matches!(&err, RunErr::Undefined { iden, .. } if iden == "x"), "{:?}", err);
+        assert_eq!(call(&mut interpreter, "nested", vec![]), Ok(Int(5)));
+        assert_eq!(call(&mut interpreter, "helper", vec![]), Ok(Int(6)));
+    }
+
+    // functions with loops, branches and calls to the function before them, and a main that calls all of them
+    fn generate(funcs: usize) -> String {
+        let mut program = String::new();
+        for i in 0..funcs {
+            let call = if i == 0 { "0".to_string() } else { format!("f{}(n - 1) % 1000", i - 1) };
+            program += &format!("
+                fn f{i}(n int) -> int {{
+                    total := 0;
+                    i := 0;
+                    while i < n {{
+                        if i % 3 == 0 {{
+                            total = total + i * {i};
+                        }} else {{
+                            total = total - 1;
+                        }}
+                        i = i + 1;
+                    }}
+                    for j, k in [1, 2, 3] {{
+                        total = total + j * k;
+                    }}
+                    return n > 0 ? total + {call} : total;
+                }}
+            ");
+        }
+        program += "fn main() -> int {\n    total := 0;\n";
+        for i in 0..funcs {
+            program += &format!("    total = total + f{}(8);\n", i);
+        }
+        program + "    return total;\n}\n"
+    }
+
+    // the parse and eval times of a large program, to measure a change to how the tree is laid out against.
+    // run with `cargo test --release bench_parse_eval -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_parse_eval() {
+        let source = generate(2000);
+        let runs = 5;
+
+        let (mut parsing, mut evaluating) = (Duration::ZERO, Duration::ZERO);
+        for _ in 0..runs {
+            let start = Instant::now();
+            let program = parse(&source);
+            parsing += start.elapsed();
+
+            let start = Instant::now();
+            let result = Interpreter::new().interpret(&program);
+            evaluating += start.elapsed();
+            assert!(matches!(result, Ok(Int(_))), "{:?}", result);
+        }
+        println!("{} bytes: parse {:?}, eval {:?}", source.len(), parsing / runs, evaluating / runs);
+    }
+}
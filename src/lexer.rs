@@ -1,9 +1,10 @@
 use std::collections::VecDeque;
 use std::fmt;
 use std::fmt::{Debug, Display, Formatter};
-use std::io::{BufRead, BufReader, Read};
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::num::IntErrorKind;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct TokenContext {
     pub kind: Token,
     pub lpos: Position,
@@ -16,7 +17,7 @@ impl Display for TokenContext {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Position {
     pub line: u32,
     pub col: u32,
@@ -30,7 +31,8 @@ impl Display for Position {
 
 #[derive(Debug, PartialEq)]
 pub enum Token {
-    IntLit(i32),
+    // the magnitude of an int literal, the parser applies any leading minus sign
+    IntLit(u64),
     FloatLit(f64),
     CharLit(char),
     StrLit(String),
@@ -41,6 +43,8 @@ pub enum Token {
     LBrace,
     RBrace,
     Dot,
+    Range,
+    RangeInclusive,
     Comma,
     Declare,
     Assign,
@@ -49,7 +53,11 @@ pub enum Token {
     Iden(String),
     True,
     False,
+    Nil,
     Fn,
+    If,
+    Else,
+    Elif,
     Struct,
     Type,
     Return,
@@ -59,8 +67,15 @@ pub enum Token {
     For,
     In,
     Import,
+    Mod,
+    Const,
     SemiColon,
     Arrow,
+    Question,
+    Colon,
+    Comment(String),
+    // a number or an operator that couldn't be lexed, only emitted in recovery mode
+    Error(String),
 }
 
 #[derive(Debug, PartialEq)]
@@ -70,6 +85,7 @@ pub enum Op {
     Minus,
     Multiply,
     Divide,
+    Mod,
     Eq,
     Neq,
     Leq,
@@ -88,22 +104,56 @@ pub enum Aop {
     Minus,
     Multiply,
     Divide,
+    Mod,
 }
 
 impl Token {
+    // true, false and nil are both literals and reserved keywords
+    pub fn is_literal(&self) -> bool {
+        matches!(self, Token::IntLit(_) | Token::FloatLit(_) | Token::CharLit(_) | Token::StrLit(_) | Token::True | Token::False | Token::Nil)
+    }
+
+    #[allow(dead_code)]
+    pub fn is_keyword(&self) -> bool {
+        matches!(self, Token::True | Token::False | Token::Nil | Token::Fn | Token::If | Token::Else | Token::Elif | Token::Struct | Token::Type
+            | Token::Return | Token::Break | Token::Continue | Token::While | Token::For | Token::In | Token::Import | Token::Mod | Token::Const)
+    }
+
+    #[allow(dead_code)]
+    pub fn is_operator(&self) -> bool {
+        matches!(self, Token::Operator(_) | Token::AssignOp(_))
+    }
+
+    // the binding power of a binary operator, higher binds tighter
+    pub fn precedence(&self) -> Option<u8> {
+        let prec = match self {
+            Token::Operator(Op::Or) => 1,
+            Token::Operator(Op::And) => 2,
+            Token::Operator(Op::Eq | Op::Neq) => 3,
+            Token::Operator(Op::Lt | Op::Gt | Op::Leq | Op::Geq) => 4,
+            Token::Operator(Op::Plus | Op::Minus) => 5,
+            Token::Operator(Op::Multiply | Op::Divide | Op::Mod) => 6,
+            Token::Operator(Op::Exp) => 7,
+            _ => return None
+        };
+        Some(prec)
+    }
+
     pub fn to_text(&self) -> &'static str {
         match self {
             Token::IntLit(_) => "<int>",
             Token::FloatLit(_) => "<float>",
             Token::CharLit(_) => "<char>",
             Token::StrLit(_) => "<string>",
-            Token::LParen => "':'",
+            Token::LParen => "'('",
             Token::RParen => "')'",
             Token::LBracket => "'['",
             Token::RBracket => "']'",
             Token::LBrace => "'{'",
             Token::RBrace => "'}'",
             Token::Dot => "'.'",
+            Token::Range => "'..'",
+            Token::RangeInclusive => "'..='",
             Token::Comma => "','",
             Token::Declare => "':='",
             Token::Assign => "'='",
@@ -112,7 +162,11 @@ impl Token {
             Token::Iden(_) => "<iden>",
             Token::True => "true",
             Token::False => "false",
+            Token::Nil => "nil",
             Token::Fn => "fn",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::Elif => "elif",
             Token::Struct => "struct",
             Token::Type => "type",
             Token::Return => "return",
@@ -122,8 +176,14 @@ impl Token {
             Token::For => "for",
             Token::In => "in",
             Token::Import => "import",
+            Token::Mod => "mod",
+            Token::Const => "const",
             Token::SemiColon => "';'",
-            Token::Arrow => "'->'"
+            Token::Arrow => "'->'",
+            Token::Question => "'?'",
+            Token::Colon => "':'",
+            Token::Comment(_) => "<comment>",
+            Token::Error(_) => "<error>"
         }
     }
 }
@@ -137,49 +197,167 @@ impl Display for Token {
 
 pub struct Lexer<T: BufRead> {
     reader: BufReader<T>,
+    // the chars that were peeked at but not consumed yet
+    ahead: VecDeque<char>,
+    // the tokens lexed but not yielded yet, which is more than one when a newline ends a statement
+    pending: VecDeque<TokenContext>,
+    terminator: Terminator,
+    failed: bool,
     pos: Position,
+    comments: bool,
+    newlines: bool,
+    recovery: bool,
+    limits: Limits,
+}
+
+// the maximum length in bytes of a single token, so a huge token can't grow a buffer without bound
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub iden: usize,
+    pub text: usize,
+    pub number: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits { iden: 1024, text: 1 << 20, number: 512 }
+    }
 }
 
 impl<T: BufRead> Lexer<T> {
     pub fn new(reader: BufReader<T>) -> Lexer<T> {
-        Lexer { reader, pos: Position { line: 0, col: 0 } }
+        Lexer {
+            reader,
+            ahead: VecDeque::new(),
+            pending: VecDeque::new(),
+            terminator: Terminator::default(),
+            failed: false,
+            pos: Position { line: 1, col: 0 },
+            comments: false,
+            newlines: false,
+            recovery: false,
+            limits: Limits::default(),
+        }
+    }
+
+    // end a statement at the end of a line without a semicolon, as long as it isn't inside of parens or brackets
+    #[allow(dead_code)]
+    pub fn with_newlines(mut self, newlines: bool) -> Lexer<T> {
+        self.newlines = newlines;
+        self
+    }
+
+    // a bad number or operator becomes an error token and lexing goes on after it, so an editor still gets
+    // the tokens of the rest of the source
+    pub fn with_recovery(mut self, recovery: bool) -> Lexer<T> {
+        self.recovery = recovery;
+        self
+    }
+
+    fn recover(&self, scanned: Result<TokenContext, String>, lpos: Position) -> Result<TokenContext, String> {
+        match scanned {
+            Err(err) if self.recovery => Ok(TokenContext { kind: Token::Error(err), lpos, rpos: self.pos }),
+            scanned => scanned
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_limits(mut self, limits: Limits) -> Lexer<T> {
+        self.limits = limits;
+        self
+    }
+
+    fn check_len(&self, kind: &str, tokstr: &str, max: usize, lpos: Position) -> Result<(), String> {
+        if tokstr.len() > max {
+            return Err(format!("Too long: {} exceeds the maximum length of {} bytes starting at {}", kind, max, lpos))
+        }
+        Ok(())
+    }
+
+    // emit comments as tokens instead of discarding them, for tools such as a formatter
+    #[allow(dead_code)]
+    pub fn with_comments(mut self, comments: bool) -> Lexer<T> {
+        self.comments = comments;
+        self
+    }
+
+    // decodes the utf-8 bytes of the next char, a byte that can't start a char or a char that is cut
+    // short by the end of the input is an error
+    fn decode(&mut self) -> Result<Option<char>, String> {
+        let mut bytes = [0; 4];
+        match self.reader.read(&mut bytes[..1]) {
+            Ok(0) => return Ok(None),
+            Ok(_) => {}
+            Err(err) => return Err(err.to_string())
+        }
+        let width = match bytes[0] {
+            0x00..=0x7f => 1,
+            0xc2..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf4 => 4,
+            _ => 1
+        };
+        let mut read = 1;
+        while read < width {
+            match self.reader.read(&mut bytes[read..width]) {
+                Ok(0) => break,
+                Ok(count) => read += count,
+                Err(err) => return Err(err.to_string())
+            }
+        }
+        match std::str::from_utf8(&bytes[..read]) {
+            Ok(str) => Ok(str.chars().next()),
+            Err(_) => Err(format!("Invalid utf-8: cannot decode the bytes {:02x?} after {}", &bytes[..read], self.pos))
+        }
+    }
+
+    // decodes chars into the lookahead until it holds count of them or the input ends
+    fn fill(&mut self, count: usize) -> Result<(), String> {
+        while self.ahead.len() < count {
+            match self.decode()? {
+                Some(c) => self.ahead.push_back(c),
+                None => break
+            }
+        }
+        Ok(())
     }
 
     fn read(&mut self) -> Result<Option<char>, String> {
-        let mut buffer = [0; 1];
-        match self.reader.read(&mut buffer) {
-            Ok(count) => Ok(
-                if count > 0 {
-                    let c = buffer[0] as char;
-                    if c == '\n' {
-                        self.pos.line += 1
-                    } else {
-                        self.pos.col += 1
-                    }
-                    Some(c)
-                } else {
-                    None
-                }
-            ),
-            Err(err) => Err(err.to_string())
+        self.fill(1)?;
+        let c = self.ahead.pop_front();
+        if let Some(c) = c {
+            self.advance(c)
         }
+        Ok(c)
     }
 
     fn peek(&mut self) -> Result<Option<char>, String> {
-        match self.reader.fill_buf() {
-            Ok(buffer) => Ok(
-                if !buffer.is_empty() {
-                    Some(buffer[0] as char)
-                } else {
-                    None
-                }
-            ),
-            Err(err) => Err(err.to_string())
+        self.fill(1)?;
+        Ok(self.ahead.front().copied())
+    }
+
+    // looks at the char after the next one without consuming either
+    fn peek_next(&mut self) -> Result<Option<char>, String> {
+        self.fill(2)?;
+        Ok(self.ahead.get(1).copied())
+    }
+
+    // moves the position past a char, so a position is the line and col of the last char read
+    fn advance(&mut self, c: char) {
+        // positions saturate instead of overflowing on an input with billions of lines or columns
+        if c == '\n' {
+            self.pos.line = self.pos.line.saturating_add(1);
+            self.pos.col = 0;
+        } else {
+            self.pos.col = self.pos.col.saturating_add(1)
         }
     }
 
     fn consume(&mut self) {
-        self.reader.consume(1)
+        if let Ok(Some(c)) = self.peek() {
+            self.ahead.pop_front();
+            self.advance(c)
+        }
     }
 
     fn skip_spaces(&mut self) -> Result<(), String> {
@@ -193,14 +371,15 @@ impl<T: BufRead> Lexer<T> {
         Ok(())
     }
 
-    fn match_escseq(c: char, term: char) -> Result<char, String> {
+    fn match_escseq(c: char) -> Result<char, String> {
         match c {
             '\\' => Ok('\\'),
             'n' => Ok('\n'),
             't' => Ok('\t'),
             'r' => Ok('\r'),
             '0' => Ok('\0'),
-            _ if c == term => Ok(term),
+            '"' => Ok('"'),
+            '\'' => Ok('\''),
             _ => Err(format!("Invalid esc seq: '\\{}'", c)),
         }
     }
@@ -214,13 +393,48 @@ impl<T: BufRead> Lexer<T> {
             '{' => Token::LBrace,
             '}' => Token::RBrace,
             ',' => Token::Comma,
-            '.' => Token::Dot,
             ';' => Token::SemiColon,
             _ => return None
         };
         Some(TokenContext { kind: tok, lpos, rpos: self.pos })
     }
 
+    fn scan_dot(&mut self) -> Result<TokenContext, String> {
+        let lpos = self.pos;
+        // the longest of '.', '..', and '..=' is taken
+        let tok = if self.peek()? == Some('.') {
+            self.consume();
+            if self.peek()? == Some('=') {
+                self.consume();
+                Token::RangeInclusive
+            } else {
+                Token::Range
+            }
+        } else {
+            Token::Dot
+        };
+        Ok(TokenContext { kind: tok, lpos, rpos: self.pos })
+    }
+
+    // an identifier starts with a letter of any script or an underscore, and continues with letters,
+    // digits and underscores, so a digit can't start one and 'café' or 'π' are single identifiers.
+    // this approximates XID_Start and XID_Continue from UAX #31 with the Alphabetic and Numeric properties
+    // std has, without the unicode tables: Alphabetic covers letters, letter numbers and the vowel signs
+    // of scripts like Devanagari, but not the general combining marks, which are accepted after the start
+    // from the blocks below, so a decomposed 'e\u{301}' is one identifier. connector punctuation other
+    // than the underscore and the rarer marks outside of those blocks aren't accepted
+    fn is_iden_start(c: char) -> bool {
+        c == '_' || c.is_alphabetic()
+    }
+
+    fn is_iden_continue(c: char) -> bool {
+        c == '_' || c.is_alphanumeric() || Self::is_combining_mark(c)
+    }
+
+    fn is_combining_mark(c: char) -> bool {
+        matches!(c, '\u{300}'..='\u{36F}' | '\u{1AB0}'..='\u{1AFF}' | '\u{1DC0}'..='\u{1DFF}' | '\u{20D0}'..='\u{20FF}' | '\u{FE20}'..='\u{FE2F}')
+    }
+
     fn is_control(c: char) -> bool {
         "[](){},.;".contains(c)
     }
@@ -228,16 +442,27 @@ impl<T: BufRead> Lexer<T> {
     fn scan_text(&mut self, term: char) -> Result<(String, Position, Position), String> {
         let lpos = self.pos;
         let mut isesc = false;
+        let mut terminated = false;
         let mut str = String::new();
         while let Some(c) = self.read()? {
+            self.check_len("text literal", &str, self.limits.text, lpos)?;
             if isesc {
                 isesc = false;
-                let c = Self::match_escseq(c, term)?;
+                // a backslash at the end of a line continues the literal on the next line without the newline
+                if c == '\n' {
+                    continue
+                }
+                if c == '\r' && self.peek()? == Some('\n') {
+                    self.consume();
+                    continue
+                }
+                let c = if c == 'x' { self.scan_hex_escseq()? } else { Self::match_escseq(c)? };
                 str.push(c)
             } else {
                 if c == '\\' {
                     isesc = true
                 } else if c == term {
+                    terminated = true;
                     break;
                 } else {
                     str.push(c)
@@ -245,18 +470,42 @@ impl<T: BufRead> Lexer<T> {
             }
         };
 
+        if !terminated {
+            let kind = if term == '\'' { "char" } else { "string" };
+            return Err(format!("Unterminated {}: expected closing {} for the literal starting at {}", kind, term, lpos))
+        }
         Ok((str, lpos, self.pos))
     }
 
+    // a '\x' escape takes exactly two hex digits, and only ascii chars can be written this way
+    fn scan_hex_escseq(&mut self) -> Result<char, String> {
+        let mut digits = String::new();
+        for _ in 0..2 {
+            match self.read()? {
+                Some(c) => digits.push(c),
+                None => break
+            }
+        }
+        if digits.len() != 2 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("Invalid esc seq: '\\x{}' must be followed by two hex digits", digits))
+        }
+        match u8::from_str_radix(&digits, 16) {
+            Ok(byte) if byte.is_ascii() => Ok(byte as char),
+            _ => Err(format!("Invalid esc seq: '\\x{}' is not an ascii char, only '\\x00' to '\\x7f' are allowed", digits))
+        }
+    }
+
     fn scan_char(&mut self) -> Result<TokenContext, String> {
         let (str, lpos, rpos) = self.scan_text('\'')?;
-        let first_char = str.chars().nth(0);
 
-        match (first_char, str.len()) {
-            (Some(c), 1) => {
+        // escapes are already replaced, so a valid literal holds exactly one scalar
+        let mut chars = str.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => {
                 let token = TokenContext { kind: Token::CharLit(c), lpos, rpos };
                 Ok(token)
             }
+            (None, _) => Err(format!("Invalid char: empty char literal between {} and {}", lpos, rpos)),
             _ => Err(format!("Invalid char: '{}' a char literal length 1 between {} and {}", str, lpos, rpos))
         }
     }
@@ -271,25 +520,41 @@ impl<T: BufRead> Lexer<T> {
         let lpos = self.pos;
         let mut is_int = true;
         let mut tokstr = String::from(c);
-        while let Some(c) = self.peek()? {
-            if !c.is_alphanumeric() {
-                break;
-            }
-            if c == '.' {
-                is_int = false;
+        loop {
+            match self.peek()? {
+                // a dot is only part of the number when a digit follows it, so ranges like 0..n still lex
+                Some('.') if is_int && self.peek_next()?.is_some_and(|c| c.is_ascii_digit()) => {
+                    is_int = false;
+                    tokstr.push('.');
+                }
+                // underscores only separate digits for readability
+                Some('_') => {}
+                Some(c) if c.is_alphanumeric() => {
+                    if c == 'e' || c == 'E' {
+                        is_int = false;
+                    }
+                    tokstr.push(c);
+                }
+                _ => break
             }
-            tokstr.push(c);
+            self.check_len("number", &tokstr, self.limits.number, lpos)?;
             self.consume()
         }
 
         let rpos = self.pos;
         if is_int {
-            match tokstr.parse::<i32>() {
+            match tokstr.parse::<u64>() {
                 Ok(int) => Ok(TokenContext { kind: Token::IntLit(int), lpos, rpos }),
+                Err(err) if *err.kind() == IntErrorKind::PosOverflow => {
+                    Err(format!("Invalid int: {} is out of range for i64 between {} and {}", tokstr, lpos, rpos))
+                }
                 Err(_) => Err(format!("Invalid int: cannot lex {} between {} and {}", tokstr, lpos, rpos))
             }
         } else {
             match tokstr.parse::<f64>() {
+                Ok(float) if float.is_infinite() => {
+                    Err(format!("Invalid float: {} is out of range for f64 between {} and {}", tokstr, lpos, rpos))
+                }
                 Ok(float) => Ok(TokenContext { kind: Token::FloatLit(float), lpos, rpos }),
                 Err(_) => Err(format!("Invalid float: cannot lex {} between {} and {}", tokstr, lpos, rpos))
             }
@@ -300,15 +565,19 @@ impl<T: BufRead> Lexer<T> {
         let lpos = self.pos;
         let mut tokstr = String::from(c);
         while let Some(c) = self.peek()? {
-            if '_' != c && !c.is_alphanumeric() {
+            if !Self::is_iden_continue(c) {
                 break;
             }
             tokstr.push(c);
+            self.check_len("identifier", &tokstr, self.limits.iden, lpos)?;
             self.consume()
         }
 
         let tok = match tokstr.as_str() {
             "fn" => Token::Fn,
+            "if" => Token::If,
+            "else" => Token::Else,
+            "elif" => Token::Elif,
             "struct" => Token::Struct,
             "type" => Token::Type,
             "return" => Token::Return,
@@ -319,7 +588,10 @@ impl<T: BufRead> Lexer<T> {
             "in" => Token::In,
             "true" => Token::True,
             "false" => Token::False,
+            "nil" => Token::Nil,
             "import" => Token::Import,
+            "mod" => Token::Mod,
+            "const" => Token::Const,
             _ => Token::Iden(tokstr),
         };
 
@@ -330,7 +602,8 @@ impl<T: BufRead> Lexer<T> {
         let lpos = self.pos;
         let mut tok = String::from(c);
         while let Some(c) = self.peek()? {
-            if c.is_whitespace() || c.is_alphanumeric() || Self::is_control(c) {
+            // no operator is longer than three chars, so a longer run is already invalid
+            if c.is_whitespace() || c.is_alphanumeric() || Self::is_control(c) || tok.len() > 3 {
                 break;
             }
             tok.push(c);
@@ -348,9 +621,12 @@ impl<T: BufRead> Lexer<T> {
             "+=" => Token::AssignOp(Aop::Plus),
             "/" => Token::Operator(Op::Divide),
             "/=" => Token::AssignOp(Aop::Divide),
+            "%" => Token::Operator(Op::Mod),
+            "%=" => Token::AssignOp(Aop::Mod),
             ":=" => Token::Declare,
             "=" => Token::Assign,
             "==" => Token::Operator(Op::Eq),
+            "!" => Token::Operator(Op::Not),
             "!=" => Token::Operator(Op::Neq),
             "<=" => Token::Operator(Op::Leq),
             "<" => Token::Operator(Op::Lt),
@@ -359,46 +635,211 @@ impl<T: BufRead> Lexer<T> {
             "&&" => Token::Operator(Op::And),
             "||" => Token::Operator(Op::Or),
             "->" => Token::Arrow,
+            "?" => Token::Question,
+            ":" => Token::Colon,
             _ => return Err(format!("Invalid token: '{}' while scanning", tok))
         };
 
         Ok(TokenContext { kind: tok, lpos, rpos: self.pos })
     }
 
+    fn scan_comment(&mut self) -> Result<TokenContext, String> {
+        let lpos = self.pos;
+        self.consume();
+        let mut str = String::new();
+        while let Some(c) = self.peek()? {
+            if c == '\n' {
+                break;
+            }
+            str.push(c);
+            self.check_len("comment", &str, self.limits.text, lpos)?;
+            self.consume()
+        }
+        Ok(TokenContext { kind: Token::Comment(str), lpos, rpos: self.pos })
+    }
+
     pub fn read_token(&mut self) -> Result<Option<TokenContext>, String> {
-        self.skip_spaces()?;
+        loop {
+            self.skip_spaces()?;
 
-        if let Some(c) = self.read()? {
-            let token = match self.match_control(c, self.pos) {
+            let c = match self.read()? {
+                Some(c) => c,
+                None => return Ok(None)
+            };
+            let lpos = self.pos;
+            let token = match self.match_control(c, lpos) {
                 Some(token) => token,
                 None => match c {
+                    '.' => self.scan_dot()?,
                     '\'' => self.scan_char()?,
                     '\"' => self.scan_string()?,
-                    _ if c.is_digit(10) => self.scan_number(c)?,
-                    _ if c.is_alphanumeric() => self.scan_keyword(c)?,
-                    _ => self.scan_special(c)?
+                    '/' if self.peek()? == Some('/') => self.scan_comment()?,
+                    _ if c.is_ascii_digit() => {
+                        let scanned = self.scan_number(c);
+                        self.recover(scanned, lpos)?
+                    }
+                    _ if Self::is_iden_start(c) => self.scan_keyword(c)?,
+                    _ => {
+                        let scanned = self.scan_special(c);
+                        self.recover(scanned, lpos)?
+                    }
                 }
             };
-            Ok(Some(token))
-        } else {
-            Ok(None)
+
+            match token.kind {
+                Token::Comment(_) if !self.comments => continue,
+                _ => return Ok(Some(token))
+            }
         }
     }
 
     pub fn read_tokens(&mut self) -> Result<VecDeque<TokenContext>, String> {
-        let mut tokens = VecDeque::new();
-        while let Some(tok) = self.read_token()? {
-            tokens.push_back(tok)
+        self.collect()
+    }
+}
+
+// yields the tokens one at a time the same as read_tokens, and stops after the first error
+impl<T: BufRead> Iterator for Lexer<T> {
+    type Item = Result<TokenContext, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(tok) = self.pending.pop_front() {
+                return Some(Ok(tok))
+            }
+            if self.failed {
+                return None
+            }
+            match self.read_token() {
+                Ok(Some(tok)) if self.newlines => self.terminator.push(tok, &mut self.pending),
+                Ok(Some(tok)) => return Some(Ok(tok)),
+                Ok(None) => return None,
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(err))
+                }
+            }
         }
-        Ok(tokens)
     }
 }
 
+// replaces a line of the source with the text, which can hold more than one line, and gives back the edited source
+// and its tokens. only the lines of the edit are lexed again, along with the lines of any token that crosses them,
+// and the tokens after the edit are moved down by the number of lines it added. the tokens are the ones a lexer
+// with the default options reads
+#[allow(dead_code)]
+pub fn relex_line(source: &str, tokens: VecDeque<TokenContext>, line: u32, text: &str) -> Result<(String, VecDeque<TokenContext>), String> {
+    let mut lines: Vec<&str> = source.split('\n').collect();
+    if line == 0 || line as usize > lines.len() {
+        return Err(format!("Cannot edit line {} of a source with {} lines", line, lines.len()))
+    }
+    let added = text.split('\n').count() as u32 - 1;
+    lines.splice(line as usize - 1..line as usize, text.split('\n'));
+
+    // a string that crosses a line of the span has to be lexed whole, and it can bring in another one that crosses
+    let (mut first, mut last) = (line, line);
+    loop {
+        let (prev_first, prev_last) = (first, last);
+        for tok in &tokens {
+            if tok.lpos.line <= last && tok.rpos.line >= first {
+                first = first.min(tok.lpos.line);
+                last = last.max(tok.rpos.line)
+            }
+        }
+        if (first, last) == (prev_first, prev_last) {
+            break;
+        }
+    }
+
+    let mut spliced = VecDeque::new();
+    let mut rest = VecDeque::new();
+    for tok in tokens {
+        if tok.rpos.line < first {
+            spliced.push_back(tok)
+        } else if tok.lpos.line > last {
+            rest.push_back(tok)
+        }
+    }
+
+    let lex_lines = |end: usize| {
+        let reader = BufReader::new(Cursor::new(lines[first as usize - 1..end].join("\n")));
+        let mut lexer = Lexer::new(reader);
+        lexer.pos.line = first;
+        lexer.read_tokens()
+    };
+    let end = (last + added) as usize;
+    let (relexed, rest) = match lex_lines(end) {
+        Ok(relexed) => (relexed, rest),
+        // the edit opened a string that goes on past the span, so the rest of the source is lexed as well
+        Err(_) if end < lines.len() => (lex_lines(lines.len())?, VecDeque::new()),
+        Err(err) => return Err(err)
+    };
+    spliced.extend(relexed);
+    for mut tok in rest {
+        tok.lpos.line += added;
+        tok.rpos.line += added;
+        spliced.push_back(tok)
+    }
+    Ok((lines.join("\n"), spliced))
+}
+
+// a newline is a semicolon when the token before it can end a statement and the token after it
+// can't continue one, so an expression can still be split before an operator or a closing bracket,
+// and the last expression of a block is still the value of the block
+#[derive(Default)]
+struct Terminator {
+    brackets: Vec<Token>,
+    last: Option<(bool, Position)>,
+}
+
+impl Terminator {
+    // adds the token to the output, after the semicolon that ends the line before it if there is one
+    fn push(&mut self, tok: TokenContext, out: &mut VecDeque<TokenContext>) {
+        if let Token::Comment(_) = tok.kind {
+            out.push_back(tok);
+            return
+        }
+        let continues = matches!(tok.kind, Token::RParen | Token::RBracket | Token::RBrace | Token::LBrace
+            | Token::Dot | Token::Range | Token::RangeInclusive | Token::Comma | Token::Arrow | Token::Else | Token::Elif
+            | Token::SemiColon | Token::Operator(_) | Token::Assign | Token::AssignOp(_) | Token::Declare
+            | Token::Question | Token::Colon);
+        let nested = matches!(self.brackets.last(), Some(Token::LParen | Token::LBracket));
+        if let Some((true, rpos)) = self.last {
+            if tok.lpos.line > rpos.line && !continues && !nested {
+                out.push_back(TokenContext { kind: Token::SemiColon, lpos: rpos, rpos })
+            }
+        }
+
+        match tok.kind {
+            Token::LParen => self.brackets.push(Token::LParen),
+            Token::LBracket => self.brackets.push(Token::LBracket),
+            Token::LBrace => self.brackets.push(Token::LBrace),
+            Token::RParen | Token::RBracket | Token::RBrace => {
+                self.brackets.pop();
+            }
+            _ => {}
+        }
+        let ends = tok.kind.is_literal() || matches!(tok.kind, Token::Iden(_) | Token::RParen | Token::RBracket
+            | Token::RBrace | Token::Break | Token::Continue);
+        self.last = Some((ends, tok.rpos));
+        out.push_back(tok)
+    }
+}
+
+#[cfg(test)]
 mod test {
     use std::collections::VecDeque;
     use std::io::{BufRead, BufReader, Cursor};
-    use crate::lexer::{Lexer, Op, Token};
-    use crate::lexer::Token::{Arrow, Assign, CharLit, Comma, Declare, Dot, Fn, Iden, IntLit, LBrace, LBracket, LParen, Operator, RBrace, RBracket, Return, RParen, SemiColon, StrLit, Struct, While};
+    use crate::lexer::{relex_line, Aop, Lexer, Limits, Op, Position, Token, TokenContext};
+    use crate::lexer::Token::{Arrow, Assign, CharLit, FloatLit, Comma, Comment, Declare, Dot, Fn, Iden, IntLit, LBrace, LBracket, LParen, Operator, RBrace, RBracket, Return, RParen, SemiColon, StrLit, Struct, While};
+
+    fn lexer(program: &str) -> Lexer<Cursor<String>> {
+        Lexer::new(BufReader::new(Cursor::new(program.to_string())))
+    }
+
+    fn lex(program: &str) -> Result<VecDeque<TokenContext>, String> {
+        lexer(program).read_tokens()
+    }
 
     fn lex_tokens<T: BufRead>(reader: BufReader<T>) -> VecDeque<Token> {
         Lexer::new(reader)
@@ -564,4 +1005,298 @@ mod test {
         ];
         assert_eq!(actual_tokens, expect_tokens)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_lex_char() {
+        let actual_tokens = lex_tokens(BufReader::new(Cursor::new("'a' '\\n'")));
+        assert_eq!(actual_tokens, vec![CharLit('a'), CharLit('\n')]);
+
+        assert!(lex("''").unwrap_err().starts_with("Invalid char: empty char literal"));
+        assert!(lex("'ab'").unwrap_err().starts_with("Invalid char: 'ab'"));
+    }
+
+    #[test]
+    fn test_lex_escaped_quotes() {
+        let actual_tokens = lex_tokens(BufReader::new(Cursor::new("\"it\\'s\" '\\\"' '\\'' \"\\\"\"")));
+        assert_eq!(actual_tokens, vec![StrLit("it's".to_string()), CharLit('"'), CharLit('\''), StrLit("\"".to_string())]);
+    }
+
+    #[test]
+    fn test_lex_comments() {
+        let program = "
+            // note
+            x := 1; // trailing
+        ";
+        let reader = BufReader::new(Cursor::new(program));
+        let actual_tokens: Vec<Token> = Lexer::new(reader)
+            .with_comments(true)
+            .read_tokens()
+            .unwrap()
+            .into_iter()
+            .map(|tok| { tok.kind })
+            .collect();
+        let expect_tokens = vec![
+            Comment(" note".to_string()),
+            Iden("x".to_string()),
+            Declare,
+            IntLit(1),
+            SemiColon,
+            Comment(" trailing".to_string()),
+        ];
+        assert_eq!(actual_tokens, expect_tokens);
+
+        let reader = BufReader::new(Cursor::new(program));
+        let actual_tokens = lex_tokens(reader);
+        let expect_tokens = vec![Iden("x".to_string()), Declare, IntLit(1), SemiColon];
+        assert_eq!(actual_tokens, expect_tokens)
+    }
+
+    #[test]
+    fn test_lex_unterminated() {
+        let err = lex("x := \"abc").unwrap_err();
+        assert_eq!(err, "Unterminated string: expected closing \" for the literal starting at line: 1, col: 6");
+
+        let err = lex("'a").unwrap_err();
+        assert_eq!(err, "Unterminated char: expected closing ' for the literal starting at line: 1, col: 1");
+    }
+
+    #[test]
+    fn test_lex_empty() {
+        assert_eq!(lex_tokens(BufReader::new(Cursor::new(""))), vec![]);
+        assert_eq!(lex_tokens(BufReader::new(Cursor::new("   \n  "))), vec![]);
+    }
+
+    #[test]
+    fn test_lex_number_range() {
+        let actual_tokens = lex_tokens(BufReader::new(Cursor::new("99999999999 1.5 2e3 0..n")));
+        assert_eq!(actual_tokens, vec![IntLit(99999999999), FloatLit(1.5), FloatLit(2000.0), IntLit(0), Token::Range, Iden("n".to_string())]);
+
+        assert!(lex("99999999999999999999").unwrap_err().starts_with("Invalid int: 99999999999999999999 is out of range for i64"));
+        assert!(lex("1e400").unwrap_err().starts_with("Invalid float: 1e400 is out of range for f64"));
+        assert!(lex("12ab").unwrap_err().starts_with("Invalid int: cannot lex 12ab"));
+    }
+
+    #[test]
+    fn test_token_predicates() {
+        assert!(IntLit(1).is_literal());
+        assert!(StrLit("a".to_string()).is_literal());
+        assert!(Token::True.is_literal());
+        assert!(!Iden("a".to_string()).is_literal());
+
+        assert!(Fn.is_keyword());
+        assert!(While.is_keyword());
+        assert!(Token::False.is_keyword());
+        assert!(!Iden("while_".to_string()).is_keyword());
+
+        assert!(Operator(Op::Plus).is_operator());
+        assert!(Token::AssignOp(Aop::Plus).is_operator());
+        assert!(!Assign.is_operator());
+        assert!(!LParen.is_operator());
+
+        assert_eq!(Operator(Op::Or).precedence(), Some(1));
+        assert_eq!(Operator(Op::Multiply).precedence(), Some(6));
+        assert!(Operator(Op::Exp).precedence() > Operator(Op::Multiply).precedence());
+        assert_eq!(Operator(Op::Not).precedence(), None);
+        assert_eq!(Comma.precedence(), None);
+    }
+
+    #[test]
+    fn test_lex_ranges() {
+        let actual_tokens = lex_tokens(BufReader::new(Cursor::new("0..3 0..=3 p.x")));
+        let expect_tokens = vec![
+            IntLit(0), Token::Range, IntLit(3),
+            IntLit(0), Token::RangeInclusive, IntLit(3),
+            Iden("p".to_string()), Dot, Iden("x".to_string())
+        ];
+        assert_eq!(actual_tokens, expect_tokens);
+    }
+
+    #[test]
+    fn test_lex_hex_escape() {
+        let actual_tokens = lex_tokens(BufReader::new(Cursor::new("'\\x41' \"\\x48i\"")));
+        assert_eq!(actual_tokens, vec![CharLit('A'), StrLit("Hi".to_string())]);
+
+        assert_eq!(lex("'\\xZZ'").unwrap_err(), "Invalid esc seq: '\\xZZ' must be followed by two hex digits");
+        assert_eq!(lex("'\\x80'").unwrap_err(), "Invalid esc seq: '\\x80' is not an ascii char, only '\\x00' to '\\x7f' are allowed");
+    }
+
+    #[test]
+    fn test_lex_limits() {
+        let limits = Limits { iden: 4, text: 4, number: 4 };
+        let lex = |program: &str| lexer(program).with_limits(limits).read_tokens();
+
+        let actual_tokens = lex("abcd \"abcd\" 1234").unwrap();
+        assert_eq!(actual_tokens.len(), 3);
+
+        assert_eq!(lex("x := abcde").unwrap_err(), "Too long: identifier exceeds the maximum length of 4 bytes starting at line: 1, col: 6");
+        assert_eq!(lex("\"abcde\"").unwrap_err(), "Too long: text literal exceeds the maximum length of 4 bytes starting at line: 1, col: 1");
+        assert_eq!(lex("12345").unwrap_err(), "Too long: number exceeds the maximum length of 4 bytes starting at line: 1, col: 1");
+    }
+
+    #[test]
+    fn test_lex_newlines() {
+        let lex = |program: &str, newlines: bool| {
+            let tokens = lexer(program).with_newlines(newlines).read_tokens().unwrap();
+            tokens.into_iter().map(|tok| tok.kind).collect::<Vec<_>>()
+        };
+
+        let with_semicolons = "
+            fn main() {
+                x := f(1,
+                    2);
+                y := x
+                    + 1;
+                y * 2
+            }
+        ";
+        let with_newlines = "
+            fn main() {
+                x := f(1,
+                    2)
+                y := x
+                    + 1
+                y * 2
+            }
+        ";
+        assert_eq!(lex(with_newlines, true), lex(with_semicolons, false));
+    }
+
+    #[test]
+    fn test_lex_random_bytes() {
+        // a fixed xorshift generator keeps the corpus the same between runs
+        let mut state: u64 = 0x2545f4914f6cdd1d;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let interesting = b"'\"\\x0.e_=<>!&|-+*/%:;(){}[], \n9aZ";
+        for _ in 0..2000 {
+            let len = (next() % 48) as usize;
+            let bytes: Vec<u8> = (0..len)
+                .map(|_| match next() % 3 {
+                    0 => next() as u8,
+                    _ => interesting[(next() % interesting.len() as u64) as usize]
+                })
+                .collect();
+            // only the absence of a panic matters, most of these inputs are errors
+            for newlines in [false, true] {
+                let reader = BufReader::new(Cursor::new(bytes.clone()));
+                let _ = Lexer::new(reader).with_comments(true).with_newlines(newlines).read_tokens();
+            }
+        }
+    }
+
+    #[test]
+    fn test_lex_multiline_string() {
+        // the newline stays in the literal, and the lines after it are counted
+        let tokens = lex("\"first\nsecond\" x").unwrap();
+        assert_eq!(tokens[0].kind, StrLit("first\nsecond".to_string()));
+        assert_eq!(tokens[0].lpos, Position { line: 1, col: 1 });
+        assert_eq!(tokens[0].rpos, Position { line: 2, col: 7 });
+        assert_eq!(tokens[1].lpos, Position { line: 2, col: 9 });
+
+        let tokens = lex("\"first \\\nsecond\" \"crlf \\\r\nline\"").unwrap();
+        assert_eq!(tokens[0].kind, StrLit("first second".to_string()));
+        assert_eq!(tokens[1].kind, StrLit("crlf line".to_string()));
+        assert_eq!(tokens[1].rpos.line, 3);
+    }
+
+    #[test]
+    fn test_lex_nil() {
+        let reader = BufReader::new(Cursor::new("x := nil; fn f(y ?int)"));
+        let tokens: Vec<Token> = Lexer::new(reader).read_tokens().unwrap().into_iter().map(|tok| tok.kind).collect();
+        assert_eq!(tokens[2], Token::Nil);
+        assert_eq!(tokens[8], Token::Question);
+        assert_eq!(tokens[9], Iden("int".to_string()));
+        assert!(Token::Nil.is_literal() && Token::Nil.is_keyword());
+    }
+
+    #[test]
+    fn test_lex_unicode() {
+        let tokens = lex("café := π * 2; _ñ1 := \"日本\"; 'é'").unwrap();
+        let kinds: Vec<&Token> = tokens.iter().map(|tok| &tok.kind).collect();
+        assert_eq!(kinds[0], &Iden("café".to_string()));
+        assert_eq!(kinds[2], &Iden("π".to_string()));
+        assert_eq!(kinds[6], &Iden("_ñ1".to_string()));
+        assert_eq!(kinds[8], &StrLit("日本".to_string()));
+        assert_eq!(kinds[10], &CharLit('é'));
+        // columns count chars rather than bytes
+        assert_eq!((tokens[0].lpos.col, tokens[0].rpos.col), (1, 4));
+        assert_eq!(tokens[1].lpos.col, 6);
+
+        // a digit can't start an identifier even in another script
+        assert!(lex("x := ٣;").is_err());
+        // a combining mark continues an identifier, but can't start one
+        let tokens = lex("cafe\u{301} := e\u{301}\u{20D7}1;").unwrap();
+        let kinds: Vec<&Token> = tokens.iter().map(|tok| &tok.kind).collect();
+        assert_eq!(kinds[0], &Iden("cafe\u{301}".to_string()));
+        assert_eq!(kinds[2], &Iden("e\u{301}\u{20D7}1".to_string()));
+        assert!(lex("x := \u{301}e;").is_err());
+        let err = lex("x := \"a").map(|_| ()).unwrap_err();
+        assert!(err.starts_with("Unterminated string"), "{}", err);
+
+        let reader = BufReader::new(Cursor::new(vec![b'x', b' ', 0xc3, b'(']));
+        let err = Lexer::new(reader).read_tokens().unwrap_err();
+        assert_eq!(err, "Invalid utf-8: cannot decode the bytes [c3, 28] after line: 1, col: 2");
+    }
+
+    #[test]
+    fn test_lex_iterator() {
+        let program = "x := 1\ny := x";
+        let kinds: Vec<Token> = lexer(program).with_newlines(true).map(|tok| tok.unwrap().kind).collect();
+        let eager: Vec<Token> = lexer(program).with_newlines(true).read_tokens().unwrap().into_iter().map(|tok| tok.kind).collect();
+        assert_eq!(kinds, eager);
+        assert_eq!(kinds[3], SemiColon);
+
+        // nothing is lexed after an error
+        let mut tokens = lexer("x 1a y").with_newlines(true);
+        assert!(matches!(tokens.next(), Some(Ok(TokenContext { kind: Iden(_), .. }))));
+        assert!(matches!(tokens.next(), Some(Err(_))));
+        assert!(tokens.next().is_none());
+    }
+
+    #[test]
+    fn test_lex_recovery() {
+        let lex = |program: &str, recovery: bool| lexer(program).with_recovery(recovery).read_tokens();
+
+        let program = "x := a <> b;\ny := 12ab;";
+        assert_eq!(lex(program, false).unwrap_err(), "Invalid token: '<>' while scanning");
+        let tokens = lex(program, true).unwrap();
+        assert_eq!((tokens[3].lpos, tokens[3].rpos), (Position { line: 1, col: 8 }, Position { line: 1, col: 9 }));
+        let kinds: Vec<Token> = tokens.into_iter().map(|tok| tok.kind).collect();
+        let expected = vec![
+            Iden("x".to_string()), Declare, Iden("a".to_string()), Token::Error("Invalid token: '<>' while scanning".to_string()),
+            Iden("b".to_string()), SemiColon, Iden("y".to_string()), Declare,
+            Token::Error("Invalid int: cannot lex 12ab between line: 2, col: 6 and line: 2, col: 9".to_string()), SemiColon,
+        ];
+        assert_eq!(kinds, expected);
+    }
+
+    #[test]
+    fn test_relex_line() {
+        // splices the tokens of the edit and checks them against lexing the edited source from the start
+        let relex = |program: &str, line: u32, text: &str| {
+            let (edited, tokens) = relex_line(program, lex(program).unwrap(), line, text).unwrap();
+            assert_eq!(tokens, lex(&edited).unwrap(), "relexing line {} of {:?} with {:?}", line, program, text);
+            edited
+        };
+        let program = "fn main() {\n    x := 1;\n    s := \"a\n    b\";\n    y := x + 2; // \"\n}";
+
+        let edited = relex(program, 2, "    x := 10 * 3;");
+        assert_eq!(edited, "fn main() {\n    x := 10 * 3;\n    s := \"a\n    b\";\n    y := x + 2; // \"\n}");
+        // the string that crosses the edited line is lexed again as a whole
+        relex(program, 4, "    b c\";");
+        // a line that opens a string pairs with the quote of the string after it, and so on up to the quote in the comment
+        relex(program, 2, "    x := \"1;");
+        // removing the quote that opens a string leaves its closing quote to open another one
+        relex(program, 3, "    s := a");
+        // an edit that adds lines moves the tokens after it down
+        relex(program, 2, "    x := 1;\n    z := 2;");
+        relex(program, 5, "}\nfn f() {}");
+
+        assert!(relex_line(program, lex(program).unwrap(), 7, "x").is_err());
+        assert!(relex_line(program, lex(program).unwrap(), 2, "    x := 12ab;").is_err());
+    }
+}
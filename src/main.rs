@@ -4,7 +4,69 @@ mod parser;
 mod interpreter;
 mod node;
 mod lexer;
+mod builtin;
+mod checker;
+mod analysis;
+mod diagnostic;
+mod optimizer;
+// the compiler and the vm are only run by their tests until the driver can pick them over the interpreter
+#[allow(dead_code)]
+mod bytecode;
+mod bigint;
 
+use std::io::{BufReader, Cursor};
+use std::{env, fs, process};
+use crate::analysis::{redeclared_variables, unreachable_code, unused_variables};
+use crate::checker::check_str;
+use crate::interpreter::Interpreter;
+use crate::lexer::Lexer;
+use crate::node::Node;
+use crate::optimizer::optimize_program;
+use crate::parser::Parser;
+
+// the likely mistakes in each function, which are reported without stopping the program from running
+fn warnings(program: &[Node]) -> Vec<String> {
+    let mut warnings = vec![];
+    for node in program {
+        if let Node::DefFunc(func) = node {
+            let unused = unused_variables(func).into_iter()
+                .map(|iden| format!("{} is declared but never read in function {}", iden, func.iden));
+            warnings.extend(unused);
+            warnings.extend(unreachable_code(func));
+            warnings.extend(redeclared_variables(func));
+        }
+    }
+    warnings
+}
+
+// checks the program in the file and only runs it from its main function when nothing was wrong with it
 fn main() {
-    println!("Hello, world!");
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("usage: howdy <file>");
+        process::exit(2)
+    };
+    let source = fs::read_to_string(&path).unwrap_or_else(|err| {
+        eprintln!("error: cannot read {}: {}", path, err);
+        process::exit(2)
+    });
+
+    if let Err(diagnostics) = check_str(&source) {
+        for diagnostic in diagnostics {
+            eprint!("{}", diagnostic.render(&source))
+        }
+        process::exit(1)
+    }
+    let result = Lexer::new(BufReader::new(Cursor::new(source.as_str())))
+        .read_tokens()
+        .and_then(|tokens| Parser::new(tokens).parse_program())
+        .and_then(|program| {
+            for warning in warnings(&program) {
+                eprintln!("warning: {}", warning)
+            }
+            Interpreter::new().interpret(&optimize_program(program)).map_err(|err| err.to_string())
+        });
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1)
+    }
 }
@@ -2,138 +2,528 @@
 // 3/15/2024
 // Abstract syntax tree for the programming language
 
-#[derive(Debug, PartialEq)]
+use std::cmp::Ordering;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use crate::bigint::BigInt;
+use crate::builtin::Builtin;
+use crate::lexer::Position;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Node {
     DefFunc(DefFuncNode),
+    DefMethod(DefMethodNode),
     DefStruct(DefStructNode),
     DefTypeAlias(DefTypeAliasNode),
     Import(ImportNode),
+    // the name of a module and the definitions within it
+    Module(String, Vec<Node>),
     Constant(Const),
     Variable(String),
     Binop(BinopNode),
     Unop(UnopNode),
     CallFunc(FuncNode),
+    Call(CallNode),
+    Index(IndexNode),
+    Slice(SliceNode),
+    Member(MemberNode),
     If(IfNode),
     Else(Vec<Node>),
     Guard(GuardNode),
+    Ternary(TernaryNode),
     While(WhileNode),
     For(ForNode),
+    Declare(String, Box<Node>),
+    // the names bound in order to the elements of a tuple
+    DeclareMany(Vec<String>, Box<Node>),
+    // a declaration that can't be assigned to afterward
+    DeclareConst(String, Box<Node>),
     Assign(String, Box<Node>),
+    Store(StoreNode),
     Return(Box<Node>),
     Break,
     Continue,
-    Func(FuncNode),
     Struct(StructNode),
     Array(Vec<Node>),
     Tuple(Vec<Node>),
-    Range(i32, i32),
-    Lambda(LambdaNode)
+    Range(RangeNode),
+    Lambda(LambdaNode),
+    Block(BlockNode),
+}
+
+impl Node {
+    // the position of the operator or the literal, only set on the nodes that the parser gives one
+    pub fn pos(&self) -> Option<Position> {
+        match self {
+            Node::Binop(node) => Some(node.pos),
+            Node::Unop(node) => Some(node.pos),
+            Node::Index(node) => Some(node.pos),
+            Node::Slice(node) => Some(node.pos),
+            Node::Member(node) => Some(node.pos),
+            Node::Struct(node) => Some(node.pos),
+            Node::Range(node) => Some(node.pos),
+            _ => None
+        }
+    }
+
+    // the position of the first node within this one that has a position, which stands in for the position
+    // of a statement since statements don't have one of their own
+    pub fn first_pos(&self) -> Option<Position> {
+        match self {
+            Node::DefFunc(func) | Node::DefMethod(DefMethodNode { func, .. }) => Some(func.pos),
+            Node::DefStruct(def) => Some(def.pos),
+            Node::DefTypeAlias(alias) => Some(alias.pos),
+            Node::Binop(node) => node.lhs.first_pos().or(Some(node.pos)),
+            Node::Unop(node) => Some(node.pos),
+            Node::Index(node) => node.base.first_pos().or(Some(node.pos)),
+            Node::Slice(node) => node.base.first_pos().or(Some(node.pos)),
+            Node::Member(node) => node.base.first_pos().or(Some(node.pos)),
+            Node::Struct(node) => Some(node.pos),
+            Node::Range(node) => node.start.first_pos().or(Some(node.pos)),
+            Node::Declare(_, expr) | Node::DeclareMany(_, expr) | Node::DeclareConst(_, expr) | Node::Assign(_, expr) | Node::Return(expr) => {
+                expr.first_pos()
+            }
+            Node::Store(node) => node.target.first_pos().or_else(|| node.value.first_pos()),
+            Node::If(node) => node.cond.first_pos(),
+            Node::Guard(node) => node.cond.first_pos(),
+            Node::Ternary(node) => node.cond.first_pos().or_else(|| node.then.first_pos()).or_else(|| node.otherwise.first_pos()),
+            Node::While(node) => node.cond.first_pos(),
+            Node::For(node) => node.collection.first_pos(),
+            Node::Call(node) => node.callee.first_pos().or_else(|| node.args.iter().find_map(Node::first_pos)),
+            Node::CallFunc(node) => node.args.iter().find_map(Node::first_pos),
+            Node::Array(elems) | Node::Tuple(elems) | Node::Else(elems) => elems.iter().find_map(Node::first_pos),
+            _ => None
+        }
+    }
+
+    // what kind of node this is, for the errors about a node that can't be used where it is
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Node::DefFunc(_) => "a nested function",
+            Node::DefMethod(_) => "a method definition",
+            Node::DefStruct(_) => "a struct definition",
+            Node::DefTypeAlias(_) => "a type alias",
+            Node::Import(_) => "an import",
+            Node::Module(_, _) => "a module",
+            Node::Constant(_) => "a constant",
+            Node::Variable(_) => "a variable",
+            Node::Binop(_) => "a binary operator",
+            Node::Unop(_) => "a unary operator",
+            Node::CallFunc(_) => "a call",
+            Node::Call(_) => "a call to a value",
+            Node::Index(_) => "an index",
+            Node::Slice(_) => "a slice",
+            Node::Member(_) => "a member access",
+            Node::If(_) => "an if statement",
+            Node::Else(_) => "an else statement",
+            Node::Guard(_) => "a guard",
+            Node::Ternary(_) => "a ternary",
+            Node::While(_) => "a while loop",
+            Node::For(_) => "a for loop",
+            Node::Declare(_, _) | Node::DeclareMany(_, _) | Node::DeclareConst(_, _) => "a declaration",
+            Node::Assign(_, _) => "an assignment",
+            Node::Store(_) => "a store",
+            Node::Return(_) => "a return",
+            Node::Break => "a break",
+            Node::Continue => "a continue",
+            Node::Struct(_) => "a struct literal",
+            Node::Array(_) => "an array literal",
+            Node::Tuple(_) => "a tuple",
+            Node::Range(_) => "a range",
+            Node::Lambda(_) => "a lambda",
+            Node::Block(_) => "a block",
+        }
+    }
+}
+
+// compares two trees without the positions they were parsed at, so the same code written on another line is equal
+#[cfg(test)]
+pub fn structural_eq(a: &Node, b: &Node) -> bool {
+    let all = |a: &[Node], b: &[Node]| a.len() == b.len() && a.iter().zip(b).all(|(a, b)| structural_eq(a, b));
+    let func_eq = |a: &DefFuncNode, b: &DefFuncNode| {
+        a.iden == b.iden && a.type_params == b.type_params && a.args == b.args && a.ret == b.ret && all(&a.body, &b.body)
+    };
+    match (a, b) {
+        (Node::DefFunc(a), Node::DefFunc(b)) => func_eq(a, b),
+        (Node::DefMethod(a), Node::DefMethod(b)) => a.recv == b.recv && a.recv_type == b.recv_type && func_eq(&a.func, &b.func),
+        (Node::DefStruct(a), Node::DefStruct(b)) => a.iden == b.iden && a.fields == b.fields,
+        (Node::DefTypeAlias(a), Node::DefTypeAlias(b)) => a.iden == b.iden && a.type_node == b.type_node,
+        (Node::Module(a_iden, a), Node::Module(b_iden, b)) => a_iden == b_iden && all(a, b),
+        (Node::Binop(a), Node::Binop(b)) => a.op == b.op && structural_eq(&a.lhs, &b.lhs) && structural_eq(&a.rhs, &b.rhs),
+        (Node::Unop(a), Node::Unop(b)) => a.op == b.op && structural_eq(&a.expr, &b.expr),
+        (Node::CallFunc(a), Node::CallFunc(b)) => a.iden == b.iden && all(&a.args, &b.args),
+        (Node::Call(a), Node::Call(b)) => structural_eq(&a.callee, &b.callee) && all(&a.args, &b.args),
+        (Node::Index(a), Node::Index(b)) => structural_eq(&a.base, &b.base) && structural_eq(&a.index, &b.index),
+        (Node::Slice(a), Node::Slice(b)) => {
+            let bound_eq = |a: &Option<Box<Node>>, b: &Option<Box<Node>>| match (a, b) {
+                (Some(a), Some(b)) => structural_eq(a, b),
+                (a, b) => a.is_none() && b.is_none()
+            };
+            a.inclusive == b.inclusive && structural_eq(&a.base, &b.base) && bound_eq(&a.start, &b.start) && bound_eq(&a.end, &b.end)
+        }
+        (Node::Member(a), Node::Member(b)) => a.field == b.field && structural_eq(&a.base, &b.base),
+        (Node::If(a), Node::If(b)) => structural_eq(&a.cond, &b.cond) && all(&a.body, &b.body),
+        (Node::Guard(a), Node::Guard(b)) => structural_eq(&a.cond, &b.cond) && structural_eq(&a.this, &b.this),
+        (Node::Ternary(a), Node::Ternary(b)) => {
+            structural_eq(&a.cond, &b.cond) && structural_eq(&a.then, &b.then) && structural_eq(&a.otherwise, &b.otherwise)
+        }
+        (Node::While(a), Node::While(b)) => structural_eq(&a.cond, &b.cond) && all(&a.body, &b.body),
+        (Node::For(a), Node::For(b)) => {
+            a.element == b.element && a.index == b.index && structural_eq(&a.collection, &b.collection) && all(&a.body, &b.body)
+        }
+        (Node::Declare(a_iden, a), Node::Declare(b_iden, b))
+            | (Node::DeclareConst(a_iden, a), Node::DeclareConst(b_iden, b))
+            | (Node::Assign(a_iden, a), Node::Assign(b_iden, b)) => {
+            a_iden == b_iden && structural_eq(a, b)
+        }
+        (Node::DeclareMany(a_idens, a), Node::DeclareMany(b_idens, b)) => a_idens == b_idens && structural_eq(a, b),
+        (Node::Store(a), Node::Store(b)) => structural_eq(&a.target, &b.target) && structural_eq(&a.value, &b.value),
+        (Node::Return(a), Node::Return(b)) => structural_eq(a, b),
+        (Node::Struct(a), Node::Struct(b)) => {
+            a.iden == b.iden && a.fields.len() == b.fields.len()
+                && a.fields.iter().zip(&b.fields).all(|((a_field, a), (b_field, b))| a_field == b_field && structural_eq(a, b))
+        }
+        (Node::Else(a), Node::Else(b)) | (Node::Array(a), Node::Array(b)) | (Node::Tuple(a), Node::Tuple(b)) => all(a, b),
+        (Node::Range(a), Node::Range(b)) => {
+            a.inclusive == b.inclusive && structural_eq(&a.start, &b.start) && structural_eq(&a.end, &b.end)
+        }
+        (Node::Lambda(a), Node::Lambda(b)) => a.args == b.args && structural_eq(&a.body, &b.body),
+        (Node::Block(a), Node::Block(b)) => all(&a.body, &b.body) && match (&a.value, &b.value) {
+            (Some(a), Some(b)) => structural_eq(a, b),
+            (a, b) => a.is_none() && b.is_none()
+        },
+        // the rest of the nodes have no positions within them
+        (a, b) => a == b
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TypeNode {
     Array(Box<TypeNode>),
+    // a value of the type or nil
+    Optional(Box<TypeNode>),
     Fn(Vec<TypeNode>, Option<Box<TypeNode>>),
+    Tuple(Vec<TypeNode>),
     Iden(String),
 }
 
-#[derive(Debug, PartialEq)]
+impl TypeNode {
+    // replaces every alias within the type with the type it names, an alias that is used within its own
+    // definition is left as its name where it comes up again, so a cyclic alias still resolves to a finite type
+    pub fn resolve(&self, aliases: &[DefTypeAliasNode]) -> TypeNode {
+        self.resolve_within(aliases, &mut vec![])
+    }
+
+    fn resolve_within<'a>(&self, aliases: &'a [DefTypeAliasNode], expanding: &mut Vec<&'a str>) -> TypeNode {
+        match self {
+            TypeNode::Array(elem) => TypeNode::Array(Box::new(elem.resolve_within(aliases, expanding))),
+            TypeNode::Optional(elem) => TypeNode::Optional(Box::new(elem.resolve_within(aliases, expanding))),
+            TypeNode::Fn(args, ret) => {
+                let args = args.iter().map(|arg| arg.resolve_within(aliases, expanding)).collect();
+                let ret = ret.as_ref().map(|ret| Box::new(ret.resolve_within(aliases, expanding)));
+                TypeNode::Fn(args, ret)
+            }
+            TypeNode::Tuple(elems) => TypeNode::Tuple(elems.iter().map(|elem| elem.resolve_within(aliases, expanding)).collect()),
+            TypeNode::Iden(iden) => match aliases.iter().find(|alias| &alias.iden == iden) {
+                Some(alias) if !expanding.contains(&alias.iden.as_str()) => {
+                    expanding.push(&alias.iden);
+                    let type_node = alias.type_node.resolve_within(aliases, expanding);
+                    expanding.pop();
+                    type_node
+                }
+                _ => self.clone()
+            }
+        }
+    }
+
+    // checks if a value of this type is acceptable where the other is expected, both types are expected to be resolved
+    pub fn assignable_to(&self, other: &TypeNode) -> bool {
+        match (self, other) {
+            (TypeNode::Array(lhs), TypeNode::Array(rhs)) => lhs == rhs,
+            // a value can be used where an optional of its type is expected, but not the other way
+            (TypeNode::Optional(lhs), TypeNode::Optional(rhs)) => lhs == rhs,
+            (lhs, TypeNode::Optional(rhs)) => lhs.assignable_to(rhs),
+            (TypeNode::Fn(lhs_args, lhs_ret), TypeNode::Fn(rhs_args, rhs_ret)) => {
+                lhs_args == rhs_args && lhs_ret == rhs_ret
+            }
+            (TypeNode::Tuple(lhs), TypeNode::Tuple(rhs)) => {
+                lhs.len() == rhs.len() && lhs.iter().zip(rhs.iter()).all(|(lhs, rhs)| lhs.assignable_to(rhs))
+            }
+            (TypeNode::Iden(lhs), TypeNode::Iden(rhs)) => lhs == rhs,
+            _ => false
+        }
+    }
+}
+
+// renders a type the way it is written in source
+impl Display for TypeNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeNode::Array(elem) => write!(f, "[]{}", elem),
+            TypeNode::Optional(elem) => write!(f, "?{}", elem),
+            TypeNode::Fn(args, ret) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                match ret {
+                    Some(ret) => write!(f, "fn({}) -> {}", args.join(", "), ret),
+                    None => write!(f, "fn({})", args.join(", "))
+                }
+            }
+            TypeNode::Tuple(elems) => {
+                let elems: Vec<String> = elems.iter().map(|elem| elem.to_string()).collect();
+                write!(f, "({})", elems.join(", "))
+            }
+            TypeNode::Iden(iden) => write!(f, "{}", iden)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct DefFuncNode {
     pub iden: String,
+    // the names in brackets after the function name, which stand for types in the signature and body
+    pub type_params: Vec<String>,
     pub args: Vec<(String, TypeNode)>,
     pub ret: Option<TypeNode>,
-    pub body: Vec<Node>
+    pub body: Vec<Node>,
+    // the position of the name, where an error in the definition is reported when nothing inside it has one
+    pub pos: Position
 }
 
-#[derive(Debug, PartialEq)]
+// a function defined on a struct, called on a value of the struct which is bound to the receiver
+#[derive(Debug, Clone, PartialEq)]
+pub struct DefMethodNode {
+    pub recv: String,
+    pub recv_type: String,
+    pub func: DefFuncNode
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct DefStructNode {
     pub iden: String,
-    pub fields: Vec<(String, TypeNode)>
+    pub fields: Vec<(String, TypeNode)>,
+    pub pos: Position
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct DefTypeAliasNode {
     pub iden: String,
-    pub type_node: TypeNode
+    pub type_node: TypeNode,
+    pub pos: Position
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ImportNode {
-    pub iden: String
+    pub iden: String,
+    pub alias: Option<String>
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct IfNode {
     pub cond: Box<Node>,
     pub body: Vec<Node>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct GuardNode {
     pub cond: Box<Node>,
     pub this: Box<Node>,
 }
 
-#[derive(Debug, PartialEq)]
+// only the branch chosen by the condition is evaluated
+#[derive(Debug, Clone, PartialEq)]
+pub struct TernaryNode {
+    pub cond: Box<Node>,
+    pub then: Box<Node>,
+    pub otherwise: Box<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct WhileNode {
     pub cond: Box<Node>,
     pub body: Vec<Node>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ForNode {
     pub element: String,
     pub index: Option<String>,
     pub collection: Box<Node>,
+    pub body: Vec<Node>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FuncNode {
     pub iden: String,
     pub args: Vec<Node>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallNode {
+    pub callee: Box<Node>,
+    pub args: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexNode {
+    pub base: Box<Node>,
+    pub index: Box<Node>,
+    pub pos: Position,
+}
+
+// a missing start slices from the beginning and a missing end slices to the end
+#[derive(Debug, Clone, PartialEq)]
+pub struct SliceNode {
+    pub base: Box<Node>,
+    pub start: Option<Box<Node>>,
+    pub end: Option<Box<Node>>,
+    pub inclusive: bool,
+    pub pos: Position,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberNode {
+    pub base: Box<Node>,
+    pub field: String,
+    pub pos: Position,
+}
+
+// an assignment to an element of an array or a field of a struct
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoreNode {
+    pub target: Box<Node>,
+    pub value: Box<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct StructNode {
     pub iden: String,
-    pub fields: Vec<(String, TypeNode)>,
+    pub fields: Vec<(String, Node)>,
+    pub pos: Position,
+}
+
+// a block's value is its trailing expression, written without a ';'
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockNode {
+    pub body: Vec<Node>,
+    pub value: Option<Box<Node>>,
+}
+
+// a range includes its start and only includes its end when inclusive, it is empty when the start is past the end
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeNode {
+    pub start: Box<Node>,
+    pub end: Box<Node>,
+    pub inclusive: bool,
+    pub pos: Position,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct LambdaNode {
     pub args: Vec<(String, Option<TypeNode>)>,
     pub body: Box<Node>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct BinopNode {
     pub op: Bop,
     pub lhs: Box<Node>,
     pub rhs: Box<Node>,
+    pub pos: Position,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct UnopNode {
     pub op: Uop,
     pub expr: Box<Node>,
+    pub pos: Position,
 }
 
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Const {
-    Int(i32),
+    Int(i64),
+    // an int outside of the range of an i64, only made by an interpreter with bigint enabled
+    BigInt(BigInt),
     Float(f64),
     Bool(bool),
     Char(char),
     String(String),
+    Tuple(Vec<Const>),
+    Array(Vec<Const>),
+    Struct(String, Vec<(String, Const)>),
+    // the start and end of a range, and whether the end is included
+    Range(i64, i64, bool),
+    Closure(Closure),
+    Func(Rc<DefFuncNode>),
+    Builtin(Builtin),
+    Nil,
+}
+
+// only values of the same primitive type have an ordering
+impl PartialOrd for Const {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Const::Int(lhs), Const::Int(rhs)) => lhs.partial_cmp(rhs),
+            (Const::BigInt(lhs), Const::BigInt(rhs)) => lhs.partial_cmp(rhs),
+            (Const::BigInt(lhs), Const::Int(rhs)) => lhs.partial_cmp(&BigInt::from(*rhs)),
+            (Const::Int(lhs), Const::BigInt(rhs)) => BigInt::from(*lhs).partial_cmp(rhs),
+            (Const::Float(lhs), Const::Float(rhs)) => lhs.partial_cmp(rhs),
+            (Const::Bool(lhs), Const::Bool(rhs)) => lhs.partial_cmp(rhs),
+            (Const::Char(lhs), Const::Char(rhs)) => lhs.partial_cmp(rhs),
+            (Const::String(lhs), Const::String(rhs)) => lhs.partial_cmp(rhs),
+            _ => None
+        }
+    }
+}
+
+impl Display for Const {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Const::Int(int) => write!(f, "{}", int),
+            Const::BigInt(int) => write!(f, "{}", int),
+            // debug formatting keeps the decimal point of whole floats and still prints the shortest exact digits
+            Const::Float(float) if float.is_nan() => write!(f, "nan"),
+            Const::Float(float) if float.is_infinite() => write!(f, "{}", if *float > 0.0 { "inf" } else { "-inf" }),
+            Const::Float(float) => write!(f, "{:?}", float),
+            Const::Bool(b) => write!(f, "{}", b),
+            Const::Char(c) => write!(f, "{}", c),
+            Const::String(str) => write!(f, "{}", str),
+            Const::Tuple(elems) => {
+                let elems: Vec<String> = elems.iter().map(|elem| elem.to_string()).collect();
+                write!(f, "({})", elems.join(", "))
+            }
+            Const::Array(elems) => {
+                let elems: Vec<String> = elems.iter().map(|elem| elem.to_string()).collect();
+                write!(f, "[{}]", elems.join(", "))
+            }
+            Const::Struct(iden, fields) => {
+                let fields: Vec<String> = fields.iter().map(|(field, value)| format!("{} = {}", field, value)).collect();
+                write!(f, "{}{{ {} }}", iden, fields.join(", "))
+            }
+            Const::Range(start, end, inclusive) => write!(f, "{}..{}{}", start, if *inclusive { "=" } else { "" }, end),
+            Const::Closure(_) => write!(f, "<closure>"),
+            Const::Func(func) => write!(f, "<fn {}>", func.iden),
+            Const::Builtin(builtin) => write!(f, "<builtin {:?}>", builtin),
+            Const::Nil => write!(f, "nil"),
+        }
+    }
+}
+
+// a lambda paired with a snapshot of the scope it was defined in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Closure {
+    pub lambda: Rc<LambdaNode>,
+    pub env: Vec<(String, Const)>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Bop {
     Plus,
     Exp,
     Minus,
     Multiply,
     Divide,
+    Mod,
     Eq,
     Neq,
     Leq,
@@ -144,8 +534,77 @@ pub enum Bop {
     Or,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Uop {
     Not,
     Minus
 }
+
+#[cfg(test)]
+mod test {
+    use crate::lexer::Position;
+    use crate::node::{Const, DefTypeAliasNode, TypeNode};
+
+    fn iden(iden: &str) -> TypeNode {
+        TypeNode::Iden(iden.to_string())
+    }
+
+    #[test]
+    fn test_assignable_array() {
+        let int_array = TypeNode::Array(Box::new(iden("int")));
+        let bool_array = TypeNode::Array(Box::new(iden("bool")));
+
+        assert!(int_array.assignable_to(&int_array));
+        assert!(!int_array.assignable_to(&bool_array));
+    }
+
+    #[test]
+    fn test_assignable_alias() {
+        let aliases = vec![DefTypeAliasNode { iden: "Id".to_string(), type_node: iden("int"), pos: Position { line: 1, col: 1 } }];
+        let id_fn = TypeNode::Fn(vec![iden("Id")], Some(Box::new(iden("bool"))));
+        let int_fn = TypeNode::Fn(vec![iden("int")], Some(Box::new(iden("bool"))));
+
+        assert!(!iden("Id").assignable_to(&iden("int")));
+        assert!(iden("Id").resolve(&aliases).assignable_to(&iden("int")));
+        assert!(id_fn.resolve(&aliases).assignable_to(&int_fn));
+        assert!(!int_fn.assignable_to(&TypeNode::Fn(vec![iden("int")], None)));
+    }
+
+    #[test]
+    fn test_resolve_cyclic_alias() {
+        let alias = |name: &str, type_node: TypeNode| DefTypeAliasNode { iden: name.to_string(), type_node, pos: Position { line: 1, col: 1 } };
+        let aliases = vec![
+            alias("A", TypeNode::Tuple(vec![iden("int"), iden("B")])),
+            alias("B", TypeNode::Array(Box::new(iden("A")))),
+            alias("Self", TypeNode::Optional(Box::new(iden("Self")))),
+            alias("Id", iden("int")),
+        ];
+
+        // the expansion stops at the alias that is already being expanded
+        assert_eq!(iden("A").resolve(&aliases).to_string(), "(int, []A)");
+        assert_eq!(iden("B").resolve(&aliases).to_string(), "[](int, B)");
+        assert_eq!(iden("Self").resolve(&aliases).to_string(), "?Self");
+        // an alias used twice but not within itself is expanded both times
+        let pair = TypeNode::Tuple(vec![iden("Id"), iden("Id")]);
+        assert_eq!(pair.resolve(&aliases).to_string(), "(int, int)");
+    }
+
+    #[test]
+    fn test_display_type() {
+        let func = TypeNode::Fn(vec![iden("int")], Some(Box::new(iden("bool"))));
+        assert_eq!(TypeNode::Array(Box::new(func)).to_string(), "[]fn(int) -> bool");
+        assert_eq!(TypeNode::Fn(vec![iden("int"), iden("char")], None).to_string(), "fn(int, char)");
+        assert_eq!(TypeNode::Tuple(vec![iden("int"), iden("string")]).to_string(), "(int, string)");
+    }
+
+    #[test]
+    fn test_display_float() {
+        assert_eq!(Const::Float(1.0).to_string(), "1.0");
+        assert_eq!(Const::Float(0.5).to_string(), "0.5");
+        assert_eq!(Const::Float(0.1).to_string(), "0.1");
+        assert_eq!(Const::Float(-2.0).to_string(), "-2.0");
+        assert_eq!(Const::Float(f64::INFINITY).to_string(), "inf");
+        assert_eq!(Const::Float(f64::NEG_INFINITY).to_string(), "-inf");
+        assert_eq!(Const::Float(f64::NAN).to_string(), "nan");
+    }
+}
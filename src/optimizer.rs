@@ -0,0 +1,286 @@
+// Rewrites function bodies before they are interpreted, folding expressions that only use constants
+
+use std::mem;
+use crate::interpreter::Interpreter;
+use crate::node::{BinopNode, BlockNode, CallNode, Const, DefFuncNode, DefMethodNode, ForNode, FuncNode, GuardNode, IfNode, IndexNode, LambdaNode, MemberNode, Node, RangeNode, SliceNode, StoreNode, StructNode, TernaryNode, UnopNode, WhileNode};
+
+struct Optimizer {
+    // the names that are bound exactly once in the function and never written to afterward
+    fixed: Vec<String>,
+    consts: Vec<(String, Const)>,
+}
+
+pub fn optimize_program(program: Vec<Node>) -> Vec<Node> {
+    program.into_iter()
+        .map(|node| match node {
+            Node::DefFunc(func) => Node::DefFunc(optimize(func)),
+            Node::DefMethod(method) => Node::DefMethod(DefMethodNode { func: optimize(method.func), ..method }),
+            Node::Module(iden, body) => Node::Module(iden, optimize_program(body)),
+            node => node
+        })
+        .collect()
+}
+
+// a declaration in the top level of the body always runs before the statements after it, so when its value
+// is a constant and the name is never bound again or reassigned the constant replaces each later read
+pub fn optimize(func: DefFuncNode) -> DefFuncNode {
+    let mut bound = vec![];
+    let mut written = vec![];
+    for (iden, _) in &func.args {
+        bound.push(iden.clone())
+    }
+    for node in &func.body {
+        bindings(node, &mut bound, &mut written)
+    }
+    let fixed = bound.iter()
+        .filter(|iden| bound.iter().filter(|other| other == iden).count() == 1 && !written.contains(iden))
+        .cloned()
+        .collect();
+
+    let mut optimizer = Optimizer { fixed, consts: vec![] };
+    let body = func.body.into_iter()
+        .map(|node| match node {
+            Node::Declare(iden, rhs) => {
+                let rhs = optimizer.declare(&iden, *rhs);
+                Node::Declare(iden, Box::new(rhs))
+            }
+            Node::DeclareConst(iden, rhs) => {
+                let rhs = optimizer.declare(&iden, *rhs);
+                Node::DeclareConst(iden, Box::new(rhs))
+            }
+            node => optimizer.expr(node)
+        })
+        .collect();
+    DefFuncNode { body, ..func }
+}
+
+// collects every name a function binds and every name it writes to, a nested function has its own names
+fn bindings(node: &Node, bound: &mut Vec<String>, written: &mut Vec<String>) {
+    match node {
+        Node::Declare(iden, _) | Node::DeclareConst(iden, _) => bound.push(iden.clone()),
+        Node::DeclareMany(idens, _) => bound.extend(idens.iter().cloned()),
+        Node::Assign(iden, _) => written.push(iden.clone()),
+        Node::Store(node) => {
+            let mut target = node.target.as_ref();
+            while let Node::Index(IndexNode { base, .. }) | Node::Member(MemberNode { base, .. }) = target {
+                target = base
+            }
+            if let Node::Variable(iden) = target {
+                written.push(iden.clone())
+            }
+        }
+        Node::For(node) => {
+            bound.push(node.element.clone());
+            bound.extend(node.index.clone())
+        }
+        Node::Lambda(node) => bound.extend(node.args.iter().map(|(iden, _)| iden.clone())),
+        Node::DefFunc(func) => {
+            bound.push(func.iden.clone());
+            return
+        }
+        _ => {}
+    }
+    for child in children(node) {
+        bindings(child, bound, written)
+    }
+}
+
+fn children(node: &Node) -> Vec<&Node> {
+    match node {
+        Node::Binop(node) => vec![&node.lhs, &node.rhs],
+        Node::Unop(node) => vec![&node.expr],
+        Node::CallFunc(node) => node.args.iter().collect(),
+        Node::Call(node) => [node.callee.as_ref()].into_iter().chain(&node.args).collect(),
+        Node::Index(node) => vec![&node.base, &node.index],
+        Node::Slice(node) => [node.base.as_ref()].into_iter().chain(node.start.as_deref()).chain(node.end.as_deref()).collect(),
+        Node::Member(node) => vec![&node.base],
+        Node::If(node) => [node.cond.as_ref()].into_iter().chain(&node.body).collect(),
+        Node::Guard(node) => vec![&node.cond, &node.this],
+        Node::Ternary(node) => vec![&node.cond, &node.then, &node.otherwise],
+        Node::While(node) => [node.cond.as_ref()].into_iter().chain(&node.body).collect(),
+        Node::For(node) => [node.collection.as_ref()].into_iter().chain(&node.body).collect(),
+        Node::Declare(_, expr) | Node::DeclareMany(_, expr) | Node::DeclareConst(_, expr) | Node::Assign(_, expr) | Node::Return(expr) => {
+            vec![expr]
+        }
+        Node::Store(node) => vec![&node.target, &node.value],
+        Node::Struct(node) => node.fields.iter().map(|(_, value)| value).collect(),
+        Node::Else(nodes) | Node::Array(nodes) | Node::Tuple(nodes) => nodes.iter().collect(),
+        Node::Range(node) => vec![&node.start, &node.end],
+        Node::Lambda(node) => vec![&node.body],
+        Node::Block(node) => node.body.iter().chain(node.value.as_deref()).collect(),
+        _ => vec![]
+    }
+}
+
+impl Optimizer {
+    fn declare(&mut self, iden: &str, rhs: Node) -> Node {
+        let rhs = self.expr(rhs);
+        if let (Node::Constant(c), true) = (&rhs, self.fixed.iter().any(|fixed| fixed == iden)) {
+            self.consts.push((iden.to_string(), c.clone()))
+        }
+        rhs
+    }
+
+    fn body(&mut self, body: Vec<Node>) -> Vec<Node> {
+        body.into_iter().map(|node| self.expr(node)).collect()
+    }
+
+    // rewrites the node in place so the allocation of the box is reused
+    fn boxed(&mut self, mut node: Box<Node>) -> Box<Node> {
+        let expr = mem::replace(node.as_mut(), Node::Break);
+        *node = self.expr(expr);
+        node
+    }
+
+    // an operator applied to constants is replaced with its value, unless applying it fails,
+    // so the error is still reported when the program runs
+    fn expr(&mut self, node: Node) -> Node {
+        match node {
+            Node::Variable(iden) => match self.consts.iter().find(|(name, _)| name == &iden) {
+                Some((_, c)) => Node::Constant(c.clone()),
+                None => Node::Variable(iden)
+            },
+            Node::Binop(node) => {
+                let lhs = self.boxed(node.lhs);
+                let rhs = self.boxed(node.rhs);
+                if let (Node::Constant(l), Node::Constant(r)) = (lhs.as_ref(), rhs.as_ref()) {
+                    if let Ok(c) = Interpreter::binary_op(&node.op, l.clone(), r.clone()) {
+                        return Node::Constant(c)
+                    }
+                }
+                Node::Binop(BinopNode { lhs, rhs, ..node })
+            }
+            Node::Unop(node) => {
+                let expr = self.boxed(node.expr);
+                if let Node::Constant(c) = expr.as_ref() {
+                    if let Ok(c) = Interpreter::unary_op(&node.op, c.clone()) {
+                        return Node::Constant(c)
+                    }
+                }
+                Node::Unop(UnopNode { expr, ..node })
+            }
+            Node::CallFunc(node) => Node::CallFunc(FuncNode { iden: node.iden, args: self.body(node.args) }),
+            Node::Call(node) => Node::Call(CallNode { callee: self.boxed(node.callee), args: self.body(node.args) }),
+            Node::Index(node) => Node::Index(IndexNode { base: self.boxed(node.base), index: self.boxed(node.index), ..node }),
+            Node::Slice(node) => {
+                let base = self.boxed(node.base);
+                let start = node.start.map(|start| self.boxed(start));
+                let end = node.end.map(|end| self.boxed(end));
+                Node::Slice(SliceNode { base, start, end, ..node })
+            }
+            Node::Member(node) => Node::Member(MemberNode { base: self.boxed(node.base), ..node }),
+            Node::If(node) => Node::If(IfNode { cond: self.boxed(node.cond), body: self.body(node.body) }),
+            Node::Else(body) => Node::Else(self.body(body)),
+            Node::Guard(node) => Node::Guard(GuardNode { cond: self.boxed(node.cond), this: self.boxed(node.this) }),
+            // a constant condition always chooses the same branch
+            Node::Ternary(node) => {
+                let cond = self.boxed(node.cond);
+                match cond.as_ref() {
+                    Node::Constant(Const::Bool(true)) => self.expr(*node.then),
+                    Node::Constant(Const::Bool(false)) => self.expr(*node.otherwise),
+                    _ => Node::Ternary(TernaryNode { cond, then: self.boxed(node.then), otherwise: self.boxed(node.otherwise) })
+                }
+            }
+            Node::While(node) => Node::While(WhileNode { cond: self.boxed(node.cond), body: self.body(node.body) }),
+            Node::For(node) => {
+                let collection = self.boxed(node.collection);
+                Node::For(ForNode { collection, body: self.body(node.body), ..node })
+            }
+            Node::Declare(iden, rhs) => Node::Declare(iden, self.boxed(rhs)),
+            Node::DeclareConst(iden, rhs) => Node::DeclareConst(iden, self.boxed(rhs)),
+            Node::DeclareMany(idens, rhs) => Node::DeclareMany(idens, self.boxed(rhs)),
+            Node::Assign(iden, rhs) => Node::Assign(iden, self.boxed(rhs)),
+            Node::Store(node) => Node::Store(StoreNode { target: self.boxed(node.target), value: self.boxed(node.value) }),
+            Node::Return(expr) => Node::Return(self.boxed(expr)),
+            Node::Struct(node) => {
+                let fields = node.fields.into_iter().map(|(field, value)| (field, self.expr(value))).collect();
+                Node::Struct(StructNode { fields, ..node })
+            }
+            Node::Array(elems) => Node::Array(self.body(elems)),
+            Node::Tuple(elems) => Node::Tuple(self.body(elems)),
+            Node::Range(node) => Node::Range(RangeNode { start: self.boxed(node.start), end: self.boxed(node.end), ..node }),
+            Node::Lambda(node) => Node::Lambda(LambdaNode { body: self.boxed(node.body), ..node }),
+            Node::Block(node) => {
+                let body = self.body(node.body);
+                let value = node.value.map(|value| self.boxed(value));
+                Node::Block(BlockNode { body, value })
+            }
+            // a nested function can't see the constants of the enclosing function
+            Node::DefFunc(func) => Node::DefFunc(optimize(func)),
+            node => node
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufReader, Cursor};
+    use crate::lexer::Lexer;
+    use crate::node::{Const, DefFuncNode, Node};
+    use crate::optimizer::optimize_program;
+    use crate::parser::Parser;
+
+    fn optimize(program: &str) -> DefFuncNode {
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+        let nodes = Parser::new(tokens).parse_program().unwrap();
+        match optimize_program(nodes).remove(0) {
+            Node::DefFunc(func) => func,
+            node => panic!("expected a function definition, got {:?}", node)
+        }
+    }
+
+    #[test]
+    fn test_propagate_constants() {
+        let func = optimize("
+            fn f() -> int {
+                x := 2;
+                return x * 3;
+            }
+        ");
+        assert_eq!(func.body[1], Node::Return(Box::new(Node::Constant(Const::Int(6)))));
+
+        let func = optimize("
+            fn f() -> int {
+                x := 2 + 1;
+                y := x * 3 - 1;
+                return y;
+            }
+        ");
+        assert_eq!(func.body[1], Node::Declare("y".to_string(), Box::new(Node::Constant(Const::Int(8)))));
+        assert_eq!(func.body[2], Node::Return(Box::new(Node::Constant(Const::Int(8)))));
+    }
+
+    #[test]
+    fn test_reassigned_not_propagated() {
+        let func = optimize("
+            fn f(n int) -> int {
+                x := 2;
+                if n > 0 {
+                    x = n;
+                }
+                y := 1;
+                y := 5;
+                return x + y;
+            }
+        ");
+        let Node::Return(expr) = &func.body[func.body.len() - 1] else { panic!("expected a return") };
+        assert!(matches!(expr.as_ref(), Node::Binop(_)));
+
+        // a failing operation is left for the interpreter to report
+        let func = optimize("
+            fn f() -> int {
+                return 1 / 0;
+            }
+        ");
+        assert!(matches!(&func.body[0], Node::Return(expr) if matches!(expr.as_ref(), Node::Binop(_))));
+
+        // so is an operation that overflows an int
+        let func = optimize("
+            fn f() -> int {
+                return 9223372036854775807 + 1;
+            }
+        ");
+        assert!(matches!(&func.body[0], Node::Return(expr) if matches!(expr.as_ref(), Node::Binop(_))));
+    }
+}
@@ -1,26 +1,145 @@
 use std::collections::VecDeque;
-use crate::lexer::{TokenContext, Token};
-use crate::node::{DefFuncNode, DefStructNode, DefTypeAliasNode, ImportNode, Node, TypeNode};
+use std::mem;
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{TokenContext, Token, Op, Position, Aop};
+use crate::node::{BinopNode, BlockNode, Bop, CallNode, Const, DefFuncNode, DefMethodNode, DefStructNode, DefTypeAliasNode, ForNode, FuncNode, GuardNode, IfNode, ImportNode, IndexNode, LambdaNode, MemberNode, Node, RangeNode, SliceNode, StoreNode, StructNode, TernaryNode, TypeNode, UnopNode, Uop, WhileNode};
+
+// the most tokens any rule looks at before taking the next one
+const LOOKAHEAD: usize = 3;
+
+type TokenSource = Box<dyn Iterator<Item = Result<TokenContext, String>>>;
+
+// the tokens left to parse, with lookahead past the next token for rules that need to disambiguate
+pub struct TokenStream {
+    tokens: VecDeque<TokenContext>,
+    // the tokens that haven't been lexed yet, which are pulled in as the buffer runs low on lookahead
+    source: Option<TokenSource>,
+    // the error that ended the source, reported in place of whatever the parser made of the tokens before it
+    err: Option<String>,
+    // where the last token taken from the stream ends
+    last: Option<Position>,
+}
+
+impl TokenStream {
+    pub fn new(tokens: VecDeque<TokenContext>) -> TokenStream {
+        TokenStream { tokens, source: None, err: None, last: None }
+    }
+
+    #[allow(dead_code)]
+    pub fn streaming(source: impl Iterator<Item = Result<TokenContext, String>> + 'static) -> TokenStream {
+        let mut stream = TokenStream { tokens: VecDeque::new(), source: Some(Box::new(source)), err: None, last: None };
+        stream.fill(LOOKAHEAD);
+        stream
+    }
+
+    // comments are skipped the same as they are filtered out of the tokens given to Parser::new
+    fn fill(&mut self, count: usize) {
+        while self.tokens.len() < count {
+            let Some(source) = &mut self.source else { return };
+            match source.next() {
+                Some(Ok(TokenContext { kind: Token::Comment(_), .. })) => {}
+                Some(Ok(tok)) => self.tokens.push_back(tok),
+                Some(Err(err)) => {
+                    self.err = Some(err);
+                    self.source = None
+                }
+                None => self.source = None
+            }
+        }
+    }
+
+    // the error that ended the source, the tokens after the ones already parsed are lexed and dropped to look for it
+    pub fn source_err(&mut self) -> Option<String> {
+        while let Some(source) = &mut self.source {
+            match source.next() {
+                Some(Ok(_)) => {}
+                Some(Err(err)) => {
+                    self.err = Some(err);
+                    self.source = None
+                }
+                None => self.source = None
+            }
+        }
+        self.err.take()
+    }
+
+    // the token n places ahead of the next one, so peek(0) is the next token
+    pub fn peek(&self, n: usize) -> Option<&TokenContext> {
+        self.tokens.get(n)
+    }
+
+    pub fn peek_kind(&self, n: usize) -> Option<&Token> {
+        self.peek(n).map(|tok| &tok.kind)
+    }
+
+    pub fn next(&mut self) -> Option<TokenContext> {
+        let tok = self.tokens.pop_front();
+        if let Some(tok) = &tok {
+            self.last = Some(tok.rpos)
+        }
+        self.fill(LOOKAHEAD);
+        tok
+    }
+
+    // the position the next token starts at
+    pub fn pos(&self) -> Option<Position> {
+        self.peek(0).map(|tok| tok.lpos)
+    }
+
+    pub fn last_pos(&self) -> Option<Position> {
+        self.last
+    }
+}
 
 pub struct Parser {
-    tokens: VecDeque<TokenContext>
+    tokens: TokenStream,
+    // struct literals aren't allowed where a block follows the expression, so 'while p { x = 1; }' is a loop
+    structs: bool,
+    // the tokens parse_recovering skips ahead to after an error before it goes on parsing definitions
+    sync: Vec<Token>,
 }
 
 impl Parser {
-    fn new(tokens: VecDeque<TokenContext>) -> Parser {
-        Parser { tokens }
+    pub fn new(tokens: VecDeque<TokenContext>) -> Parser {
+        // comments are only kept by the lexer for tooling, they have no meaning to the grammar
+        let tokens = tokens.into_iter()
+            .filter(|tok| !matches!(tok.kind, Token::Comment(_)))
+            .collect();
+        Parser { tokens: TokenStream::new(tokens), structs: true, sync: Self::default_sync() }
+    }
+
+    // pulls each token from the lexer as it is needed instead of holding every token of the program at once
+    #[allow(dead_code)]
+    pub fn streaming(tokens: impl Iterator<Item = Result<TokenContext, String>> + 'static) -> Parser {
+        Parser { tokens: TokenStream::streaming(tokens), structs: true, sync: Self::default_sync() }
+    }
+
+    fn default_sync() -> Vec<Token> {
+        vec![Token::SemiColon, Token::RBrace]
+    }
+
+    // syncing on the keywords that start a definition resumes at the next definition after a badly broken body,
+    // instead of at each statement left in it
+    #[allow(dead_code)]
+    pub fn with_sync(mut self, sync: Vec<Token>) -> Parser {
+        self.sync = sync;
+        self
     }
 
     fn peek_token(&self) -> Option<&TokenContext> {
-        self.tokens.front()
+        self.tokens.peek(0)
+    }
+
+    fn peek_kind(&self) -> Option<&Token> {
+        self.tokens.peek_kind(0)
     }
 
     fn consume_token(&mut self) {
-        self.tokens.pop_front();
+        self.tokens.next();
     }
 
     fn next_token(&mut self) -> Option<TokenContext> {
-        self.tokens.pop_front()
+        self.tokens.next()
     }
 
     fn advance_token(&mut self) -> Result<TokenContext, String> {
@@ -39,50 +158,205 @@ impl Parser {
         }
     }
 
+    // an error lexing the source is reported before any error parsing it, as it is when the tokens are lexed up front
     pub fn parse_program(&mut self) -> Result<Vec<Node>, String> {
+        let nodes = self.parse_defs();
+        match self.tokens.source_err() {
+            Some(err) => Err(err),
+            None => nodes
+        }
+    }
+
+    fn parse_defs(&mut self) -> Result<Vec<Node>, String> {
         let mut nodes = vec![];
         while let Some(tok) = self.next_token() {
-            let node = match tok.kind {
-                Token::Import => self.parse_import()?,
-                Token::Fn => self.parse_def_func()?,
-                Token::Type => self.parse_def_type()?,
-                Token::Struct => self.parse_def_struct()?,
-                _ => return Err(format!("import, fn, or type expected, got {}", &tok)),
-            };
-            nodes.push(node)
+            if let Some(node) = self.parse_def(tok)? {
+                nodes.push(node)
+            }
         }
         Ok(nodes)
     }
 
+    // keeps parsing definitions after an error so each of them is reported, a sync token that starts a
+    // definition is left for the next definition to start at and any other one is skipped over
+    // an error points at the end of the last token taken before it, which is usually the one it is about
+    pub fn parse_recovering(&mut self) -> (Vec<Node>, Vec<Diagnostic>) {
+        let mut nodes = vec![];
+        let mut errs = vec![];
+        while let Some(tok) = self.next_token() {
+            match self.parse_def(tok) {
+                Ok(Some(node)) => nodes.push(node),
+                Ok(None) => {}
+                Err(err) => {
+                    errs.push(Diagnostic::new(err, self.tokens.last_pos(), None));
+                    self.synchronize()
+                }
+            }
+        }
+        errs.extend(self.tokens.source_err().map(|err| Diagnostic::new(err, None, None)));
+        (nodes, errs)
+    }
+
+    fn synchronize(&mut self) {
+        while let Some(kind) = self.peek_kind() {
+            if self.sync.contains(kind) {
+                if !matches!(kind, Token::Import | Token::Mod | Token::Fn | Token::Type | Token::Struct) {
+                    self.consume_token()
+                }
+                return
+            }
+            self.consume_token()
+        }
+    }
+
+    // parses the definition starting with the token, an empty statement defines nothing
+    fn parse_def(&mut self, tok: TokenContext) -> Result<Option<Node>, String> {
+        let node = match tok.kind {
+            Token::Import => self.parse_import()?,
+            Token::Mod => self.parse_module()?,
+            Token::Fn => self.parse_def_func()?,
+            Token::Type => self.parse_def_type()?,
+            Token::Struct => self.parse_def_struct()?,
+            Token::SemiColon => return Ok(None),
+            _ => return Err(format!("import, mod, fn, type, or struct expected, got {}", &tok)),
+        };
+        Ok(Some(node))
+    }
+
+    fn parse_module(&mut self) -> Result<Node, String> {
+        let tok = self.advance_token()?;
+        let iden = match tok.kind {
+            Token::Iden(iden) => iden,
+            _ => return Err(format!("expected <iden> after mod, got {}", &tok)),
+        };
+        self.expect_token(Token::LBrace)?;
+
+        let mut nodes = vec![];
+        loop {
+            let tok = self.advance_token()?;
+            if tok.kind == Token::RBrace {
+                break
+            }
+            if let Some(node) = self.parse_def(tok)? {
+                nodes.push(node)
+            }
+        }
+        Ok(Node::Module(iden, nodes))
+    }
+
     fn parse_import(&mut self) -> Result<Node, String> {
         let tok = self.advance_token()?;
         match tok.kind {
             Token::Iden(iden) => {
-                let node = ImportNode { iden };
+                // 'as' is only special after the name of an import, so it can still be used as a name elsewhere
+                let alias = match self.peek_kind() {
+                    Some(Token::Iden(word)) if word == "as" => {
+                        self.consume_token();
+                        let tok = self.advance_token()?;
+                        match tok.kind {
+                            Token::Iden(alias) => Some(alias),
+                            _ => return Err(format!("expected <iden> after 'as' in import, got {}", &tok)),
+                        }
+                    }
+                    _ => None
+                };
+                let node = ImportNode { iden, alias };
                 Ok(Node::Import(node))
             }
-            _ => return Err(format!("expected <iden> in import, got {}", &tok)),
+            _ => Err(format!("expected <iden> in import, got {}", &tok)),
         }
     }
 
     fn parse_def_func(&mut self) -> Result<Node, String> {
+        if let Some(Token::LParen) = self.peek_kind() {
+            self.consume_token();
+            return self.parse_def_method()
+        }
+        let func = self.parse_func()?;
+        Ok(Node::DefFunc(func))
+    }
+
+    // a method puts its receiver in parens before the name, like fn (p Point) dist() -> float
+    fn parse_def_method(&mut self) -> Result<Node, String> {
+        let tok = self.advance_token()?;
+        let recv = match tok.kind {
+            Token::Iden(iden) => iden,
+            _ => return Err(format!("expected <iden> for the receiver of a method, got {}", &tok)),
+        };
+        let tok = self.advance_token()?;
+        let recv_type = match tok.kind {
+            Token::Iden(iden) => iden,
+            _ => return Err(format!("expected a struct <iden> for the type of a receiver, got {}", &tok)),
+        };
+        self.expect_token(Token::RParen)?;
+
+        let func = self.parse_func()?;
+        if func.args.iter().any(|(iden, _)| iden == &recv) {
+            return Err(format!("duplicate parameter {} in method {}, it is already the receiver", recv, func.iden))
+        }
+        Ok(Node::DefMethod(DefMethodNode { recv, recv_type, func }))
+    }
+
+    fn parse_func(&mut self) -> Result<DefFuncNode, String> {
         let tok = self.advance_token()?;
         let iden = match tok.kind {
             Token::Iden(iden) => iden,
             _ => return Err(format!("expected <iden> in function definition, got {}", &tok)),
         };
+        let pos = tok.lpos;
+        let type_params = match self.peek_kind() {
+            Some(Token::LBracket) => {
+                self.consume_token();
+                self.parse_type_params()?
+            }
+            _ => vec![]
+        };
 
         self.expect_token(Token::LParen)?;
 
-        let args =  self.parse_type_pairs(Token::RParen)?;
+        let args = self.parse_type_pairs(Token::RParen, "parameter")?;
         let ret = self.parse_ret_type()?;
-        let body = vec![];
-        let node = DefFuncNode { iden, args, ret, body };
+        // a function without a body is only a declaration of its signature
+        let body = match self.peek_kind() {
+            Some(Token::LBrace) => {
+                // the trailing expression of a function body is returned
+                let (mut body, value) = self.parse_body()?;
+                if let Some(value) = value {
+                    body.push(Node::Return(Box::new(value)))
+                }
+                body
+            }
+            _ => vec![]
+        };
+        Ok(DefFuncNode { iden, type_params, args, ret, body, pos })
+    }
+
+    fn parse_type_params(&mut self) -> Result<Vec<String>, String> {
+        let mut params: Vec<String> = vec![];
+        loop {
+            let tok = self.advance_token()?;
+            let param = match tok.kind {
+                Token::Iden(param) => param,
+                Token::RBracket => break,
+                _ => return Err(format!("expected ']' or <iden> in type parameters, got {}", &tok))
+            };
+            if params.contains(&param) {
+                return Err(format!("duplicate type parameter {} between {} and {}", param, tok.lpos, tok.rpos))
+            }
+            params.push(param);
 
-        Ok(Node::DefFunc(node))
+            let tok = self.advance_token()?;
+            match tok.kind {
+                Token::Comma => continue,
+                Token::RBracket => break,
+                _ => return Err(format!("expected ']' or ',' in type parameters, got {}", &tok))
+            }
+        }
+        Ok(params)
     }
 
-    fn parse_type_pairs(&mut self, term: Token) -> Result<Vec<(String, TypeNode)>, String> {
+    // each name in the list must be unique, the kind describes what the names are in errors
+    fn parse_type_pairs(&mut self, term: Token, kind: &str) -> Result<Vec<(String, TypeNode)>, String> {
         let mut args = vec![];
         loop {
             let tok = self.advance_token()?;
@@ -93,6 +367,9 @@ impl Parser {
                     return Err(format!("expected {} or <iden> in function definition, got {}", term.to_text(), &tok))
                 }
             };
+            if args.iter().any(|(iden, _)| iden == &iden_arg) {
+                return Err(format!("duplicate {} {} between {} and {}", kind, iden_arg, tok.lpos, tok.rpos))
+            }
 
             let type_node = self.parse_type()?;
             args.push((iden_arg, type_node));
@@ -128,6 +405,7 @@ impl Parser {
         let tok = self.advance_token()?;
         match tok.kind {
             Token::Iden(iden) => Ok(TypeNode::Iden(iden)),
+            Token::Question => Ok(TypeNode::Optional(Box::new(self.parse_type()?))),
             Token::Fn => {
                 let tok = self.advance_token()?;
                 match tok.kind {
@@ -135,8 +413,22 @@ impl Parser {
                         let type_node = self.parse_fn_type()?;
                         Ok(type_node)
                     }
-                    _ => return Err(format!("expected ')' after <fn>, got {}", &tok))
+                    _ => Err(format!("expected ')' after <fn>, got {}", &tok))
+                }
+            }
+            Token::LParen => {
+                let mut elems = vec![];
+                loop {
+                    elems.push(self.parse_type()?);
+
+                    let tok = self.advance_token()?;
+                    match tok.kind {
+                        Token::Comma => continue,
+                        Token::RParen => break,
+                        _ => return Err(format!("expected ',' or ')' after element type in tuple type, got {}", &tok))
+                    }
                 }
+                Ok(TypeNode::Tuple(elems))
             }
             Token::LBracket => {
                 let tok = self.advance_token()?;
@@ -145,10 +437,10 @@ impl Parser {
                         let type_node = Box::new(self.parse_type()?);
                         Ok(TypeNode::Array(type_node))
                     }
-                    _ => return Err(format!("expected '[]' before an array type, got {}", &tok))
+                    _ => Err(format!("expected '[]' before an array type, got {}", &tok))
                 }
             }
-            _ => return Err(format!("expected <iden>, <fn>, or <array> as type definition, got {}", &tok))
+            _ => Err(format!("expected <iden>, <fn>, <array>, <tuple>, or <optional> as type definition, got {}", &tok))
         }
     }
 
@@ -166,7 +458,7 @@ impl Parser {
             }
         }
 
-        let ret = self.parse_ret_type()?.map(|t| Box::new(t));
+        let ret = self.parse_ret_type()?.map(Box::new);
         Ok(TypeNode::Fn(args, ret))
     }
 
@@ -178,7 +470,7 @@ impl Parser {
         };
 
         let type_node = self.parse_type()?;
-        let node = DefTypeAliasNode { iden, type_node };
+        let node = DefTypeAliasNode { iden, type_node, pos: tok.lpos };
         Ok(Node::DefTypeAlias(node))
     }
 
@@ -191,120 +483,1489 @@ impl Parser {
 
         self.expect_token(Token::LBrace)?;
 
-        let fields = self.parse_type_pairs(Token::RBrace)?;
-        let node = DefStructNode{ iden, fields };
+        let fields = self.parse_type_pairs(Token::RBrace, "field")?;
+        let node = DefStructNode{ iden, fields, pos: tok.lpos };
 
         Ok(Node::DefStruct(node))
     }
-}
 
-mod test {
-    use std::io::{BufReader, Cursor};
-    use crate::lexer::Lexer;
-    use crate::node::{BinopNode, WhileNode, Bop, DefFuncNode, GuardNode, FuncNode, TypeNode, DefStructNode};
-    use crate::node::Bop::{Plus, Leq, Multiply, Minus};
-    use crate::node::Const::Int;
-    use crate::node::Node::{Assign, Binop, Constant, DefFunc, DefStruct, Func, Guard, Return, Variable, While};
-    use crate::parser::Parser;
+    // parses the statements of a block, and the trailing expression that gives the block its value
+    fn parse_body(&mut self) -> Result<(Vec<Node>, Option<Node>), String> {
+        self.expect_token(Token::LBrace)?;
+        let mut nodes = vec![];
+        loop {
+            match self.peek_kind() {
+                Some(Token::RBrace) => {
+                    self.consume_token();
+                    return Ok((nodes, None))
+                }
+                Some(Token::If) => {
+                    self.consume_token();
+                    nodes.extend(self.parse_if()?)
+                }
+                Some(Token::While) => {
+                    self.consume_token();
+                    nodes.push(self.parse_while()?)
+                }
+                // an empty statement, which newline termination leaves after a closing brace
+                Some(Token::SemiColon) => self.consume_token(),
+                // an else is only parsed as part of the if before it, so one reached here has no if
+                Some(Token::Else) => {
+                    let tok = self.advance_token()?;
+                    return Err(format!("dangling else at {}, an else must follow the block of an if", tok.lpos))
+                }
+                Some(Token::Elif) => {
+                    let tok = self.advance_token()?;
+                    return Err(format!("dangling elif at {}, an elif must follow the block of an if", tok.lpos))
+                }
+                Some(Token::For) => {
+                    self.consume_token();
+                    nodes.push(self.parse_for()?)
+                }
+                // a fn followed by a name is a nested definition rather than a lambda
+                Some(Token::Fn) if matches!(self.tokens.peek_kind(1), Some(Token::Iden(_))) => {
+                    self.consume_token();
+                    nodes.push(self.parse_def_func()?)
+                }
+                Some(_) => {
+                    let node = self.parse_stmt()?;
+                    match self.peek_kind() {
+                        Some(Token::RBrace) if Self::is_expr(&node) => {
+                            self.consume_token();
+                            return Ok((nodes, Some(node)))
+                        }
+                        // the semicolon of the last statement is optional since newline termination doesn't add one
+                        Some(Token::RBrace) => {}
+                        _ => self.expect_token(Token::SemiColon)?
+                    }
+                    nodes.push(node)
+                }
+                None => return Err("expected '}' to close block, but reached end of the stream".to_string())
+            }
+        }
+    }
 
-    #[test]
-    fn test_parse_def() {
-        let program = "
-            struct Point {
-                x int,
-                y int,
+    fn parse_block(&mut self) -> Result<Vec<Node>, String> {
+        match self.parse_body()? {
+            (nodes, None) => Ok(nodes),
+            (_, Some(_)) => Err("expected ';' after the last statement, only function and lambda bodies have a value".to_string())
+        }
+    }
+
+    fn is_expr(node: &Node) -> bool {
+        !matches!(node, Node::Declare(_, _) | Node::DeclareMany(_, _) | Node::DeclareConst(_, _) | Node::Assign(_, _) | Node::Store(_) | Node::Return(_) | Node::Break | Node::Continue)
+    }
+
+    fn parse_stmt(&mut self) -> Result<Node, String> {
+        let node = match self.peek_kind() {
+            // a return without a value gives the empty tuple, like the end of a function without a return type
+            Some(Token::Return) => {
+                self.consume_token();
+                let expr = match self.peek_kind() {
+                    Some(Token::SemiColon | Token::RBrace) => Node::Tuple(vec![]),
+                    _ => self.parse_expr()?
+                };
+                Node::Return(Box::new(expr))
             }
-            fn concat_points(p1 Point, p2 Point) -> []Point
-        ";
-        let reader = BufReader::new(Cursor::new(program));
-        let tokens = Lexer::new(reader).read_tokens().unwrap();
-        println!("tokens {:?}", tokens);
+            Some(Token::Break) => {
+                self.consume_token();
+                Node::Break
+            }
+            Some(Token::Continue) => {
+                self.consume_token();
+                Node::Continue
+            }
+            Some(Token::Const) => {
+                self.consume_token();
+                let tok = self.advance_token()?;
+                let iden = match tok.kind {
+                    Token::Iden(iden) => iden,
+                    _ => return Err(format!("expected <iden> after const, got {}", &tok))
+                };
+                self.expect_token(Token::Declare)?;
+                let rhs = self.parse_expr()?;
+                Node::DeclareConst(iden, Box::new(rhs))
+            }
+            _ => {
+                let lpos = self.tokens.pos();
+                let expr = self.parse_expr()?;
+                match (expr, self.peek_kind()) {
+                    (Node::Variable(iden), Some(Token::Declare)) => {
+                        self.consume_token();
+                        let rhs = self.parse_expr()?;
+                        Node::Declare(iden, Box::new(rhs))
+                    }
+                    (Node::Variable(iden), Some(Token::Comma)) => self.parse_declare_many(iden)?,
+                    (Node::Variable(iden), Some(Token::Assign)) => {
+                        self.consume_token();
+                        let rhs = self.parse_expr()?;
+                        Node::Assign(iden, Box::new(rhs))
+                    }
+                    (target, Some(Token::Assign)) if Self::is_lvalue(&target) => {
+                        self.consume_token();
+                        let rhs = self.parse_expr()?;
+                        Node::Store(StoreNode { target: Box::new(target), value: Box::new(rhs) })
+                    }
+                    // a compound assignment is written as the assignment of the operator applied to the target
+                    (target, Some(Token::AssignOp(_))) if Self::is_lvalue(&target) => {
+                        let tok = self.advance_token()?;
+                        let op = match &tok.kind {
+                            Token::AssignOp(aop) => Self::assign_op(aop),
+                            _ => return Err(format!("expected an assignment operator, got {}", &tok))
+                        };
+                        let rhs = self.parse_expr()?;
+                        let value = Box::new(Node::Binop(BinopNode { op, lhs: Box::new(target.clone()), rhs: Box::new(rhs), pos: tok.lpos }));
+                        match target {
+                            Node::Variable(iden) => Node::Assign(iden, value),
+                            target => Node::Store(StoreNode { target: Box::new(target), value })
+                        }
+                    }
+                    (_, Some(tok @ (Token::Declare | Token::Assign | Token::AssignOp(_)))) => {
+                        let span = lpos.zip(self.tokens.last_pos())
+                            .map(|(lpos, rpos)| format!(" between {} and {}", lpos, rpos))
+                            .unwrap_or_default();
+                        return Err(match tok {
+                            Token::Declare => format!("cannot declare the expression{}, only a variable can be declared", span),
+                            _ => format!("cannot assign to the expression{}, only a variable, an index or a member can be assigned", span)
+                        })
+                    }
+                    (expr, _) => expr
+                }
+            }
+        };
+        Ok(node)
+    }
 
-        let actual_nodes = Parser::new(tokens).parse_program().unwrap();
-        let expect_nodes = vec![
-            DefStruct(DefStructNode{
-                iden: "Point".to_string(),
-                fields: vec![
-                    ("x".to_string(), TypeNode::Iden("int".to_string())),
-                    ("y".to_string(), TypeNode::Iden("int".to_string()))
-                ],
-            }),
-            DefFunc(DefFuncNode {
-                iden: "concat_points".to_string(),
-                args: vec![
-                    ("p1".to_string(), TypeNode::Iden("Point".to_string())),
-                    ("p2".to_string(), TypeNode::Iden("Point".to_string()))
-                ],
-                ret: Some(TypeNode::Array(
-                    Box::new(TypeNode::Iden("Point".to_string()))
-                )),
-                body: vec![],
-            })
-        ];
-        assert_eq!(actual_nodes, expect_nodes)
+    // the values on the right are declared together as a tuple, and a single value is a tuple to destructure
+    fn parse_declare_many(&mut self, first: String) -> Result<Node, String> {
+        let mut idens = vec![first];
+        while self.peek_kind() == Some(&Token::Comma) {
+            self.consume_token();
+            let tok = self.advance_token()?;
+            match tok.kind {
+                Token::Iden(iden) => idens.push(iden),
+                _ => return Err(format!("expected <iden> after ',' in declaration, got {}", &tok))
+            }
+        }
+        self.expect_token(Token::Declare)?;
+
+        let mut values = vec![self.parse_expr()?];
+        while self.peek_kind() == Some(&Token::Comma) {
+            self.consume_token();
+            values.push(self.parse_expr()?)
+        }
+        let rhs = match values.len() {
+            1 => values.remove(0),
+            len if len == idens.len() => Node::Tuple(values),
+            len => return Err(format!("cannot declare {} names from {} values", idens.len(), len))
+        };
+        Ok(Node::DeclareMany(idens, Box::new(rhs)))
     }
 
-    #[test]
-    fn test_parse_loop() {
-        let expect_node =
-            While(WhileNode{
-                cond: Box::new(Binop(BinopNode {
-                    op: Bop::Lt,
-                    lhs: Box::new(Variable("i".to_string())),
-                    rhs: Box::new(Variable("n".to_string())),
-                })),
-                body: vec![
-                    Assign(
-                        "acc".to_string(),
-                        Box::new(Binop(BinopNode {
-                            op: Multiply,
-                            lhs: Box::new(Variable("acc".to_string())),
-                            rhs: Box::new(Variable("x".to_string())),
-                        }))
-                    ),
-                    Assign(
-                        "i".to_string(),
-                        Box::new(Binop(BinopNode {
-                            op: Plus,
-                            lhs: Box::new(Variable("i".to_string())),
-                            rhs: Box::new(Variable("1".to_string())),
-                        }))
-                    ),
-                ]
-            });
+    fn is_lvalue(node: &Node) -> bool {
+        matches!(node, Node::Variable(_) | Node::Index(_) | Node::Member(_))
     }
 
-    #[test]
-    fn test_parse_func() {
-        let expect_node =
-            DefFunc(DefFuncNode{
-                iden: "sum".to_string(),
-                args: vec![("n".to_string(), TypeNode::Iden("int".to_string()))],
-                ret: Some(TypeNode::Iden("int".to_string())),
-                body: vec![
-                    Guard(GuardNode{
-                        cond: Box::new(Binop(BinopNode{
-                            op: Leq,
-                            lhs: Box::new(Variable("n".to_string())),
-                            rhs: Box::new(Constant(Int(0))),
-                        })),
-                        this: Box::new(Constant(Int(0))),
-                    }),
-                    Return(Box::new(Binop(BinopNode{
-                        op: Plus,
-                        lhs: Box::new(Func(FuncNode{
-                            iden: "fib".to_string(),
-                            args: vec![
-                                Binop(BinopNode{
-                                    op: Minus,
-                                    lhs: Box::new(Variable("n".to_string())),
-                                    rhs: Box::new(Constant(Int(1))),
-                                })
-                            ],
-                        })),
-                        rhs: Box::new(Constant(Int(1))),
-                    })))
-                ],
-            });
+    fn assign_op(aop: &Aop) -> Bop {
+        match aop {
+            Aop::Plus => Bop::Plus,
+            Aop::Exp => Bop::Exp,
+            Aop::Minus => Bop::Minus,
+            Aop::Multiply => Bop::Multiply,
+            Aop::Divide => Bop::Divide,
+            Aop::Mod => Bop::Mod,
+        }
     }
-}
\ No newline at end of file
+
+    // an if statement is followed by an else node as a sibling, and an 'else if' chain
+    // nests the next if and else nodes inside of the else body, which is also what 'elif' means
+    fn parse_if(&mut self) -> Result<Vec<Node>, String> {
+        let cond = self.parse_cond()?;
+        if let Some(Token::Arrow) = self.peek_kind() {
+            return Ok(vec![self.parse_guard(cond)?])
+        }
+
+        let body = self.parse_block()?;
+        let mut nodes = vec![Node::If(IfNode { cond: Box::new(cond), body })];
+
+        if let Some(Token::Else) = self.peek_kind() {
+            self.consume_token();
+            let body = match self.peek_kind() {
+                Some(Token::If) => {
+                    self.consume_token();
+                    self.parse_if()?
+                }
+                _ => self.parse_block()?
+            };
+            nodes.push(Node::Else(body))
+        } else if let Some(Token::Elif) = self.peek_kind() {
+            self.consume_token();
+            nodes.push(Node::Else(self.parse_if()?))
+        }
+        Ok(nodes)
+    }
+
+    fn parse_while(&mut self) -> Result<Node, String> {
+        let cond = self.parse_cond()?;
+        let body = self.parse_block()?;
+        Ok(Node::While(WhileNode { cond: Box::new(cond), body }))
+    }
+
+    // a for loop binds each element of the collection, and optionally the index of the element
+    fn parse_for(&mut self) -> Result<Node, String> {
+        let element = self.parse_loop_iden()?;
+        let index = match self.peek_kind() {
+            Some(Token::Comma) => {
+                self.consume_token();
+                Some(self.parse_loop_iden()?)
+            }
+            _ => None
+        };
+        self.expect_token(Token::In)?;
+
+        let collection = self.parse_cond()?;
+        let body = self.parse_block()?;
+        Ok(Node::For(ForNode { element, index, collection: Box::new(collection), body }))
+    }
+
+    fn parse_loop_iden(&mut self) -> Result<String, String> {
+        let tok = self.advance_token()?;
+        match tok.kind {
+            Token::Iden(iden) => Ok(iden),
+            _ => Err(format!("expected <iden> in for loop, got {}", &tok))
+        }
+    }
+
+    // a guard returns its value from the enclosing function when the condition holds
+    fn parse_guard(&mut self, cond: Node) -> Result<Node, String> {
+        self.expect_token(Token::Arrow)?;
+        let this = self.parse_expr()?;
+        self.expect_token(Token::SemiColon)?;
+        let node = GuardNode { cond: Box::new(cond), this: Box::new(this) };
+        Ok(Node::Guard(node))
+    }
+
+    // a range binds looser than any binary operator, so '0..n + 1' ends at n + 1
+    // a ternary binds looser than any other expression, and the branches can be ternaries themselves
+    fn parse_expr(&mut self) -> Result<Node, String> {
+        let cond = self.parse_range()?;
+        self.parse_ternary(cond)
+    }
+
+    fn parse_ternary(&mut self, cond: Node) -> Result<Node, String> {
+        if self.peek_kind() != Some(&Token::Question) {
+            return Ok(cond)
+        }
+        self.consume_token();
+        let then = self.parse_expr()?;
+        self.expect_token(Token::Colon)?;
+        let otherwise = self.parse_expr()?;
+        Ok(Node::Ternary(TernaryNode { cond: Box::new(cond), then: Box::new(then), otherwise: Box::new(otherwise) }))
+    }
+
+    fn parse_range(&mut self) -> Result<Node, String> {
+        let start = self.parse_binop(0)?;
+        let inclusive = match self.peek_kind() {
+            Some(Token::Range) => false,
+            Some(Token::RangeInclusive) => true,
+            _ => return Ok(start)
+        };
+        let pos = self.advance_token()?.lpos;
+        let end = self.parse_binop(0)?;
+        Ok(Node::Range(RangeNode { start: Box::new(start), end: Box::new(end), inclusive, pos }))
+    }
+
+    fn parse_cond(&mut self) -> Result<Node, String> {
+        self.with_structs(false, Self::parse_expr)
+    }
+
+    // a struct literal is allowed again between brackets, since the block can't start until they are closed
+    fn with_structs<T>(&mut self, allowed: bool, parse: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        let structs = mem::replace(&mut self.structs, allowed);
+        let result = parse(self);
+        self.structs = structs;
+        result
+    }
+
+    // the binary operator for an operator token, and whether it is right associative
+    fn binop(op: &Op) -> Option<(Bop, bool)> {
+        let binop = match op {
+            Op::Or => (Bop::Or, false),
+            Op::And => (Bop::And, false),
+            Op::Eq => (Bop::Eq, false),
+            Op::Neq => (Bop::Neq, false),
+            Op::Lt => (Bop::Lt, false),
+            Op::Gt => (Bop::Gt, false),
+            Op::Leq => (Bop::Leq, false),
+            Op::Geq => (Bop::Geq, false),
+            Op::Plus => (Bop::Plus, false),
+            Op::Minus => (Bop::Minus, false),
+            Op::Multiply => (Bop::Multiply, false),
+            Op::Divide => (Bop::Divide, false),
+            Op::Mod => (Bop::Mod, false),
+            Op::Exp => (Bop::Exp, true),
+            Op::Not => return None,
+        };
+        Some(binop)
+    }
+
+    fn is_ordering(op: &Bop) -> bool {
+        matches!(op, Bop::Lt | Bop::Gt | Bop::Leq | Bop::Geq)
+    }
+
+    fn parse_binop(&mut self, min_prec: u8) -> Result<Node, String> {
+        let mut lhs = self.parse_unop()?;
+        // set when lhs is the bool result of an ordering comparison parsed within this loop
+        let mut compared = false;
+        while let Some(kind @ Token::Operator(op)) = self.peek_kind() {
+            let ((op, right), prec) = match (Self::binop(op), kind.precedence()) {
+                (Some(binop), Some(prec)) => (binop, prec),
+                _ => break
+            };
+            if prec < min_prec {
+                break;
+            }
+            let tok = self.advance_token()?;
+            let pos = tok.lpos;
+
+            if Self::is_ordering(&op) {
+                if compared {
+                    return Err(format!("comparisons cannot be chained, got {} after a comparison, use 'a < b && b < c' instead", &tok))
+                }
+                compared = true
+            } else {
+                compared = false
+            }
+
+            let next_prec = if right { prec } else { prec + 1 };
+            let rhs = self.parse_binop(next_prec)?;
+            lhs = Node::Binop(BinopNode { op, lhs: Box::new(lhs), rhs: Box::new(rhs), pos });
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unop(&mut self) -> Result<Node, String> {
+        let op = match self.peek_kind() {
+            Some(Token::Operator(Op::Minus)) => Uop::Minus,
+            Some(Token::Operator(Op::Not)) => Uop::Not,
+            _ => return self.parse_postfix()
+        };
+        let pos = self.advance_token()?.lpos;
+
+        // a negated int literal is folded into a constant so the minimum int can be written
+        if let (Uop::Minus, Some(Token::IntLit(_))) = (&op, self.peek_kind()) {
+            let tok = self.advance_token()?;
+            return Self::parse_int(tok, true)
+        }
+
+        // unary operators bind tighter than any binary operator
+        let expr = self.parse_unop()?;
+        Ok(Node::Unop(UnopNode { op, expr: Box::new(expr), pos }))
+    }
+
+    fn parse_postfix(&mut self) -> Result<Node, String> {
+        let mut node = self.parse_primary()?;
+        loop {
+            node = match self.peek_kind() {
+                Some(Token::LParen) => {
+                    self.consume_token();
+                    let args = self.parse_args(Token::RParen)?;
+                    match node {
+                        Node::Variable(iden) => Node::CallFunc(FuncNode { iden, args }),
+                        callee => Node::Call(CallNode { callee: Box::new(callee), args })
+                    }
+                }
+                Some(Token::LBracket) => {
+                    let pos = self.advance_token()?.lpos;
+                    let node = self.with_structs(true, |parser| parser.parse_subscript(node, pos))?;
+                    self.expect_token(Token::RBracket)?;
+                    node
+                }
+                Some(Token::Dot) if matches!(self.tokens.peek_kind(1), Some(Token::Iden(_))) => {
+                    let pos = self.advance_token()?.lpos;
+                    let field = match self.advance_token()?.kind {
+                        Token::Iden(field) => field,
+                        _ => return Err("expected <iden> after '.'".to_string())
+                    };
+                    Node::Member(MemberNode { base: Box::new(node), field, pos })
+                }
+                _ => break
+            };
+        }
+        Ok(node)
+    }
+
+    // a subscript with a range operator slices the base instead of indexing it, and either side of the
+    // operator can be left out
+    fn parse_subscript(&mut self, base: Node, pos: Position) -> Result<Node, String> {
+        let start = match self.peek_kind() {
+            Some(Token::Range | Token::RangeInclusive) => None,
+            _ => {
+                let start = self.parse_binop(0)?;
+                if !matches!(self.peek_kind(), Some(Token::Range | Token::RangeInclusive)) {
+                    let index = self.parse_ternary(start)?;
+                    return Ok(Node::Index(IndexNode { base: Box::new(base), index: Box::new(index), pos }))
+                }
+                Some(Box::new(start))
+            }
+        };
+        let tok = self.advance_token()?;
+        let inclusive = tok.kind == Token::RangeInclusive;
+        let end = match self.peek_kind() {
+            Some(Token::RBracket) if inclusive => return Err(format!("expected the end of the inclusive slice after {}", tok)),
+            Some(Token::RBracket) => None,
+            _ => Some(Box::new(self.parse_binop(0)?))
+        };
+        Ok(Node::Slice(SliceNode { base: Box::new(base), start, end, inclusive, pos }))
+    }
+
+    fn parse_args(&mut self, term: Token) -> Result<Vec<Node>, String> {
+        self.with_structs(true, |parser| parser.parse_args_within(term))
+    }
+
+    fn parse_args_within(&mut self, term: Token) -> Result<Vec<Node>, String> {
+        let mut args = vec![];
+        if self.peek_kind() == Some(&term) {
+            self.consume_token();
+            return Ok(args)
+        }
+        loop {
+            args.push(self.parse_expr()?);
+
+            let tok = self.advance_token()?;
+            match tok.kind {
+                Token::Comma => continue,
+                typ if typ == term => break,
+                _ => return Err(format!("expected {} or ',' after argument, got {}", term.to_text(), &tok))
+            }
+        }
+        Ok(args)
+    }
+
+    // lambda arguments may leave out their types
+    fn parse_lambda(&mut self) -> Result<Node, String> {
+        self.expect_token(Token::LParen)?;
+        let mut args = vec![];
+        if let Some(Token::RParen) = self.peek_kind() {
+            self.consume_token();
+        } else {
+            loop {
+                let tok = self.advance_token()?;
+                let iden = match tok.kind {
+                    Token::Iden(iden) => iden,
+                    _ => return Err(format!("expected <iden> in lambda arguments, got {}", &tok))
+                };
+                if args.iter().any(|(arg, _)| arg == &iden) {
+                    return Err(format!("duplicate parameter {} between {} and {}", iden, tok.lpos, tok.rpos))
+                }
+                let type_node = match self.peek_kind() {
+                    Some(Token::Comma) | Some(Token::RParen) => None,
+                    _ => Some(self.parse_type()?)
+                };
+                args.push((iden, type_node));
+
+                let tok = self.advance_token()?;
+                match tok.kind {
+                    Token::Comma => continue,
+                    Token::RParen => break,
+                    _ => return Err(format!("expected ')' or ',' in lambda arguments, got {}", &tok))
+                }
+            }
+        }
+
+        let (body, value) = self.parse_body()?;
+        let block = BlockNode { body, value: value.map(Box::new) };
+        Ok(Node::Lambda(LambdaNode { args, body: Box::new(Node::Block(block)) }))
+    }
+
+    fn parse_struct(&mut self, iden: String, pos: Position) -> Result<Node, String> {
+        self.expect_token(Token::LBrace)?;
+        let mut fields = vec![];
+        loop {
+            let tok = self.advance_token()?;
+            let field = match tok.kind {
+                Token::Iden(field) => field,
+                Token::RBrace => break,
+                _ => return Err(format!("expected <iden> or '}}' in struct literal, got {}", &tok))
+            };
+            self.expect_token(Token::Assign)?;
+            fields.push((field, self.parse_expr()?));
+
+            let tok = self.advance_token()?;
+            match tok.kind {
+                Token::Comma => continue,
+                Token::RBrace => break,
+                _ => return Err(format!("expected ',' or '}}' after field in struct literal, got {}", &tok))
+            }
+        }
+        Ok(Node::Struct(StructNode { iden, fields, pos }))
+    }
+
+    fn parse_int(tok: TokenContext, negate: bool) -> Result<Node, String> {
+        let magnitude = match tok.kind {
+            Token::IntLit(magnitude) => magnitude as i128,
+            _ => return Err(format!("expected <int>, got {}", &tok))
+        };
+        let int = if negate { -magnitude } else { magnitude };
+        match i64::try_from(int) {
+            Ok(int) => Ok(Node::Constant(Const::Int(int))),
+            Err(_) => Err(format!("int literal {} is out of range between {} and {}", int, tok.lpos, tok.rpos))
+        }
+    }
+
+    // a block is an expression anywhere a primary expression can start, since a braced statement block can't appear
+    // there and struct literals always start with a name
+    fn parse_block_expr(&mut self) -> Result<Node, String> {
+        match self.parse_body()? {
+            (body, Some(value)) => Ok(Node::Block(BlockNode { body, value: Some(Box::new(value)) })),
+            (_, None) => Err("expected a value at the end of a block expression".to_string())
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Node, String> {
+        if let Some(Token::LBrace) = self.peek_kind() {
+            return self.parse_block_expr()
+        }
+        let tok = self.advance_token()?;
+        let node = match tok.kind {
+            Token::IntLit(_) => Self::parse_int(tok, false)?,
+            Token::FloatLit(float) => Node::Constant(Const::Float(float)),
+            Token::CharLit(c) => Node::Constant(Const::Char(c)),
+            // string literals written one after another are joined into one literal
+            Token::StrLit(mut str) => {
+                while let Some(Token::StrLit(next)) = self.peek_kind() {
+                    str.push_str(next);
+                    self.consume_token()
+                }
+                Node::Constant(Const::String(str))
+            }
+            Token::True => Node::Constant(Const::Bool(true)),
+            Token::Nil => Node::Constant(Const::Nil),
+            Token::False => Node::Constant(Const::Bool(false)),
+            // a name followed by '{' is only a struct literal when a field assignment comes next, otherwise it's a block
+            Token::Iden(iden) => match (self.tokens.peek_kind(0), self.tokens.peek_kind(1), self.tokens.peek_kind(2)) {
+                (Some(Token::LBrace), Some(Token::Iden(_)), Some(Token::Assign)) if self.structs => self.parse_struct(iden, tok.lpos)?,
+                _ => Node::Variable(iden)
+            },
+            // a parenthesized list of expressions is a tuple
+            Token::LParen => {
+                let node = self.with_structs(true, Self::parse_expr)?;
+                match self.peek_kind() {
+                    Some(Token::Comma) => {
+                        self.consume_token();
+                        let mut elems = vec![node];
+                        elems.extend(self.parse_args(Token::RParen)?);
+                        Node::Tuple(elems)
+                    }
+                    _ => {
+                        self.expect_token(Token::RParen)?;
+                        node
+                    }
+                }
+            }
+            Token::LBracket => Node::Array(self.parse_args(Token::RBracket)?),
+            Token::Fn => self.parse_lambda()?,
+            _ => return Err(format!("expected an expression, got {}", &tok))
+        };
+        Ok(node)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+    use std::io::{BufReader, Cursor};
+    use crate::lexer::{Lexer, Position, Token, TokenContext};
+    use crate::node::{BinopNode, WhileNode, Bop, DefFuncNode, GuardNode, FuncNode, TypeNode, DefStructNode, IfNode, BlockNode, LambdaNode, ImportNode, TernaryNode, Const, IndexNode, SliceNode, UnopNode, Uop};
+    use crate::node::Bop::{Plus, Leq, Multiply, Minus};
+    use crate::node::Const::{Bool, Int, Nil};
+    use crate::node::Node::{Assign, Binop, Block, Break, CallFunc, Constant, Declare, DeclareMany, DefFunc, DefStruct, Else, Guard, If, Import, Lambda, Module, Return, Ternary, Tuple, Variable, While};
+    use crate::node::{structural_eq, Node};
+    use crate::parser::{Parser, TokenStream};
+
+    fn pos(line: u32, col: u32) -> Position {
+        Position { line, col }
+    }
+
+    // stands in for the positions of the expected nodes in the tests that only check the structure
+    fn any_pos() -> Position {
+        Position { line: 0, col: 0 }
+    }
+
+    fn lex(program: &str) -> VecDeque<TokenContext> {
+        let reader = BufReader::new(Cursor::new(program));
+        Lexer::new(reader).read_tokens().unwrap()
+    }
+
+    fn parse(program: &str) -> Result<Vec<Node>, String> {
+        Parser::new(lex(program)).parse_program()
+    }
+
+    fn parse_expr(program: &str) -> Result<Node, String> {
+        Parser::new(lex(program)).parse_expr()
+    }
+
+    fn assert_structural_eq(actual: &[Node], expected: &[Node]) {
+        let eq = actual.len() == expected.len() && actual.iter().zip(expected).all(|(a, b)| structural_eq(a, b));
+        assert!(eq, "{:?}\n!=\n{:?}", actual, expected)
+    }
+
+    #[test]
+    fn test_parse_def() {
+        let program = "
+            struct Point {
+                x int,
+                y int,
+            }
+            fn concat_points(p1 Point, p2 Point) -> []Point
+        ";
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+        println!("tokens {:?}", tokens);
+
+        let actual_nodes = Parser::new(tokens).parse_program().unwrap();
+        let expect_nodes = vec![
+            DefStruct(DefStructNode{
+                iden: "Point".to_string(),
+                fields: vec![
+                    ("x".to_string(), TypeNode::Iden("int".to_string())),
+                    ("y".to_string(), TypeNode::Iden("int".to_string()))
+                ],
+                pos: pos(2, 20),
+            }),
+            DefFunc(DefFuncNode {
+                iden: "concat_points".to_string(),
+                type_params: vec![],
+                args: vec![
+                    ("p1".to_string(), TypeNode::Iden("Point".to_string())),
+                    ("p2".to_string(), TypeNode::Iden("Point".to_string()))
+                ],
+                ret: Some(TypeNode::Array(
+                    Box::new(TypeNode::Iden("Point".to_string()))
+                )),
+                body: vec![],
+                pos: pos(6, 16),
+            })
+        ];
+        assert_eq!(actual_nodes, expect_nodes)
+    }
+
+    #[test]
+    fn test_parse_loop() {
+        let program = "
+            fn exp(x int, n int) -> int {
+                while i < n {
+                    acc = acc * x;
+                    i = i + 1;
+                }
+                return acc;
+            }
+        ";
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+
+        let actual_nodes = Parser::new(tokens).parse_program().unwrap();
+        let expect_node =
+            While(WhileNode{
+                cond: Box::new(Binop(BinopNode {
+                    op: Bop::Lt,
+                    lhs: Box::new(Variable("i".to_string())),
+                    rhs: Box::new(Variable("n".to_string())),
+                    pos: any_pos(),
+                })),
+                body: vec![
+                    Assign(
+                        "acc".to_string(),
+                        Box::new(Binop(BinopNode {
+                            op: Multiply,
+                            lhs: Box::new(Variable("acc".to_string())),
+                            rhs: Box::new(Variable("x".to_string())),
+                            pos: any_pos(),
+                        }))
+                    ),
+                    Assign(
+                        "i".to_string(),
+                        Box::new(Binop(BinopNode {
+                            op: Plus,
+                            lhs: Box::new(Variable("i".to_string())),
+                            rhs: Box::new(Constant(Int(1))),
+                            pos: any_pos(),
+                        }))
+                    ),
+                ]
+            });
+        match &actual_nodes[0] {
+            DefFunc(func) => assert_structural_eq(&func.body, &[expect_node, Return(Box::new(Variable("acc".to_string())))]),
+            node => panic!("expected a function definition, got {:?}", node)
+        }
+    }
+
+    #[test]
+    fn test_parse_while_true() {
+        let program = "
+            fn f() {
+                while true { break; }
+            }
+        ";
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+
+        let actual_nodes = Parser::new(tokens).parse_program().unwrap();
+        let expect_nodes = vec![
+            DefFunc(DefFuncNode{
+                iden: "f".to_string(),
+                type_params: vec![],
+                args: vec![],
+                ret: None,
+                body: vec![While(WhileNode { cond: Box::new(Constant(Bool(true))), body: vec![Break] })],
+                pos: pos(2, 16),
+            })
+        ];
+        assert_eq!(actual_nodes, expect_nodes);
+    }
+
+    #[test]
+    fn test_parse_func() {
+        let program = "
+            fn sum(n int) -> int {
+                if n <= 0 -> 0;
+                return fib(n - 1) + 1;
+            }
+        ";
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+
+        let actual_nodes = Parser::new(tokens).parse_program().unwrap();
+        let expect_nodes = vec![
+            DefFunc(DefFuncNode{
+                iden: "sum".to_string(),
+                type_params: vec![],
+                args: vec![("n".to_string(), TypeNode::Iden("int".to_string()))],
+                ret: Some(TypeNode::Iden("int".to_string())),
+                body: vec![
+                    Guard(GuardNode{
+                        cond: Box::new(Binop(BinopNode{
+                            op: Leq,
+                            lhs: Box::new(Variable("n".to_string())),
+                            rhs: Box::new(Constant(Int(0))),
+                            pos: any_pos(),
+                        })),
+                        this: Box::new(Constant(Int(0))),
+                    }),
+                    Return(Box::new(Binop(BinopNode{
+                        op: Plus,
+                        lhs: Box::new(CallFunc(FuncNode{
+                            iden: "fib".to_string(),
+                            args: vec![
+                                Binop(BinopNode{
+                                    op: Minus,
+                                    lhs: Box::new(Variable("n".to_string())),
+                                    rhs: Box::new(Constant(Int(1))),
+                                    pos: any_pos(),
+                                })
+                            ],
+                        })),
+                        rhs: Box::new(Constant(Int(1))),
+                        pos: any_pos(),
+                    })))
+                ],
+                pos: any_pos(),
+            })
+        ];
+        assert_structural_eq(&actual_nodes, &expect_nodes)
+    }
+
+    #[test]
+    fn test_parse_compound_guard() {
+        let program = "
+            fn f(a bool, b bool, c bool) -> int {
+                if a && b || !c -> 1;
+                return 0;
+            }
+        ";
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+
+        let actual_nodes = match Parser::new(tokens).parse_program().unwrap().remove(0) {
+            DefFunc(func) => func.body,
+            node => panic!("expected a function definition, got {:?}", node)
+        };
+        // && binds tighter than || and ! binds tighter than both, so the condition is '(a && b) || (!c)'
+        let expect_nodes = vec![
+            Guard(GuardNode{
+                cond: Box::new(Binop(BinopNode{
+                    op: Bop::Or,
+                    lhs: Box::new(Binop(BinopNode{
+                        op: Bop::And,
+                        lhs: Box::new(Variable("a".to_string())),
+                        rhs: Box::new(Variable("b".to_string())),
+                        pos: any_pos(),
+                    })),
+                    rhs: Box::new(Node::Unop(UnopNode{
+                        op: Uop::Not,
+                        expr: Box::new(Variable("c".to_string())),
+                        pos: any_pos(),
+                    })),
+                    pos: any_pos(),
+                })),
+                this: Box::new(Constant(Int(1))),
+            }),
+            Return(Box::new(Constant(Int(0)))),
+        ];
+        assert_structural_eq(&actual_nodes, &expect_nodes)
+    }
+
+    #[test]
+    fn test_parse_else_if() {
+        let program = "
+            fn chain(a bool, b bool) {
+                if a {
+                    return 1;
+                } else if b {
+                    return 2;
+                } else {
+                    return 3;
+                }
+            }
+        ";
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+
+        let actual_nodes = Parser::new(tokens).parse_program().unwrap();
+        let expect_nodes = vec![
+            DefFunc(DefFuncNode{
+                iden: "chain".to_string(),
+                type_params: vec![],
+                args: vec![
+                    ("a".to_string(), TypeNode::Iden("bool".to_string())),
+                    ("b".to_string(), TypeNode::Iden("bool".to_string()))
+                ],
+                ret: None,
+                body: vec![
+                    If(IfNode{
+                        cond: Box::new(Variable("a".to_string())),
+                        body: vec![Return(Box::new(Constant(Int(1))))],
+                    }),
+                    Else(vec![
+                        If(IfNode{
+                            cond: Box::new(Variable("b".to_string())),
+                            body: vec![Return(Box::new(Constant(Int(2))))],
+                        }),
+                        Else(vec![Return(Box::new(Constant(Int(3))))]),
+                    ]),
+                ],
+                pos: pos(2, 16),
+            })
+        ];
+        assert_eq!(actual_nodes, expect_nodes)
+    }
+
+    #[test]
+    fn test_parse_int_range() {
+        let program = "
+            fn max_int() -> int {
+                return 9223372036854775808;
+            }
+        ";
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+
+        let result = Parser::new(tokens).parse_program();
+        assert!(result.unwrap_err().starts_with("int literal 9223372036854775808 is out of range"))
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        let program = "
+            // a function with no body
+            fn noop() // returns nothing
+        ";
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).with_comments(true).read_tokens().unwrap();
+
+        let actual_nodes = Parser::new(tokens).parse_program().unwrap();
+        let expect_nodes = vec![
+            DefFunc(DefFuncNode {
+                iden: "noop".to_string(),
+                type_params: vec![],
+                args: vec![],
+                ret: None,
+                body: vec![],
+                pos: pos(3, 16),
+            })
+        ];
+        assert_eq!(actual_nodes, expect_nodes)
+    }
+
+    #[test]
+    fn test_parse_chained_comparison() {
+        let result = parse("fn f(a int, b int, c int) -> bool { return a < b < c; }");
+        assert!(result.unwrap_err().starts_with("comparisons cannot be chained, got <operator>"));
+
+        assert!(parse("fn f(a int, b int, c int) -> bool { return a < b && b < c; }").is_ok());
+        assert!(parse("fn f(a int, b int, c bool) -> bool { return a < b == c; }").is_ok());
+    }
+
+    #[test]
+    fn test_parse_block_value() {
+        let program = "fn f() -> int { g := fn() { x := 2; x * 3 }; g() }";
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+
+        let actual_nodes = Parser::new(tokens).parse_program().unwrap();
+        let expect_nodes = vec![
+            DefFunc(DefFuncNode {
+                iden: "f".to_string(),
+                type_params: vec![],
+                args: vec![],
+                ret: Some(TypeNode::Iden("int".to_string())),
+                body: vec![
+                    Declare("g".to_string(), Box::new(Lambda(LambdaNode {
+                        args: vec![],
+                        body: Box::new(Block(BlockNode {
+                            body: vec![Declare("x".to_string(), Box::new(Constant(Int(2))))],
+                            value: Some(Box::new(Binop(BinopNode {
+                                op: Multiply,
+                                lhs: Box::new(Variable("x".to_string())),
+                                rhs: Box::new(Constant(Int(3))),
+                                pos: any_pos(),
+                            }))),
+                        })),
+                    }))),
+                    Return(Box::new(CallFunc(FuncNode { iden: "g".to_string(), args: vec![] }))),
+                ],
+                pos: any_pos(),
+            })
+        ];
+        assert_structural_eq(&actual_nodes, &expect_nodes)
+    }
+
+    #[test]
+    fn test_parse_tuple() {
+        let program = "
+            fn swap(a int, b int) -> (int, int) {
+                return (b, a);
+            }
+        ";
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+
+        let actual_nodes = Parser::new(tokens).parse_program().unwrap();
+        let int = || TypeNode::Iden("int".to_string());
+        let expect_nodes = vec![
+            DefFunc(DefFuncNode{
+                iden: "swap".to_string(),
+                type_params: vec![],
+                args: vec![("a".to_string(), int()), ("b".to_string(), int())],
+                ret: Some(TypeNode::Tuple(vec![int(), int()])),
+                body: vec![Return(Box::new(Tuple(vec![Variable("b".to_string()), Variable("a".to_string())])))],
+                pos: pos(2, 16),
+            })
+        ];
+        assert_eq!(actual_nodes, expect_nodes);
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        for program in ["", "   \n  "] {
+            let reader = BufReader::new(Cursor::new(program));
+            let tokens = Lexer::new(reader).read_tokens().unwrap();
+            assert_eq!(Parser::new(tokens).parse_program(), Ok(vec![]));
+        }
+    }
+
+    #[test]
+    fn test_token_stream_lookahead() {
+        let reader = BufReader::new(Cursor::new("fn f"));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+
+        let mut stream = TokenStream::new(tokens);
+        assert_eq!(stream.peek_kind(0), Some(&Token::Fn));
+        assert_eq!(stream.peek_kind(1), Some(&Token::Iden("f".to_string())));
+        assert_eq!(stream.peek_kind(2), None);
+        assert_eq!(stream.pos(), Some(pos(1, 1)));
+
+        stream.next();
+        assert_eq!(stream.peek_kind(0), Some(&Token::Iden("f".to_string())));
+        assert_eq!(stream.peek_kind(1), None);
+        assert_eq!(stream.pos(), Some(pos(1, 4)));
+    }
+
+    #[test]
+    fn test_parse_block_expr_value() {
+        let program = "
+            fn f() -> int {
+                return { x := 1; };
+            }
+        ";
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+        assert_eq!(Parser::new(tokens).parse_program(), Err("expected a value at the end of a block expression".to_string()));
+    }
+
+    #[test]
+    fn test_parse_duplicate_names() {
+        assert_eq!(parse("fn f(x int, x int) {}"), Err("duplicate parameter x between line: 1, col: 13 and line: 1, col: 13".to_string()));
+        assert_eq!(parse("struct S { a int, a int }"), Err("duplicate field a between line: 1, col: 19 and line: 1, col: 19".to_string()));
+    }
+
+    #[test]
+    fn test_parse_compound_assign() {
+        let program = "
+            fn f(x int) {
+                x -= 1;
+            }
+        ";
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+
+        let actual_nodes = Parser::new(tokens).parse_program().unwrap();
+        let expect_nodes = vec![
+            DefFunc(DefFuncNode{
+                iden: "f".to_string(),
+                type_params: vec![],
+                args: vec![("x".to_string(), TypeNode::Iden("int".to_string()))],
+                ret: None,
+                body: vec![
+                    Assign("x".to_string(), Box::new(Binop(BinopNode {
+                        op: Minus,
+                        lhs: Box::new(Variable("x".to_string())),
+                        rhs: Box::new(Constant(Int(1))),
+                        pos: pos(3, 19),
+                    })))
+                ],
+                pos: pos(2, 16),
+            })
+        ];
+        assert_eq!(actual_nodes, expect_nodes);
+    }
+
+    #[test]
+    fn test_parse_dangling_else() {
+        let program = "
+            fn main() {
+                x := 1;
+                else { }
+            }
+        ";
+        assert_eq!(parse(program).unwrap_err(), "dangling else at line: 4, col: 17, an else must follow the block of an if");
+
+        // a guard has no block for an else to follow
+        let program = "
+            fn main() -> int {
+                if true -> 1;
+                else { }
+            }
+        ";
+        assert!(parse(program).unwrap_err().starts_with("dangling else"));
+    }
+
+    #[test]
+    fn test_parse_newlines() {
+        let with_semicolons = "
+            struct Point { x int, y int }
+
+            fn main() -> int {
+                p := Point { x = 1, y = 2 };
+                if p.x < p.y {
+                    p.x = 3;
+                } else {
+                    p.y = 4;
+                }
+                return {
+                    z := p.x;
+                    z + p.y
+                };
+            }
+        ";
+        let with_newlines = "
+            struct Point { x int, y int }
+
+            fn main() -> int {
+                p := Point { x = 1, y = 2 }
+                if p.x < p.y {
+                    p.x = 3
+                } else {
+                    p.y = 4
+                }
+                return {
+                    z := p.x
+                    z + p.y
+                }
+            }
+        ";
+        let reader = BufReader::new(Cursor::new(with_newlines));
+        let tokens = Lexer::new(reader).with_newlines(true).read_tokens().unwrap();
+        assert_eq!(Parser::new(tokens).parse_program(), parse(with_semicolons));
+    }
+
+    #[test]
+    fn test_parse_import_alias() {
+        let program = "
+            import math as m
+            import io
+        ";
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+
+        let actual_nodes = Parser::new(tokens).parse_program().unwrap();
+        let expect_nodes = vec![
+            Import(ImportNode { iden: "math".to_string(), alias: Some("m".to_string()) }),
+            Import(ImportNode { iden: "io".to_string(), alias: None }),
+        ];
+        assert_eq!(actual_nodes, expect_nodes)
+    }
+
+    #[test]
+    fn test_parse_type_params() {
+        let expect_nodes = vec![
+            DefFunc(DefFuncNode {
+                iden: "id".to_string(),
+                type_params: vec!["T".to_string()],
+                args: vec![("x".to_string(), TypeNode::Iden("T".to_string()))],
+                ret: Some(TypeNode::Iden("T".to_string())),
+                body: vec![Return(Box::new(Variable("x".to_string())))],
+                pos: pos(1, 4),
+            })
+        ];
+        assert_eq!(parse("fn id[T](x T) -> T { return x; }"), Ok(expect_nodes));
+
+        let Ok(nodes) = parse("fn pair[A, B](a A, b B) -> (A, B) { return (a, b); }") else { panic!("expected a program") };
+        assert!(matches!(&nodes[0], DefFunc(func) if func.type_params == vec!["A".to_string(), "B".to_string()]));
+
+        assert!(parse("fn id[T, T](x T) -> T { return x; }").unwrap_err().starts_with("duplicate type parameter T"));
+    }
+
+    #[test]
+    fn test_parse_optional() {
+        let program = "fn f(x ?int) -> ?[]int { return nil; }";
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+
+        let actual_nodes = Parser::new(tokens).parse_program().unwrap();
+        let expect_nodes = vec![
+            DefFunc(DefFuncNode {
+                iden: "f".to_string(),
+                type_params: vec![],
+                args: vec![("x".to_string(), TypeNode::Optional(Box::new(TypeNode::Iden("int".to_string()))))],
+                ret: Some(TypeNode::Optional(Box::new(TypeNode::Array(Box::new(TypeNode::Iden("int".to_string())))))),
+                body: vec![Return(Box::new(Constant(Nil)))],
+                pos: pos(1, 4),
+            })
+        ];
+        assert_eq!(actual_nodes, expect_nodes)
+    }
+
+    #[test]
+    fn test_parse_module() {
+        let program = "
+            mod math {
+                fn add(a int, b int) -> int { return a + b; }
+                mod consts {}
+            }
+        ";
+        let reader = BufReader::new(Cursor::new(program));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+
+        let actual_nodes = Parser::new(tokens).parse_program().unwrap();
+        let int = || TypeNode::Iden("int".to_string());
+        let expect_nodes = vec![
+            Module("math".to_string(), vec![
+                DefFunc(DefFuncNode {
+                    iden: "add".to_string(),
+                    type_params: vec![],
+                    args: vec![("a".to_string(), int()), ("b".to_string(), int())],
+                    ret: Some(int()),
+                    body: vec![Return(Box::new(Binop(BinopNode {
+                        op: Plus,
+                        lhs: Box::new(Variable("a".to_string())),
+                        rhs: Box::new(Variable("b".to_string())),
+                        pos: any_pos(),
+                    })))],
+                    pos: any_pos(),
+                }),
+                Module("consts".to_string(), vec![]),
+            ])
+        ];
+        assert_structural_eq(&actual_nodes, &expect_nodes);
+
+        // a statement where a definition belongs lists each definition that could start there
+        let reader = BufReader::new(Cursor::new("mod math { x := 1; }"));
+        let tokens = Lexer::new(reader).read_tokens().unwrap();
+        let err = Parser::new(tokens).parse_program().unwrap_err();
+        assert!(err.starts_with("import, mod, fn, type, or struct expected, got <iden>"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_ternary() {
+        let var = |iden: &str| Box::new(Variable(iden.to_string()));
+
+        // the ternary binds looser than the operators and groups to the right
+        let expect_node = Ternary(TernaryNode {
+            cond: Box::new(Binop(BinopNode { op: Bop::Or, lhs: var("a"), rhs: var("b"), pos: pos(1, 3) })),
+            then: var("x"),
+            otherwise: Box::new(Ternary(TernaryNode { cond: var("c"), then: var("y"), otherwise: var("z") })),
+        });
+        assert_eq!(parse_expr("a || b ? x : c ? y : z"), Ok(expect_node));
+        assert!(parse_expr("a ? x").is_err());
+    }
+
+    #[test]
+    fn test_parse_adjacent_strings() {
+        assert_eq!(parse_expr("\"foo\" \"bar\""), Ok(Constant(Const::String("foobar".to_string()))));
+        assert_eq!(parse_expr("\"a\"\n\"b\" \"c\""), Ok(Constant(Const::String("abc".to_string()))));
+
+        // only literals right next to each other are joined
+        let mut parser = Parser::new(lex("\"foo\" 'c'"));
+        assert_eq!(parser.parse_expr(), Ok(Constant(Const::String("foo".to_string()))));
+        assert_eq!(parser.parse_expr(), Ok(Constant(Const::Char('c'))));
+    }
+
+    #[test]
+    fn test_parse_invalid_lvalue() {
+        let assign_err = |span: &str| {
+            Err(format!("cannot assign to the expression between {}, only a variable, an index or a member can be assigned", span))
+        };
+
+        assert_eq!(parse("fn f() { 1 = 2; }"), assign_err("line: 1, col: 10 and line: 1, col: 10"));
+        assert_eq!(parse("fn f() { f() = 3; }"), assign_err("line: 1, col: 10 and line: 1, col: 12"));
+        assert_eq!(parse("fn f() { 1 + 2 += 3; }"), assign_err("line: 1, col: 10 and line: 1, col: 14"));
+
+        let declare_err = "cannot declare the expression between line: 1, col: 17 and line: 1, col: 20, only a variable can be declared";
+        assert_eq!(parse("fn f(a []int) { a[0] := 1; }"), Err(declare_err.to_string()));
+    }
+
+    #[test]
+    fn test_structural_eq() {
+        let program = "fn f(a []int) -> int { return a[0] + -a[1] * 2; }";
+        let nodes = parse(program).unwrap();
+        let shifted = parse(&format!("\n\n    {}", program)).unwrap();
+        assert_structural_eq(&nodes, &shifted);
+        assert_ne!(nodes, shifted);
+
+        let other = parse("fn f(a []int) -> int { return a[0] - -a[1] * 2; }").unwrap();
+        assert!(!structural_eq(&nodes[0], &other[0]));
+    }
+
+    #[test]
+    fn test_parse_declare_many() {
+        let body = |program: &str| match parse(program).unwrap().remove(0) {
+            DefFunc(func) => func.body,
+            node => panic!("expected a function definition, got {:?}", node)
+        };
+        let names = || vec!["x".to_string(), "y".to_string()];
+
+        let expect_node = DeclareMany(names(), Box::new(Tuple(vec![Constant(Int(1)), Constant(Int(2))])));
+        assert_eq!(body("fn f() { x, y := 1, 2; }"), vec![expect_node]);
+        assert_eq!(body("fn f(p (int, int)) { x, y := p; }"), vec![DeclareMany(names(), Box::new(Variable("p".to_string())))]);
+
+        assert_eq!(parse("fn f() { x, y := 1, 2, 3; }"), Err("cannot declare 2 names from 3 values".to_string()));
+        assert!(parse("fn f() { x, 1 := 1, 2; }").unwrap_err().starts_with("expected <iden> after ',' in declaration"));
+    }
+
+    #[test]
+    fn test_parse_elif() {
+        let elif = parse("
+            fn sign(x int) -> int {
+                if x > 0 {
+                    return 1;
+                } elif x < 0 {
+                    return -1;
+                } elif x == 0 {
+                    return 0;
+                } else {
+                    return 2;
+                }
+            }
+        ").unwrap();
+        let else_if = parse("
+            fn sign(x int) -> int {
+                if x > 0 {
+                    return 1;
+                } else if x < 0 {
+                    return -1;
+                } else if x == 0 {
+                    return 0;
+                } else {
+                    return 2;
+                }
+            }
+        ").unwrap();
+        assert_structural_eq(&elif, &else_if);
+
+        let err = parse("fn f() { x := 1; elif x > 0 { } }").unwrap_err();
+        assert_eq!(err, "dangling elif at line: 1, col: 18, an elif must follow the block of an if");
+        assert!(parse("fn f() { if true { } else { } elif false { } }").unwrap_err().starts_with("dangling elif"));
+    }
+
+    #[test]
+    fn test_parse_streaming() {
+        let program = "
+            // a comment between definitions
+            struct Point { x int, y int }
+
+            fn main() -> int {
+                p := Point { x = 1, y = 2 };
+                if p.x > 0 {
+                    return p.x + p.y;
+                } elif p.y > 0 {
+                    return p.y;
+                }
+                return 0;
+            }
+        ";
+        let eager = |program: &str, newlines: bool| {
+            let reader = BufReader::new(Cursor::new(program.to_string()));
+            let tokens = Lexer::new(reader).with_comments(true).with_newlines(newlines).read_tokens()?;
+            Parser::new(tokens).parse_program()
+        };
+        let streaming = |program: &str, newlines: bool| {
+            let reader = BufReader::new(Cursor::new(program.to_string()));
+            Parser::streaming(Lexer::new(reader).with_comments(true).with_newlines(newlines)).parse_program()
+        };
+
+        assert!(streaming(program, false).is_ok());
+        assert_eq!(streaming(program, false), eager(program, false));
+        let newlines = program.replace(';', "");
+        assert_eq!(streaming(&newlines, true), eager(&newlines, true));
+
+        // an error lexing comes first even when the tokens before it don't parse
+        let err = Err("Invalid int: cannot lex 1a between line: 1, col: 18 and line: 1, col: 19".to_string());
+        assert_eq!(streaming("fn f() { return; 1a }", false), err);
+        assert_eq!(streaming("fn f() { ) } x := 1a;", false), eager("fn f() { ) } x := 1a;", false));
+        assert_eq!(streaming("fn f() { ) }", false), eager("fn f() { ) }", false));
+    }
+
+    #[test]
+    fn test_parse_slice() {
+        let returned = |expr: &str| match parse(&format!("fn f() {{ return {}; }}", expr)).unwrap().remove(0) {
+            DefFunc(mut func) => match func.body.remove(0) {
+                Return(expr) => *expr,
+                node => panic!("expected a return, got {:?}", node)
+            },
+            node => panic!("expected a function definition, got {:?}", node)
+        };
+        let var = |iden: &str| Box::new(Variable(iden.to_string()));
+        let slice = |start: Option<Box<Node>>, end: Option<Box<Node>>, inclusive: bool| {
+            Node::Slice(SliceNode { base: var("a"), start, end, inclusive, pos: pos(1, 18) })
+        };
+
+        assert_eq!(returned("a[i..j]"), slice(Some(var("i")), Some(var("j")), false));
+        assert_eq!(returned("a[..=j]"), slice(None, Some(var("j")), true));
+        assert_eq!(returned("a[i..]"), slice(Some(var("i")), None, false));
+        assert_eq!(returned("a[..]"), slice(None, None, false));
+        // a subscript without a range operator is still an index, even a ternary one
+        assert!(matches!(returned("a[b ? i : j]"), Node::Index(IndexNode { index, .. }) if matches!(*index, Ternary(_))));
+
+        assert!(parse("fn f() { return a[i..=]; }").unwrap_err().starts_with("expected the end of the inclusive slice"));
+    }
+
+    #[test]
+    fn test_parse_const() {
+        let DefFunc(func) = parse("fn f() { const x := 1; }").unwrap().remove(0) else { panic!("expected a function definition") };
+        assert_eq!(func.body, vec![Node::DeclareConst("x".to_string(), Box::new(Constant(Int(1))))]);
+
+        assert!(parse("fn f() { const 1 := 1; }").unwrap_err().starts_with("expected <iden> after const"));
+        assert!(parse("fn f() { const x = 1; }").is_err());
+    }
+
+    #[test]
+    fn test_parse_recovering() {
+        let program = "
+            fn broken() {
+                x := (1 + ;
+                y := ) 2;
+                if { z := 3; }
+            }
+            fn sum(a int, b int) -> int {
+                return a + b;
+            }
+        ";
+        let recover = |sync: Option<Vec<Token>>| match sync {
+            Some(sync) => Parser::new(lex(program)).with_sync(sync).parse_recovering(),
+            None => Parser::new(lex(program)).parse_recovering()
+        };
+
+        // syncing on fn skips the rest of the broken body, so only its first error is reported
+        let (nodes, errs) = recover(Some(vec![Token::Fn, Token::Struct, Token::Type]));
+        assert_eq!(errs.len(), 1, "{:?}", errs);
+        let expect_nodes = vec![
+            DefFunc(DefFuncNode{
+                iden: "sum".to_string(),
+                type_params: vec![],
+                args: vec![("a".to_string(), TypeNode::Iden("int".to_string())), ("b".to_string(), TypeNode::Iden("int".to_string()))],
+                ret: Some(TypeNode::Iden("int".to_string())),
+                body: vec![Return(Box::new(Binop(BinopNode{
+                    op: Plus,
+                    lhs: Box::new(Variable("a".to_string())),
+                    rhs: Box::new(Variable("b".to_string())),
+                    pos: any_pos(),
+                })))],
+                pos: any_pos(),
+            })
+        ];
+        assert_structural_eq(&nodes, &expect_nodes);
+
+        // by default each statement left in the broken body resumes parsing and fails again
+        let (nodes, errs) = recover(None);
+        assert!(errs.len() > 1, "{:?}", errs);
+        assert_structural_eq(&nodes, &expect_nodes);
+    }
+
+    #[test]
+    fn test_parse_exponent() {
+        let body = |program: &str| match parse(program).unwrap().remove(0) {
+            DefFunc(func) => func.body,
+            node => panic!("expected a function definition, got {:?}", node)
+        };
+        let exp = |lhs: Node, rhs: Node| Binop(BinopNode { op: Bop::Exp, lhs: Box::new(lhs), rhs: Box::new(rhs), pos: any_pos() });
+
+        // the exponent binds to the right, so '2 ** 2 ** 3' is '2 ** (2 ** 3)'
+        let actual_nodes = body("fn f() -> int { return 2 ** 2 ** 3; }");
+        let expect_nodes = vec![Return(Box::new(exp(Constant(Int(2)), exp(Constant(Int(2)), Constant(Int(3))))))];
+        assert_structural_eq(&actual_nodes, &expect_nodes);
+
+        // and it binds tighter than a multiply on either side of it
+        let actual_nodes = body("fn f() -> int { return 3 * 2 ** 2; }");
+        let expect_nodes = vec![Return(Box::new(Binop(BinopNode {
+            op: Multiply,
+            lhs: Box::new(Constant(Int(3))),
+            rhs: Box::new(exp(Constant(Int(2)), Constant(Int(2)))),
+            pos: any_pos(),
+        })))];
+        assert_structural_eq(&actual_nodes, &expect_nodes);
+
+        let actual_nodes = body("fn f(x int) { x **= 2; }");
+        let expect_nodes = vec![Assign("x".to_string(), Box::new(exp(Variable("x".to_string()), Constant(Int(2)))))];
+        assert_structural_eq(&actual_nodes, &expect_nodes);
+    }
+
+    #[test]
+    fn test_parse_struct_in_cond() {
+        let program = "
+            fn f() {
+                for p in [P{ x = 1 }] {}
+                while g(P{ x = 1 }) {}
+                if (P{ x = 1 }).x > 0 {}
+                while p { x = 1; }
+            }
+        ";
+        let program = parse(program).unwrap();
+        let DefFunc(func) = &program[0] else { panic!("expected a function, got {:?}", program) };
+        let is_struct = |node: &Node| matches!(node, Node::Struct(_));
+        match &func.body[0] {
+            Node::For(node) => assert!(matches!(node.collection.as_ref(), Node::Array(elems) if is_struct(&elems[0])), "{:?}", node),
+            node => panic!("expected a for loop, got {:?}", node)
+        }
+        match &func.body[1] {
+            While(node) => assert!(matches!(node.cond.as_ref(), CallFunc(call) if is_struct(&call.args[0])), "{:?}", node),
+            node => panic!("expected a while loop, got {:?}", node)
+        }
+        assert!(matches!(&func.body[2], If(_)), "{:?}", func.body[2]);
+        // outside of brackets the brace still starts the body
+        match &func.body[3] {
+            While(node) => assert!(matches!(node.cond.as_ref(), Variable(_)) && node.body.len() == 1, "{:?}", node),
+            node => panic!("expected a while loop, got {:?}", node)
+        }
+    }
+}